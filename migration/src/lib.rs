@@ -5,6 +5,23 @@ mod m20250529_055640_create_creator_and_game_tables;
 mod m20250529_061644_rename_creator_to_creators;
 mod m20250529_070451_rename_game_to_games;
 mod m20250529_080000_create_users_table;
+mod m20250601_000001_add_user_id_to_creators;
+mod m20250601_000002_create_invitations_table;
+mod m20250602_000001_create_password_reset_tokens_table;
+mod m20250603_000001_add_email_verification;
+mod m20250604_000001_add_two_factor_auth;
+mod m20250605_000001_add_login_lockout;
+mod m20250606_000001_create_api_keys_table;
+mod m20250607_000001_create_user_identities_table;
+mod m20250608_000001_create_sessions_table;
+mod m20250609_000001_create_email_change_tokens_table;
+mod m20250610_000001_create_idempotency_keys_table;
+mod m20250611_000001_add_impersonator_to_sessions;
+mod m20250612_000001_add_totp_last_step;
+mod m20250613_000001_create_sso_login_codes_table;
+mod m20250614_000001_add_response_headers_to_idempotency_keys;
+mod m20250615_000001_add_unique_email_to_creators;
+mod m20250616_000001_add_started_at_to_idempotency_keys;
 
 pub struct Migrator;
 
@@ -17,6 +34,23 @@ impl MigratorTrait for Migrator {
             Box::new(m20250529_061644_rename_creator_to_creators::Migration),
             Box::new(m20250529_070451_rename_game_to_games::Migration),
             Box::new(m20250529_080000_create_users_table::Migration),
+            Box::new(m20250601_000001_add_user_id_to_creators::Migration),
+            Box::new(m20250601_000002_create_invitations_table::Migration),
+            Box::new(m20250602_000001_create_password_reset_tokens_table::Migration),
+            Box::new(m20250603_000001_add_email_verification::Migration),
+            Box::new(m20250604_000001_add_two_factor_auth::Migration),
+            Box::new(m20250605_000001_add_login_lockout::Migration),
+            Box::new(m20250606_000001_create_api_keys_table::Migration),
+            Box::new(m20250607_000001_create_user_identities_table::Migration),
+            Box::new(m20250608_000001_create_sessions_table::Migration),
+            Box::new(m20250609_000001_create_email_change_tokens_table::Migration),
+            Box::new(m20250610_000001_create_idempotency_keys_table::Migration),
+            Box::new(m20250611_000001_add_impersonator_to_sessions::Migration),
+            Box::new(m20250612_000001_add_totp_last_step::Migration),
+            Box::new(m20250613_000001_create_sso_login_codes_table::Migration),
+            Box::new(m20250614_000001_add_response_headers_to_idempotency_keys::Migration),
+            Box::new(m20250615_000001_add_unique_email_to_creators::Migration),
+            Box::new(m20250616_000001_add_started_at_to_idempotency_keys::Migration),
         ]
     }
 }
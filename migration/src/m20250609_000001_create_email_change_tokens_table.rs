@@ -0,0 +1,61 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(EmailChangeTokens::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(EmailChangeTokens::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(EmailChangeTokens::UserId).uuid().not_null())
+                    .col(ColumnDef::new(EmailChangeTokens::NewEmail).string().not_null())
+                    .col(ColumnDef::new(EmailChangeTokens::TokenHash).string().not_null().unique_key())
+                    .col(ColumnDef::new(EmailChangeTokens::ExpiresAt).timestamp_with_time_zone().not_null())
+                    .col(ColumnDef::new(EmailChangeTokens::UsedAt).timestamp_with_time_zone().null())
+                    .col(ColumnDef::new(EmailChangeTokens::CreatedAt).timestamp_with_time_zone().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-email_change_tokens-user_id")
+                            .from(EmailChangeTokens::Table, EmailChangeTokens::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(EmailChangeTokens::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum EmailChangeTokens {
+    Table,
+    Id,
+    UserId,
+    NewEmail,
+    TokenHash,
+    ExpiresAt,
+    UsedAt,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    Table,
+    Id,
+}
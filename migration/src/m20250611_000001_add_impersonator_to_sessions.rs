@@ -0,0 +1,51 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Sessions::Table)
+                    .add_column(ColumnDef::new(Sessions::ImpersonatorId).uuid().null())
+                    .add_foreign_key(
+                        TableForeignKey::new()
+                            .name("fk-sessions-impersonator_id")
+                            .from_tbl(Sessions::Table)
+                            .from_col(Sessions::ImpersonatorId)
+                            .to_tbl(Users::Table)
+                            .to_col(Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Sessions::Table)
+                    .drop_foreign_key(Alias::new("fk-sessions-impersonator_id"))
+                    .drop_column(Sessions::ImpersonatorId)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Sessions {
+    Table,
+    ImpersonatorId,
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    Table,
+    Id,
+}
@@ -0,0 +1,59 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(SsoLoginCodes::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(SsoLoginCodes::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(SsoLoginCodes::UserId).uuid().not_null())
+                    .col(ColumnDef::new(SsoLoginCodes::CodeHash).string().not_null().unique_key())
+                    .col(ColumnDef::new(SsoLoginCodes::ExpiresAt).timestamp_with_time_zone().not_null())
+                    .col(ColumnDef::new(SsoLoginCodes::UsedAt).timestamp_with_time_zone().null())
+                    .col(ColumnDef::new(SsoLoginCodes::CreatedAt).timestamp_with_time_zone().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-sso_login_codes-user_id")
+                            .from(SsoLoginCodes::Table, SsoLoginCodes::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(SsoLoginCodes::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum SsoLoginCodes {
+    Table,
+    Id,
+    UserId,
+    CodeHash,
+    ExpiresAt,
+    UsedAt,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    Table,
+    Id,
+}
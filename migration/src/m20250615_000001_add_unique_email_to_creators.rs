@@ -0,0 +1,65 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::{ConnectionTrait, Statement};
+
+// How many duplicated emails the error lists before truncating
+const LISTED_DUPLICATES: usize = 10;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Existing duplicates would make CREATE UNIQUE INDEX fail with a bare constraint error;
+        // name them instead so the operator knows which rows to merge or rename
+        let duplicates = manager
+            .get_connection()
+            .query_all(Statement::from_string(
+                manager.get_database_backend(),
+                "SELECT email, COUNT(*) AS count FROM creators GROUP BY email HAVING COUNT(*) > 1 ORDER BY email",
+            ))
+            .await?;
+        if !duplicates.is_empty() {
+            let listed = duplicates
+                .iter()
+                .take(LISTED_DUPLICATES)
+                .map(|row| {
+                    let email: String = row.try_get("", "email")?;
+                    let count: i64 = row.try_get("", "count")?;
+                    Ok(format!("{email} ({count} rows)"))
+                })
+                .collect::<Result<Vec<_>, DbErr>>()?;
+            let more = duplicates.len().saturating_sub(LISTED_DUPLICATES);
+            return Err(DbErr::Migration(format!(
+                "Cannot add a unique index on creators.email: {} email(s) are used by more than one creator: {}{}. \
+                 Merge or rename those rows (see \"Upgrading\" in README.md), then run the migration again",
+                duplicates.len(),
+                listed.join(", "),
+                if more > 0 { format!(" and {more} more") } else { String::new() },
+            )));
+        }
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-creators-email")
+                    .table(Creators::Table)
+                    .col(Creators::Email)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(Index::drop().name("idx-creators-email").table(Creators::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Creators {
+    Table,
+    Email,
+}
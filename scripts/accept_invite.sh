@@ -0,0 +1,78 @@
+#!/usr/bin/env bash
+# Checks that accepting an invite takes over a creator profile an admin created earlier for the
+# same email, and that a profile already owned by another account is a 409 (not a 500).
+#
+# Requires a running server with a migrated database.
+#
+# Usage: ADMIN_TOKEN=<admin jwt> scripts/accept_invite.sh [base-url]
+set -uo pipefail
+
+BASE_URL="${1:-http://localhost:8080}"
+: "${ADMIN_TOKEN:?ADMIN_TOKEN must be an admin bearer token}"
+failures=0
+run="$(date +%s)-$$"
+
+expect() {
+  local description="$1" actual="$2" expected="$3"
+  if [ "$actual" = "$expected" ]; then
+    echo "ok    $description"
+  else
+    echo "FAIL  $description (expected '$expected', got '$actual')"
+    failures=$((failures + 1))
+  fi
+}
+
+# Admin request; prints the response body
+admin() {
+  local method="$1" path="$2" content_type="$3" body="${4:-}"
+  curl -s -X "$method" "$BASE_URL$path" \
+    -H "Authorization: Bearer $ADMIN_TOKEN" \
+    -H "Content-Type: $content_type" \
+    ${body:+-d "$body"}
+}
+
+# Prints a top-level string (or null) field of a flat JSON object
+field() {
+  sed -n "s/.*\"$1\":\"\{0,1\}\([^\",}]*\).*/\1/p"
+}
+
+invite() {
+  admin POST /api/admin/invitations application/json "{\"email\":\"$1\",\"role\":\"creator\"}" | field token
+}
+
+# Prints the status code of accepting invite token $1
+accept() {
+  curl -s -o /dev/null -w '%{http_code}' -X POST "$BASE_URL/api/auth/accept-invite" \
+    -H "Content-Type: application/json" \
+    -d "{\"token\":\"$1\",\"password\":\"invite-password-1\",\"first_name\":\"Hideo\",\"last_name\":\"Kojima\"}"
+}
+
+# 1. A profile the admin created before the invite is linked to the new account, not duplicated
+email="invite-existing-$run@example.com"
+creator_id=$(admin POST /api/creators application/json \
+  "{\"first_name\":\"Admin\",\"last_name\":\"Made\",\"email\":\"$email\"}" | field id)
+expect "accepting with an unlinked creator profile succeeds" "$(accept "$(invite "$email")")" "201"
+
+creator=$(admin GET "/api/creators/$creator_id" application/json)
+user_id=$(field user_id <<<"$creator")
+expect "existing profile is linked to the new user" "$([ -n "$user_id" ] && [ "$user_id" != null ] && echo linked)" "linked"
+expect "profile takes the names from the invite" "$(field first_name <<<"$creator")" "Hideo"
+expect "no second profile is created" \
+  "$(admin GET /api/creators application/json | grep -o "\"email\":\"$email\"" | wc -l | tr -d ' ')" "1"
+
+# 2. A profile that already belongs to an account is not taken over
+owned="invite-owned-$run@example.com"
+accept "$(invite "$owned")" >/dev/null
+owned_id=$(admin GET /api/creators application/json | sed 's/},{/}\n{/g' | grep "\"email\":\"$owned\"" | field id)
+moved="invite-moved-$run@example.com"
+admin PATCH "/api/creators/$owned_id" application/merge-patch+json "{\"email\":\"$moved\"}" >/dev/null
+
+token=$(invite "$moved")
+expect "accepting with a profile owned by another account is a conflict" "$(accept "$token")" "409"
+expect "the failed accept does not use up the invite" "$(accept "$token")" "409"
+
+if [ "$failures" -gt 0 ]; then
+  echo "$failures check(s) failed" >&2
+  exit 1
+fi
+echo "all accept-invite checks passed"
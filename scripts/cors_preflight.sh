@@ -0,0 +1,73 @@
+#!/usr/bin/env bash
+# Checks CORS preflight behavior against a running server.
+#
+# Start the server with:
+#   CORS_PRESET=production \
+#   CORS_ALLOWED_ORIGINS="https://app.example.com,https://*.example.org" \
+#   CORS_ALLOW_CREDENTIALS=true CORS_MAX_AGE_SECS=600 cargo run
+#
+# Usage: scripts/cors_preflight.sh [base-url]
+set -uo pipefail
+
+BASE_URL="${1:-http://localhost:8080}"
+failures=0
+
+# Sends a preflight for POST /api/games and prints the response headers (lower-cased)
+preflight() {
+  local origin="$1" method="${2:-POST}" headers="${3:-authorization,content-type}"
+  curl -s -o /dev/null -D - -X OPTIONS "$BASE_URL/api/games" \
+    -H "Origin: $origin" \
+    -H "Access-Control-Request-Method: $method" \
+    -H "Access-Control-Request-Headers: $headers" | tr -d '\r' | tr '[:upper:]' '[:lower:]'
+}
+
+expect() {
+  local description="$1" response="$2" pattern="$3"
+  if grep -q "$pattern" <<<"$response"; then
+    echo "ok    $description"
+  else
+    echo "FAIL  $description (expected '$pattern')"
+    failures=$((failures + 1))
+  fi
+}
+
+expect_not() {
+  local description="$1" response="$2" pattern="$3"
+  if grep -q "$pattern" <<<"$response"; then
+    echo "FAIL  $description (did not expect '$pattern')"
+    failures=$((failures + 1))
+  else
+    echo "ok    $description"
+  fi
+}
+
+response=$(preflight "https://app.example.com")
+expect "exact origin is echoed" "$response" "^access-control-allow-origin: https://app.example.com$"
+expect "allowed methods are listed" "$response" "^access-control-allow-methods:.*post"
+expect "requested headers are allowed" "$response" "^access-control-allow-headers:.*authorization"
+expect "credentials are allowed" "$response" "^access-control-allow-credentials: true$"
+expect "preflight max-age is sent" "$response" "^access-control-max-age: 600$"
+
+response=$(preflight "https://studio.example.org")
+expect "wildcard subdomain matches" "$response" "^access-control-allow-origin: https://studio.example.org$"
+
+response=$(preflight "https://example.org")
+expect_not "wildcard does not match the bare domain" "$response" "^access-control-allow-origin:"
+
+response=$(preflight "https://evil-example.org")
+expect_not "lookalike domain is rejected" "$response" "^access-control-allow-origin:"
+
+response=$(preflight "http://app.example.com")
+expect_not "scheme must match" "$response" "^access-control-allow-origin:"
+
+response=$(preflight "https://app.example.com" "TRACE")
+expect_not "disallowed method is rejected" "$response" "^access-control-allow-origin:"
+
+response=$(preflight "https://app.example.com" "POST" "x-unknown-header")
+expect_not "disallowed header is rejected" "$response" "^access-control-allow-origin:"
+
+if [ "$failures" -gt 0 ]; then
+  echo "$failures check(s) failed" >&2
+  exit 1
+fi
+echo "all CORS preflight checks passed"
@@ -0,0 +1,105 @@
+#!/usr/bin/env bash
+# Load test: game listing latency must stay flat while the server is hammered with logins.
+#
+# Usage: scripts/login_storm.sh [base-url]
+#   STORM_CONCURRENCY  parallel login loops              (default 32)
+#   STORM_SECONDS      how long the storm lasts          (default 20)
+#   SAMPLES            game-list requests per phase      (default 100)
+#   MAX_EXTRA_MS       allowed p95 increase during storm (default 25)
+#
+# Requires curl, awk and a running server with a migrated database. A single Argon2id/bcrypt
+# hash takes tens of milliseconds, so hashing on the actix workers shows up as p95 in the
+# hundreds of milliseconds; on the blocking pool only CPU contention remains. On small
+# machines run the load generator elsewhere, as its curl processes compete for the same CPUs.
+#
+# Start the server with RATE_LIMIT_ENABLED=false (or raised RATE_LIMITS=auth=...,api_ip=...): rejected
+# logins skip password hashing, so any 429 would make the storm cheaper than it looks. The
+# script fails if one is received, and if any GET /api/games sample is not a 200 (a fast
+# error would otherwise pass as low latency).
+set -euo pipefail
+
+BASE_URL="${1:-http://localhost:8080}"
+STORM_CONCURRENCY="${STORM_CONCURRENCY:-32}"
+STORM_SECONDS="${STORM_SECONDS:-20}"
+SAMPLES="${SAMPLES:-100}"
+MAX_EXTRA_MS="${MAX_EXTRA_MS:-25}"
+
+EMAIL="loadtest-$(date +%s)-$$@example.com"
+PASSWORD="load-test-password"
+STATUSES="$(mktemp)"
+GAME_STATUSES="$(mktemp)"
+pids=()
+trap 'kill "${pids[@]}" 2>/dev/null || true; rm -f "$STATUSES" "$GAME_STATUSES"' EXIT
+
+token=$(curl -sf -X POST "$BASE_URL/api/auth/register" \
+  -H "Content-Type: application/json" \
+  -d "{\"email\":\"$EMAIL\",\"password\":\"$PASSWORD\",\"role\":\"creator\"}" |
+  sed -n 's/.*"token":"\([^"]*\)".*/\1/p')
+
+if [ -z "$token" ]; then
+  echo "could not register load test user" >&2
+  exit 1
+fi
+
+# Prints the p50 and p95 (in ms) of $SAMPLES sequential GET /api/games requests; their status
+# codes go to $GAME_STATUSES
+measure() {
+  for _ in $(seq "$SAMPLES"); do
+    curl -s -o /dev/null -w '%{http_code} %{time_total}\n' "$BASE_URL/api/games" \
+      -H "Authorization: Bearer $token"
+  done | sort -k2 -n | awk -v statuses="$GAME_STATUSES" '{ print $1 > statuses; t[NR] = $2 * 1000 } END {
+    close(statuses)
+    printf "%.1f %.1f\n", t[int(NR * 0.50) + 1], t[int(NR * 0.95)]
+  }'
+}
+
+# Fails unless every sample of the last measurement was a 200
+check_samples() {
+  local failed
+  failed=$(grep -vc '^200$' "$GAME_STATUSES" || true)
+  if [ "$failed" -gt 0 ]; then
+    echo "FAIL: ${failed} of ${SAMPLES} game listing samples ($1) were not 200:" \
+      "$(sort "$GAME_STATUSES" | uniq -c | awk '{ printf "%s%sx%s", sep, $1, $2; sep = ", " }')" >&2
+    exit 1
+  fi
+}
+
+login_loop() {
+  local deadline=$((SECONDS + STORM_SECONDS))
+  while [ "$SECONDS" -lt "$deadline" ]; do
+    curl -s -o /dev/null -w '%{http_code}\n' -X POST "$BASE_URL/api/auth/login" \
+      -H "Content-Type: application/json" \
+      -d "{\"email\":\"$EMAIL\",\"password\":\"$PASSWORD\"}"
+  done >>"$STATUSES"
+}
+
+read -r base_p50 base_p95 < <(measure)
+echo "baseline     p50=${base_p50}ms p95=${base_p95}ms"
+check_samples baseline
+
+for _ in $(seq "$STORM_CONCURRENCY"); do
+  login_loop &
+  pids+=($!)
+done
+
+sleep 2 # let the storm saturate the hashing pool
+read -r storm_p50 storm_p95 < <(measure)
+echo "login storm  p50=${storm_p50}ms p95=${storm_p95}ms"
+check_samples "login storm"
+
+wait "${pids[@]}" 2>/dev/null || true
+
+logins=$(wc -l <"$STATUSES")
+throttled=$(grep -c '^429$' "$STATUSES" || true)
+echo "logins       total=${logins} throttled=${throttled}"
+if [ "$throttled" -gt 0 ]; then
+  echo "FAIL: ${throttled} logins were rate limited; start the server with RATE_LIMIT_ENABLED=false" >&2
+  exit 1
+fi
+
+if awk -v base="$base_p95" -v storm="$storm_p95" -v max="$MAX_EXTRA_MS" \
+  'BEGIN { exit !(storm - base > max) }'; then
+  echo "FAIL: game listing p95 grew by more than ${MAX_EXTRA_MS}ms during the login storm" >&2
+  exit 1
+fi
+echo "OK: game listing latency stayed flat"
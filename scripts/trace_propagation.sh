@@ -0,0 +1,47 @@
+#!/usr/bin/env bash
+# Checks that an incoming traceparent is continued and that controller and query spans are
+# recorded, using the in-memory exporter.
+#
+# Start the server with:
+#   OTEL_TRACES_EXPORTER=memory cargo run
+#
+# Usage: ADMIN_TOKEN=<admin jwt> scripts/trace_propagation.sh [base-url]
+set -uo pipefail
+
+BASE_URL="${1:-http://localhost:8080}"
+: "${ADMIN_TOKEN:?ADMIN_TOKEN must be an admin bearer token}"
+failures=0
+
+trace_id=$(od -An -N16 -tx1 /dev/urandom | tr -d ' \n')
+parent_id=$(od -An -N8 -tx1 /dev/urandom | tr -d ' \n')
+
+curl -s -o /dev/null -X POST "$BASE_URL/api/auth/login" \
+  -H "traceparent: 00-$trace_id-$parent_id-01" \
+  -H "Content-Type: application/json" \
+  -d '{"email":"trace-check@example.com","password":"not-the-password"}'
+
+# One span per line: "<span_id> <parent_span_id> <kind> <name>"
+spans=$(curl -s "$BASE_URL/api/admin/traces?trace_id=$trace_id" -H "Authorization: Bearer $ADMIN_TOKEN" |
+  sed 's/},{/}\n{/g' |
+  sed -n 's/.*"span_id":"\([^"]*\)","parent_span_id":"\{0,1\}\([^",]*\)"\{0,1\},"name":"\([^"]*\)","kind":"\([^"]*\)".*/\1 \2 \4 \3/p')
+
+expect() {
+  local description="$1" pattern="$2"
+  if grep -q "$pattern" <<<"$spans"; then
+    echo "ok    $description"
+  else
+    echo "FAIL  $description (expected '$pattern')"
+    failures=$((failures + 1))
+  fi
+}
+
+expect "request span continues the caller's trace" "^[0-9a-f]* $parent_id server POST /api/auth/login$"
+expect "controller span is recorded" " internal login$"
+expect "query spans are recorded" " client SELECT$"
+
+if [ "$failures" -gt 0 ]; then
+  echo "$failures check(s) failed" >&2
+  echo "$spans" >&2
+  exit 1
+fi
+echo "all trace propagation checks passed"
@@ -0,0 +1,156 @@
+use actix_web::http::header::HeaderMap;
+use actix_web::{web, HttpRequest};
+use ipnet::IpNet;
+use serde::Deserialize;
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use crate::config::Config;
+
+// A reverse proxy whose X-Forwarded-For is believed: an address (`10.0.0.5`), a network
+// (`10.0.0.0/8`) or `unix` for connections on the Unix socket listeners
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum TrustedProxy {
+    Net(IpNet),
+    Unix,
+}
+
+impl FromStr for TrustedProxy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        if value == "unix" {
+            return Ok(Self::Unix);
+        }
+        value
+            .parse::<IpNet>()
+            .or_else(|_| value.parse::<IpAddr>().map(IpNet::from))
+            .map(Self::Net)
+            .map_err(|_| format!("invalid trusted proxy '{value}' (expected an IP, a CIDR network or unix)"))
+    }
+}
+
+impl TryFrom<String> for TrustedProxy {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl fmt::Display for TrustedProxy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Net(net) => write!(f, "{net}"),
+            Self::Unix => f.write_str("unix"),
+        }
+    }
+}
+
+// Address used for login throttling, rate limits and audit records. It is the TCP peer
+// unless that peer is a trusted proxy; only then is X-Forwarded-For read, right to left,
+// skipping further trusted hops. Clients cannot spoof it by sending the header themselves.
+pub fn client_ip(req: &HttpRequest) -> Option<String> {
+    let trusted = req
+        .app_data::<web::Data<Config>>()
+        .map(|config| config.server.trusted_proxies.as_slice())
+        .unwrap_or_default();
+    resolve(req.peer_addr().map(|addr| addr.ip()), req.headers(), trusted).map(|ip| ip.to_string())
+}
+
+fn resolve(peer: Option<IpAddr>, headers: &HeaderMap, trusted: &[TrustedProxy]) -> Option<IpAddr> {
+    let is_trusted = |ip: &IpAddr| trusted.iter().any(|proxy| matches!(proxy, TrustedProxy::Net(net) if net.contains(ip)));
+
+    // No peer address means a Unix socket connection
+    let peer_trusted = match peer {
+        Some(ip) => is_trusted(&ip),
+        None => trusted.contains(&TrustedProxy::Unix),
+    };
+    if !peer_trusted {
+        return peer;
+    }
+
+    let forwarded: Vec<IpAddr> = headers
+        .get_all("x-forwarded-for")
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|hop| hop.trim().parse::<IpAddr>())
+        .collect::<Result<_, _>>()
+        // A malformed chain cannot be trusted hop by hop
+        .unwrap_or_default();
+
+    forwarded
+        .iter()
+        .rev()
+        .find(|ip| !is_trusted(ip))
+        .or(forwarded.first())
+        .copied()
+        .or(peer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::{HeaderName, HeaderValue};
+
+    fn forwarded_for(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(HeaderName::from_static("x-forwarded-for"), HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    fn ip(value: &str) -> Option<IpAddr> {
+        Some(value.parse().unwrap())
+    }
+
+    fn proxies(values: &[&str]) -> Vec<TrustedProxy> {
+        values.iter().map(|value| value.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn ignores_forwarded_for_without_trusted_proxies() {
+        let headers = forwarded_for("1.2.3.4");
+        assert_eq!(resolve(ip("203.0.113.7"), &headers, &[]), ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn ignores_forwarded_for_from_untrusted_peer() {
+        let headers = forwarded_for("1.2.3.4");
+        let trusted = proxies(&["10.0.0.0/8"]);
+        assert_eq!(resolve(ip("203.0.113.7"), &headers, &trusted), ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn takes_rightmost_untrusted_hop_behind_trusted_proxies() {
+        // The client prepended a fake hop; the proxies appended the real address
+        let headers = forwarded_for("1.2.3.4, 198.51.100.9, 10.0.0.2");
+        let trusted = proxies(&["10.0.0.0/8"]);
+        assert_eq!(resolve(ip("10.0.0.1"), &headers, &trusted), ip("198.51.100.9"));
+    }
+
+    #[test]
+    fn falls_back_to_peer_without_header_or_on_garbage() {
+        let trusted = proxies(&["10.0.0.1"]);
+        assert_eq!(resolve(ip("10.0.0.1"), &HeaderMap::new(), &trusted), ip("10.0.0.1"));
+        assert_eq!(resolve(ip("10.0.0.1"), &forwarded_for("not-an-ip"), &trusted), ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn unix_socket_peers_need_explicit_trust() {
+        let headers = forwarded_for("198.51.100.9");
+        assert_eq!(resolve(None, &headers, &proxies(&["10.0.0.0/8"])), None);
+        assert_eq!(resolve(None, &headers, &proxies(&["unix"])), ip("198.51.100.9"));
+    }
+
+    #[test]
+    fn parses_addresses_networks_and_unix() {
+        assert!("10.0.0.5".parse::<TrustedProxy>().is_ok());
+        assert!("fd00::/8".parse::<TrustedProxy>().is_ok());
+        assert_eq!("unix".parse::<TrustedProxy>(), Ok(TrustedProxy::Unix));
+        assert!("10.0.0.0/33".parse::<TrustedProxy>().is_err());
+        assert!("proxy.local".parse::<TrustedProxy>().is_err());
+    }
+}
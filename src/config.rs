@@ -0,0 +1,1046 @@
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::client_ip::TrustedProxy;
+use crate::cors::CorsPreset;
+use crate::encryption::SecretBox;
+use crate::mailer::MailTransport;
+use crate::password::HashAlgorithm;
+use crate::rate_limit::RateLimit;
+use crate::telemetry::TraceExporter;
+
+const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+// Application settings, loaded once at startup and shared with handlers as `web::Data<Config>`.
+// Every value can come from the TOML file; the environment variable next to it wins when set.
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub app_url: String, // APP_URL, frontend base for links in emails
+    pub server: ServerConfig,
+    pub tls: TlsConfig,
+    pub database: DatabaseConfig,
+    pub jwt: JwtConfig,
+    pub cors: CorsConfig,
+    pub metrics: MetricsConfig,
+    pub tracing: TracingConfig,
+    pub rate_limit: RateLimitConfig,
+    pub tokens: TokenLifetimes,
+    pub mfa: MfaConfig,
+    pub login_throttle: LoginThrottleConfig,
+    pub password_hashing: PasswordHashingConfig,
+    pub mail: MailConfig,
+    pub oidc: OidcConfig,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub host: String,               // HOST
+    pub port: u16,                  // PORT
+    // BIND_ADDRESSES="0.0.0.0:8080,[::]:8080,unix:/run/app/api.sock"; replaces HOST/PORT when set
+    pub bind: Vec<String>,
+    pub workers: Option<usize>,     // WORKERS, one per CPU core when unset
+    pub keep_alive_secs: u64,       // KEEP_ALIVE_SECS, 0 disables keep-alive
+    pub body_limit_bytes: usize,    // BODY_LIMIT_BYTES, larger request bodies get 413
+    pub shutdown_timeout_secs: u64, // SHUTDOWN_TIMEOUT_SECS, how long in-flight requests may finish
+    // TRUSTED_PROXIES="10.0.0.0/8,unix"; X-Forwarded-For is only read from these peers
+    pub trusted_proxies: Vec<TrustedProxy>,
+}
+
+// HTTPS is served on the TCP bind addresses when both paths are set
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    pub cert_path: Option<PathBuf>,     // TLS_CERT_PATH, PEM chain, leaf first
+    pub key_path: Option<PathBuf>,      // TLS_KEY_PATH, PEM private key
+    pub reload_interval_secs: u64,      // TLS_RELOAD_INTERVAL_SECS, how often the files are checked; 0 disables
+    pub redirect_bind: Vec<String>,     // TLS_REDIRECT_ADDRESSES, plain-HTTP listeners that redirect to HTTPS
+    pub hsts_max_age_secs: u64,         // HSTS_MAX_AGE_SECS, 0 omits Strict-Transport-Security
+    pub hsts_include_subdomains: bool,  // HSTS_INCLUDE_SUBDOMAINS
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub url: String,               // DATABASE_URL
+    pub max_connections: u32,      // DATABASE_MAX_CONNECTIONS
+    pub min_connections: u32,      // DATABASE_MIN_CONNECTIONS
+    pub connect_timeout_secs: u64, // DATABASE_CONNECT_TIMEOUT_SECS
+    pub idle_timeout_secs: u64,    // DATABASE_IDLE_TIMEOUT_SECS
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JwtConfig {
+    pub algorithm: String,                 // JWT_ALGORITHM: HS256 | RS256 | EdDSA
+    pub secret: Option<String>,            // JWT_SECRET
+    pub key_id: Option<String>,            // JWT_KEY_ID
+    pub private_key_path: Option<PathBuf>, // JWT_PRIVATE_KEY_PATH
+    pub public_key_path: Option<PathBuf>,  // JWT_PUBLIC_KEY_PATH
+    // JWT_PREVIOUS_PUBLIC_KEYS="kid=path,kid=path"; still accepted during key rotation
+    pub previous_public_keys: BTreeMap<String, PathBuf>,
+    // JWT_ACCEPT_LEGACY_HS256_UNTIL (RFC 3339); with RS256/EdDSA, HS256 tokens signed with
+    // JWT_SECRET are only accepted until then
+    pub accept_legacy_hs256_until: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsConfig {
+    pub preset: CorsPreset,              // CORS_PRESET: development | production
+    pub allowed_origins: Vec<String>,    // CORS_ALLOWED_ORIGINS, comma separated
+    pub allowed_methods: Vec<String>,    // CORS_ALLOWED_METHODS
+    pub allowed_headers: Vec<String>,    // CORS_ALLOWED_HEADERS
+    pub expose_headers: Vec<String>,     // CORS_EXPOSE_HEADERS
+    pub allow_credentials: Option<bool>, // CORS_ALLOW_CREDENTIALS, preset decides when unset
+    pub max_age_secs: Option<usize>,     // CORS_MAX_AGE_SECS, preset decides when unset
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    pub enabled: bool,     // METRICS_ENABLED, serve GET /metrics
+    pub host: String,      // METRICS_HOST, only used with a separate port
+    pub port: Option<u16>, // METRICS_PORT, serve /metrics there instead of on the API port
+    pub on_api_port: bool, // METRICS_ON_API_PORT, serve /metrics on the public API port when no port is set
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TracingConfig {
+    pub exporter: TraceExporter, // OTEL_TRACES_EXPORTER: none | otlp | memory
+    pub otlp_endpoint: String,   // OTEL_EXPORTER_OTLP_ENDPOINT, collector base URL (OTLP/HTTP)
+    pub service_name: String,    // OTEL_SERVICE_NAME
+    pub sample_ratio: f64,       // OTEL_TRACES_SAMPLER_ARG, share of new traces kept (0.0 - 1.0)
+}
+
+// Limits per route scope, named where the scope is wrapped in `routes::config`
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub enabled: bool, // RATE_LIMIT_ENABLED
+    // RATE_LIMITS="auth=20/60,games=300/60" (requests/seconds); scopes left out are not limited
+    pub routes: BTreeMap<String, RateLimit>,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TokenLifetimes {
+    pub session_hours: i64,            // SESSION_TTL_HOURS
+    pub mfa_challenge_minutes: i64,    // MFA_CHALLENGE_TTL_MINUTES
+    pub invite_hours: i64,             // INVITE_TTL_HOURS
+    pub password_reset_minutes: i64,   // PASSWORD_RESET_TTL_MINUTES
+    pub email_verification_hours: i64, // EMAIL_VERIFICATION_TTL_HOURS
+    pub email_change_hours: i64,       // EMAIL_CHANGE_TTL_HOURS
+    pub impersonation_minutes: i64,    // IMPERSONATION_TTL_MINUTES
+    pub idempotency_key_hours: i64,    // IDEMPOTENCY_KEY_TTL_HOURS, how long POST responses are replayed
+    // IDEMPOTENCY_IN_PROGRESS_TIMEOUT_SECONDS, after which a retry may take over an attempt that never finished
+    pub idempotency_in_progress_seconds: i64,
+    pub sso_login_code_minutes: i64,   // SSO_LOGIN_CODE_TTL_MINUTES, one-time code handed to OIDC_FRONTEND_REDIRECT_URL
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MfaConfig {
+    pub required_roles: Vec<String>, // MFA_REQUIRED_ROLES, role-protected routes need 2FA for these roles
+    pub totp_issuer: String,         // TOTP_ISSUER, the account label shown in authenticator apps
+    // TOTP_ENCRYPTION_KEY, 32 random bytes base64 encoded; required, users.totp_secret is encrypted with it
+    pub totp_encryption_key: Option<String>,
+}
+
+// Failed-login thresholds; lockouts grow exponentially from base to max
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoginThrottleConfig {
+    pub max_account_attempts: u32, // LOGIN_MAX_ATTEMPTS, per account before it is locked
+    pub max_ip_attempts: u32,      // LOGIN_IP_MAX_ATTEMPTS, per client IP within the window
+    pub ip_window_secs: u64,       // LOGIN_IP_WINDOW_SECONDS
+    pub lockout_base_secs: u64,    // LOGIN_LOCKOUT_BASE_SECONDS
+    pub lockout_max_secs: u64,     // LOGIN_LOCKOUT_MAX_SECONDS
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PasswordHashingConfig {
+    pub algorithm: HashAlgorithm,   // PASSWORD_HASH_ALGORITHM: argon2id | bcrypt
+    pub bcrypt_cost: u32,           // BCRYPT_COST
+    pub argon2_memory_kib: u32,     // ARGON2_MEMORY_KIB
+    pub argon2_iterations: u32,     // ARGON2_ITERATIONS
+    pub argon2_parallelism: u32,    // ARGON2_PARALLELISM
+    pub concurrency: Option<usize>, // PASSWORD_HASH_CONCURRENCY, one per CPU core when unset
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MailConfig {
+    pub transport: Option<MailTransport>, // MAIL_TRANSPORT: smtp | file | log, required
+    pub from: String,                     // MAIL_FROM
+    pub smtp_host: Option<String>,        // SMTP_HOST, required for smtp
+    pub smtp_port: u16,                   // SMTP_PORT
+    pub smtp_username: Option<String>,    // SMTP_USERNAME
+    pub smtp_password: Option<String>,    // SMTP_PASSWORD (prefer the env var for secrets)
+    pub dir: PathBuf,                     // MAIL_DIR, where the file transport writes .eml files
+}
+
+// SSO is only enabled when issuer_url is set
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OidcConfig {
+    pub issuer_url: Option<String>,            // OIDC_ISSUER_URL
+    pub client_id: Option<String>,             // OIDC_CLIENT_ID
+    pub client_secret: Option<String>,         // OIDC_CLIENT_SECRET
+    pub redirect_url: Option<String>,          // OIDC_REDIRECT_URL
+    pub default_role: String,                  // OIDC_DEFAULT_ROLE, for users provisioned on first login
+    pub frontend_redirect_url: Option<String>, // OIDC_FRONTEND_REDIRECT_URL
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            app_url: "http://localhost:3000".to_string(),
+            server: ServerConfig::default(),
+            tls: TlsConfig::default(),
+            database: DatabaseConfig::default(),
+            jwt: JwtConfig::default(),
+            cors: CorsConfig::default(),
+            metrics: MetricsConfig::default(),
+            tracing: TracingConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+            tokens: TokenLifetimes::default(),
+            mfa: MfaConfig::default(),
+            login_throttle: LoginThrottleConfig::default(),
+            password_hashing: PasswordHashingConfig::default(),
+            mail: MailConfig::default(),
+            oidc: OidcConfig::default(),
+        }
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            host: "127.0.0.1".to_string(),
+            port: 8080,
+            bind: Vec::new(),
+            workers: None,
+            keep_alive_secs: 5,
+            body_limit_bytes: 256 * 1024,
+            shutdown_timeout_secs: 30,
+            trusted_proxies: Vec::new(),
+        }
+    }
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            cert_path: None,
+            key_path: None,
+            reload_interval_secs: 60,
+            redirect_bind: Vec::new(),
+            hsts_max_age_secs: 31_536_000,
+            hsts_include_subdomains: false,
+        }
+    }
+}
+
+impl TlsConfig {
+    pub fn enabled(&self) -> bool {
+        self.cert_path.is_some() && self.key_path.is_some()
+    }
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            max_connections: 10,
+            min_connections: 1,
+            connect_timeout_secs: 8,
+            idle_timeout_secs: 600,
+        }
+    }
+}
+
+impl Default for JwtConfig {
+    fn default() -> Self {
+        Self {
+            algorithm: "HS256".to_string(),
+            secret: None,
+            key_id: None,
+            private_key_path: None,
+            public_key_path: None,
+            previous_public_keys: BTreeMap::new(),
+            accept_legacy_hs256_until: None,
+        }
+    }
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            preset: CorsPreset::Production,
+            allowed_origins: vec!["http://localhost:3000".to_string()],
+            allowed_methods: ["GET", "POST", "PUT", "PATCH", "DELETE"].map(String::from).to_vec(),
+            allowed_headers: [
+                "Authorization",
+                "Content-Type",
+                "X-API-Key",
+                "X-Request-ID",
+                "Idempotency-Key",
+                "If-Match",
+                "If-None-Match",
+            ]
+            .map(String::from)
+            .to_vec(),
+            expose_headers: [
+                "X-Request-ID",
+                "X-RateLimit-Limit",
+                "X-RateLimit-Remaining",
+                "X-RateLimit-Reset",
+                "Retry-After",
+                "Idempotent-Replayed",
+                "ETag",
+                "Location",
+            ]
+            .map(String::from)
+            .to_vec(),
+            allow_credentials: None,
+            max_age_secs: None,
+        }
+    }
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            host: "127.0.0.1".to_string(),
+            port: None,
+            on_api_port: false,
+        }
+    }
+}
+
+impl Default for TracingConfig {
+    fn default() -> Self {
+        Self {
+            exporter: TraceExporter::None,
+            otlp_endpoint: "http://localhost:4318".to_string(),
+            service_name: "game-creator-api".to_string(),
+            sample_ratio: 1.0,
+        }
+    }
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        let routes = [
+            ("auth", "20/60"),
+            ("account", "60/60"),
+            ("admin", "120/60"),
+            ("creators", "300/60"),
+            ("games", "300/60"),
+            ("api_ip", "600/60"),
+        ];
+        Self {
+            enabled: true,
+            routes: routes
+                .into_iter()
+                .map(|(route, limit)| (route.to_string(), limit.parse().unwrap()))
+                .collect(),
+        }
+    }
+}
+
+impl Default for TokenLifetimes {
+    fn default() -> Self {
+        Self {
+            session_hours: 24,
+            mfa_challenge_minutes: 5,
+            invite_hours: 72,
+            password_reset_minutes: 60,
+            email_verification_hours: 24,
+            email_change_hours: 24,
+            impersonation_minutes: 15,
+            idempotency_key_hours: 24,
+            idempotency_in_progress_seconds: 60,
+            sso_login_code_minutes: 1,
+        }
+    }
+}
+
+impl Default for MfaConfig {
+    fn default() -> Self {
+        Self {
+            required_roles: Vec::new(),
+            totp_issuer: "GameCreatorAPI".to_string(),
+            totp_encryption_key: None,
+        }
+    }
+}
+
+impl MfaConfig {
+    pub fn required_for_role(&self, role: &str) -> bool {
+        self.required_roles.iter().any(|r| r == role)
+    }
+}
+
+impl Default for LoginThrottleConfig {
+    fn default() -> Self {
+        Self {
+            max_account_attempts: 5,
+            max_ip_attempts: 20,
+            ip_window_secs: 900,
+            lockout_base_secs: 60,
+            lockout_max_secs: 3600,
+        }
+    }
+}
+
+impl Default for PasswordHashingConfig {
+    // Argon2id defaults follow the OWASP recommendation (19 MiB, 2 passes)
+    fn default() -> Self {
+        Self {
+            algorithm: HashAlgorithm::Argon2id,
+            bcrypt_cost: bcrypt::DEFAULT_COST,
+            argon2_memory_kib: 19 * 1024,
+            argon2_iterations: 2,
+            argon2_parallelism: 1,
+            concurrency: None,
+        }
+    }
+}
+
+impl Default for MailConfig {
+    fn default() -> Self {
+        Self {
+            transport: None,
+            from: "no-reply@localhost".to_string(),
+            smtp_host: None,
+            smtp_port: 587,
+            smtp_username: None,
+            smtp_password: None,
+            dir: PathBuf::from("mail"),
+        }
+    }
+}
+
+impl Default for OidcConfig {
+    fn default() -> Self {
+        Self {
+            issuer_url: None,
+            client_id: None,
+            client_secret: None,
+            redirect_url: None,
+            default_role: "creator".to_string(),
+            frontend_redirect_url: None,
+        }
+    }
+}
+
+impl Config {
+    // Defaults, then the TOML file named by CONFIG_FILE (or ./config.toml when present),
+    // then environment variables
+    pub fn load() -> anyhow::Result<Self> {
+        let mut config = match env::var("CONFIG_FILE") {
+            Ok(path) => Self::from_file(Path::new(&path))?,
+            Err(_) if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                Self::from_file(Path::new(DEFAULT_CONFIG_FILE))?
+            }
+            Err(_) => Self::default(),
+        };
+        config.apply_env()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read config file {}: {e}", path.display()))?;
+        toml::from_str(&content)
+            .map_err(|e| anyhow::anyhow!("Invalid config file {}: {e}", path.display()))
+    }
+
+    fn apply_env(&mut self) -> anyhow::Result<()> {
+        set_from_env(&mut self.app_url, "APP_URL")?;
+
+        set_from_env(&mut self.server.host, "HOST")?;
+        set_from_env(&mut self.server.port, "PORT")?;
+        set_list_from_env(&mut self.server.bind, "BIND_ADDRESSES");
+        set_optional_from_env(&mut self.server.workers, "WORKERS")?;
+        set_from_env(&mut self.server.keep_alive_secs, "KEEP_ALIVE_SECS")?;
+        set_from_env(&mut self.server.body_limit_bytes, "BODY_LIMIT_BYTES")?;
+        set_from_env(&mut self.server.shutdown_timeout_secs, "SHUTDOWN_TIMEOUT_SECS")?;
+        if let Ok(proxies) = env::var("TRUSTED_PROXIES") {
+            self.server.trusted_proxies = split_list(&proxies)
+                .iter()
+                .map(|proxy| proxy.parse())
+                .collect::<Result<_, _>>()
+                .map_err(|e| anyhow::anyhow!("TRUSTED_PROXIES: {e}"))?;
+        }
+
+        let tls = &mut self.tls;
+        set_optional_from_env(&mut tls.cert_path, "TLS_CERT_PATH")?;
+        set_optional_from_env(&mut tls.key_path, "TLS_KEY_PATH")?;
+        set_from_env(&mut tls.reload_interval_secs, "TLS_RELOAD_INTERVAL_SECS")?;
+        set_list_from_env(&mut tls.redirect_bind, "TLS_REDIRECT_ADDRESSES");
+        set_from_env(&mut tls.hsts_max_age_secs, "HSTS_MAX_AGE_SECS")?;
+        set_from_env(&mut tls.hsts_include_subdomains, "HSTS_INCLUDE_SUBDOMAINS")?;
+
+        set_from_env(&mut self.database.url, "DATABASE_URL")?;
+        set_from_env(&mut self.database.max_connections, "DATABASE_MAX_CONNECTIONS")?;
+        set_from_env(&mut self.database.min_connections, "DATABASE_MIN_CONNECTIONS")?;
+        set_from_env(&mut self.database.connect_timeout_secs, "DATABASE_CONNECT_TIMEOUT_SECS")?;
+        set_from_env(&mut self.database.idle_timeout_secs, "DATABASE_IDLE_TIMEOUT_SECS")?;
+
+        set_from_env(&mut self.jwt.algorithm, "JWT_ALGORITHM")?;
+        set_optional_from_env(&mut self.jwt.secret, "JWT_SECRET")?;
+        set_optional_from_env(&mut self.jwt.key_id, "JWT_KEY_ID")?;
+        set_optional_from_env(&mut self.jwt.private_key_path, "JWT_PRIVATE_KEY_PATH")?;
+        set_optional_from_env(&mut self.jwt.public_key_path, "JWT_PUBLIC_KEY_PATH")?;
+        set_optional_from_env(&mut self.jwt.accept_legacy_hs256_until, "JWT_ACCEPT_LEGACY_HS256_UNTIL")?;
+        if let Ok(previous) = env::var("JWT_PREVIOUS_PUBLIC_KEYS") {
+            self.jwt.previous_public_keys.clear();
+            for entry in previous.split(',').filter(|entry| !entry.trim().is_empty()) {
+                let (kid, path) = entry
+                    .split_once('=')
+                    .ok_or_else(|| anyhow::anyhow!("JWT_PREVIOUS_PUBLIC_KEYS: invalid entry '{entry}' (expected kid=path)"))?;
+                self.jwt
+                    .previous_public_keys
+                    .insert(kid.trim().to_string(), PathBuf::from(path.trim()));
+            }
+        }
+
+        let cors = &mut self.cors;
+        set_from_env(&mut cors.preset, "CORS_PRESET")?;
+        set_list_from_env(&mut cors.allowed_origins, "CORS_ALLOWED_ORIGINS");
+        set_list_from_env(&mut cors.allowed_methods, "CORS_ALLOWED_METHODS");
+        set_list_from_env(&mut cors.allowed_headers, "CORS_ALLOWED_HEADERS");
+        set_list_from_env(&mut cors.expose_headers, "CORS_EXPOSE_HEADERS");
+        set_optional_from_env(&mut cors.allow_credentials, "CORS_ALLOW_CREDENTIALS")?;
+        set_optional_from_env(&mut cors.max_age_secs, "CORS_MAX_AGE_SECS")?;
+
+        set_from_env(&mut self.metrics.enabled, "METRICS_ENABLED")?;
+        set_from_env(&mut self.metrics.host, "METRICS_HOST")?;
+        set_optional_from_env(&mut self.metrics.port, "METRICS_PORT")?;
+        set_from_env(&mut self.metrics.on_api_port, "METRICS_ON_API_PORT")?;
+
+        let tracing = &mut self.tracing;
+        set_from_env(&mut tracing.exporter, "OTEL_TRACES_EXPORTER")?;
+        set_from_env(&mut tracing.otlp_endpoint, "OTEL_EXPORTER_OTLP_ENDPOINT")?;
+        set_from_env(&mut tracing.service_name, "OTEL_SERVICE_NAME")?;
+        set_from_env(&mut tracing.sample_ratio, "OTEL_TRACES_SAMPLER_ARG")?;
+
+        set_from_env(&mut self.rate_limit.enabled, "RATE_LIMIT_ENABLED")?;
+        if let Ok(limits) = env::var("RATE_LIMITS") {
+            self.rate_limit.routes.clear();
+            for entry in split_list(&limits) {
+                let (route, limit) = entry
+                    .split_once('=')
+                    .ok_or_else(|| anyhow::anyhow!("RATE_LIMITS: invalid entry '{entry}' (expected route=requests/seconds)"))?;
+                let limit = limit.parse().map_err(|e| anyhow::anyhow!("RATE_LIMITS: {e}"))?;
+                self.rate_limit.routes.insert(route.trim().to_string(), limit);
+            }
+        }
+
+        let tokens = &mut self.tokens;
+        set_from_env(&mut tokens.session_hours, "SESSION_TTL_HOURS")?;
+        set_from_env(&mut tokens.mfa_challenge_minutes, "MFA_CHALLENGE_TTL_MINUTES")?;
+        set_from_env(&mut tokens.invite_hours, "INVITE_TTL_HOURS")?;
+        set_from_env(&mut tokens.password_reset_minutes, "PASSWORD_RESET_TTL_MINUTES")?;
+        set_from_env(&mut tokens.email_verification_hours, "EMAIL_VERIFICATION_TTL_HOURS")?;
+        set_from_env(&mut tokens.email_change_hours, "EMAIL_CHANGE_TTL_HOURS")?;
+        set_from_env(&mut tokens.impersonation_minutes, "IMPERSONATION_TTL_MINUTES")?;
+        set_from_env(&mut tokens.idempotency_key_hours, "IDEMPOTENCY_KEY_TTL_HOURS")?;
+        set_from_env(&mut tokens.idempotency_in_progress_seconds, "IDEMPOTENCY_IN_PROGRESS_TIMEOUT_SECONDS")?;
+        set_from_env(&mut tokens.sso_login_code_minutes, "SSO_LOGIN_CODE_TTL_MINUTES")?;
+
+        set_list_from_env(&mut self.mfa.required_roles, "MFA_REQUIRED_ROLES");
+        set_from_env(&mut self.mfa.totp_issuer, "TOTP_ISSUER")?;
+        set_optional_from_env(&mut self.mfa.totp_encryption_key, "TOTP_ENCRYPTION_KEY")?;
+
+        let login_throttle = &mut self.login_throttle;
+        set_from_env(&mut login_throttle.max_account_attempts, "LOGIN_MAX_ATTEMPTS")?;
+        set_from_env(&mut login_throttle.max_ip_attempts, "LOGIN_IP_MAX_ATTEMPTS")?;
+        set_from_env(&mut login_throttle.ip_window_secs, "LOGIN_IP_WINDOW_SECONDS")?;
+        set_from_env(&mut login_throttle.lockout_base_secs, "LOGIN_LOCKOUT_BASE_SECONDS")?;
+        set_from_env(&mut login_throttle.lockout_max_secs, "LOGIN_LOCKOUT_MAX_SECONDS")?;
+
+        let hashing = &mut self.password_hashing;
+        set_from_env(&mut hashing.algorithm, "PASSWORD_HASH_ALGORITHM")?;
+        set_from_env(&mut hashing.bcrypt_cost, "BCRYPT_COST")?;
+        set_from_env(&mut hashing.argon2_memory_kib, "ARGON2_MEMORY_KIB")?;
+        set_from_env(&mut hashing.argon2_iterations, "ARGON2_ITERATIONS")?;
+        set_from_env(&mut hashing.argon2_parallelism, "ARGON2_PARALLELISM")?;
+        set_optional_from_env(&mut hashing.concurrency, "PASSWORD_HASH_CONCURRENCY")?;
+
+        let mail = &mut self.mail;
+        set_optional_from_env(&mut mail.transport, "MAIL_TRANSPORT")?;
+        set_from_env(&mut mail.from, "MAIL_FROM")?;
+        set_optional_from_env(&mut mail.smtp_host, "SMTP_HOST")?;
+        set_from_env(&mut mail.smtp_port, "SMTP_PORT")?;
+        set_optional_from_env(&mut mail.smtp_username, "SMTP_USERNAME")?;
+        set_optional_from_env(&mut mail.smtp_password, "SMTP_PASSWORD")?;
+        set_from_env(&mut mail.dir, "MAIL_DIR")?;
+
+        let oidc = &mut self.oidc;
+        set_optional_from_env(&mut oidc.issuer_url, "OIDC_ISSUER_URL")?;
+        set_optional_from_env(&mut oidc.client_id, "OIDC_CLIENT_ID")?;
+        set_optional_from_env(&mut oidc.client_secret, "OIDC_CLIENT_SECRET")?;
+        set_optional_from_env(&mut oidc.redirect_url, "OIDC_REDIRECT_URL")?;
+        set_from_env(&mut oidc.default_role, "OIDC_DEFAULT_ROLE")?;
+        set_optional_from_env(&mut oidc.frontend_redirect_url, "OIDC_FRONTEND_REDIRECT_URL")?;
+
+        Ok(())
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if !is_http_url(&self.app_url) {
+            anyhow::bail!("app_url (APP_URL) must be an http(s) URL, got '{}'", self.app_url);
+        }
+
+        let server = &self.server;
+        if server.host.trim().is_empty() {
+            anyhow::bail!("server.host (HOST) must not be empty");
+        }
+        server
+            .bind_addresses()
+            .map_err(|e| anyhow::anyhow!("server.bind (BIND_ADDRESSES): {e}"))?;
+        if server.workers == Some(0) {
+            anyhow::bail!("server.workers (WORKERS) must be at least 1");
+        }
+        if server.body_limit_bytes == 0 {
+            anyhow::bail!("server.body_limit_bytes (BODY_LIMIT_BYTES) must be positive");
+        }
+
+        let tls = &self.tls;
+        if tls.cert_path.is_some() != tls.key_path.is_some() {
+            anyhow::bail!("tls.cert_path (TLS_CERT_PATH) and tls.key_path (TLS_KEY_PATH) must be set together");
+        }
+        if tls.enabled() && server.https_port()?.is_none() {
+            anyhow::bail!("TLS needs at least one TCP bind address; Unix sockets are served without TLS");
+        }
+        if !tls.redirect_bind.is_empty() {
+            if !tls.enabled() {
+                anyhow::bail!("tls.redirect_bind (TLS_REDIRECT_ADDRESSES) requires TLS_CERT_PATH and TLS_KEY_PATH");
+            }
+            tls.redirect_addresses()
+                .map_err(|e| anyhow::anyhow!("tls.redirect_bind (TLS_REDIRECT_ADDRESSES): {e}"))?;
+        }
+
+        let database = &self.database;
+        if database.url.is_empty() {
+            anyhow::bail!("database.url (DATABASE_URL) must be set");
+        }
+        if !database.url.starts_with("postgres://") && !database.url.starts_with("postgresql://") {
+            anyhow::bail!("database.url (DATABASE_URL) must be a postgres:// URL");
+        }
+        if database.max_connections == 0 {
+            anyhow::bail!("database.max_connections (DATABASE_MAX_CONNECTIONS) must be at least 1");
+        }
+        if database.min_connections > database.max_connections {
+            anyhow::bail!(
+                "database.min_connections ({}) must not exceed database.max_connections ({})",
+                database.min_connections,
+                database.max_connections
+            );
+        }
+
+        let jwt = &self.jwt;
+        match jwt.algorithm.as_str() {
+            "HS256" => {
+                if jwt.secret.as_deref().unwrap_or_default().is_empty() {
+                    anyhow::bail!("jwt.secret (JWT_SECRET) must be set when jwt.algorithm is HS256");
+                }
+                if jwt.accept_legacy_hs256_until.is_some() {
+                    anyhow::bail!("jwt.accept_legacy_hs256_until (JWT_ACCEPT_LEGACY_HS256_UNTIL) only applies when jwt.algorithm is RS256 or EdDSA");
+                }
+            }
+            "RS256" | "EdDSA" => {
+                if jwt.key_id.is_none() {
+                    anyhow::bail!("jwt.key_id (JWT_KEY_ID) must be set when jwt.algorithm is {}", jwt.algorithm);
+                }
+                if jwt.private_key_path.is_none() || jwt.public_key_path.is_none() {
+                    anyhow::bail!(
+                        "jwt.private_key_path and jwt.public_key_path (JWT_PRIVATE_KEY_PATH / JWT_PUBLIC_KEY_PATH) must be set when jwt.algorithm is {}",
+                        jwt.algorithm
+                    );
+                }
+                if jwt.accept_legacy_hs256_until.is_some() && jwt.secret.as_deref().unwrap_or_default().is_empty() {
+                    anyhow::bail!("jwt.accept_legacy_hs256_until (JWT_ACCEPT_LEGACY_HS256_UNTIL) requires jwt.secret (JWT_SECRET)");
+                }
+            }
+            other => anyhow::bail!("jwt.algorithm (JWT_ALGORITHM) must be HS256, RS256 or EdDSA, got '{other}'"),
+        }
+
+        self.cors.validate()?;
+
+        if let Some(port) = self.metrics.port {
+            if self.metrics.on_api_port {
+                anyhow::bail!("metrics.on_api_port (METRICS_ON_API_PORT) cannot be combined with metrics.port (METRICS_PORT)");
+            }
+            if self.metrics.host.trim().is_empty() {
+                anyhow::bail!("metrics.host (METRICS_HOST) must not be empty");
+            }
+            if self.server.bind_addresses()?.iter().any(|address| address.port() == Some(port)) {
+                anyhow::bail!("metrics.port (METRICS_PORT) must differ from the API port(s) in server.bind / server.port ({port})");
+            }
+        }
+
+        let tracing = &self.tracing;
+        if tracing.exporter == TraceExporter::Otlp && !is_http_url(&tracing.otlp_endpoint) {
+            anyhow::bail!(
+                "tracing.otlp_endpoint (OTEL_EXPORTER_OTLP_ENDPOINT) must be an http(s) URL, got '{}'",
+                tracing.otlp_endpoint
+            );
+        }
+        if !(0.0..=1.0).contains(&tracing.sample_ratio) {
+            anyhow::bail!(
+                "tracing.sample_ratio (OTEL_TRACES_SAMPLER_ARG) must be between 0.0 and 1.0, got {}",
+                tracing.sample_ratio
+            );
+        }
+        if tracing.service_name.trim().is_empty() {
+            anyhow::bail!("tracing.service_name (OTEL_SERVICE_NAME) must not be empty");
+        }
+
+        let tokens = &self.tokens;
+        for (name, value) in [
+            ("tokens.session_hours", tokens.session_hours),
+            ("tokens.mfa_challenge_minutes", tokens.mfa_challenge_minutes),
+            ("tokens.invite_hours", tokens.invite_hours),
+            ("tokens.password_reset_minutes", tokens.password_reset_minutes),
+            ("tokens.email_verification_hours", tokens.email_verification_hours),
+            ("tokens.email_change_hours", tokens.email_change_hours),
+            ("tokens.impersonation_minutes", tokens.impersonation_minutes),
+            ("tokens.idempotency_key_hours", tokens.idempotency_key_hours),
+            ("tokens.idempotency_in_progress_seconds", tokens.idempotency_in_progress_seconds),
+            ("tokens.sso_login_code_minutes", tokens.sso_login_code_minutes),
+        ] {
+            if value <= 0 {
+                anyhow::bail!("{name} must be positive, got {value}");
+            }
+        }
+
+        let login_throttle = &self.login_throttle;
+        if login_throttle.max_account_attempts == 0 || login_throttle.max_ip_attempts == 0 {
+            anyhow::bail!("login_throttle.max_account_attempts and max_ip_attempts (LOGIN_MAX_ATTEMPTS / LOGIN_IP_MAX_ATTEMPTS) must be at least 1");
+        }
+        if login_throttle.lockout_base_secs > login_throttle.lockout_max_secs {
+            anyhow::bail!("login_throttle.lockout_base_secs (LOGIN_LOCKOUT_BASE_SECONDS) must not exceed lockout_max_secs");
+        }
+
+        if self.password_hashing.concurrency == Some(0) {
+            anyhow::bail!("password_hashing.concurrency (PASSWORD_HASH_CONCURRENCY) must be at least 1");
+        }
+
+        let mail = &self.mail;
+        match mail.transport {
+            None => anyhow::bail!("mail.transport (MAIL_TRANSPORT) must be set to smtp, file or log"),
+            Some(MailTransport::Smtp) if mail.smtp_host.as_deref().unwrap_or_default().is_empty() => {
+                anyhow::bail!("mail.smtp_host (SMTP_HOST) must be set when mail.transport is smtp");
+            }
+            Some(_) => {}
+        }
+
+        match &self.mfa.totp_encryption_key {
+            None => anyhow::bail!("mfa.totp_encryption_key (TOTP_ENCRYPTION_KEY) must be set, e.g. `openssl rand -base64 32`"),
+            Some(key) => {
+                SecretBox::from_base64(key)
+                    .map_err(|e| anyhow::anyhow!("mfa.totp_encryption_key (TOTP_ENCRYPTION_KEY): {e}"))?;
+            }
+        }
+
+        let oidc = &self.oidc;
+        // Accounts created on first SSO login must never get elevated roles
+        if oidc.default_role != "creator" {
+            anyhow::bail!("oidc.default_role (OIDC_DEFAULT_ROLE) must be 'creator', got '{}'", oidc.default_role);
+        }
+        if let Some(issuer_url) = &oidc.issuer_url {
+            if !is_http_url(issuer_url) {
+                anyhow::bail!("oidc.issuer_url (OIDC_ISSUER_URL) must be an http(s) URL, got '{issuer_url}'");
+            }
+            if oidc.client_id.is_none() {
+                anyhow::bail!("oidc.client_id (OIDC_CLIENT_ID) must be set when oidc.issuer_url is set");
+            }
+            if oidc.redirect_url.is_none() {
+                anyhow::bail!("oidc.redirect_url (OIDC_REDIRECT_URL) must be set when oidc.issuer_url is set");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn set_from_env<T: FromStr>(target: &mut T, name: &str) -> anyhow::Result<()> {
+    if let Ok(value) = env::var(name) {
+        *target = value
+            .parse()
+            .map_err(|_| anyhow::anyhow!("{name}: invalid value '{value}'"))?;
+    }
+    Ok(())
+}
+
+fn set_optional_from_env<T: FromStr>(target: &mut Option<T>, name: &str) -> anyhow::Result<()> {
+    if let Ok(value) = env::var(name) {
+        *target = Some(
+            value
+                .parse()
+                .map_err(|_| anyhow::anyhow!("{name}: invalid value '{value}'"))?,
+        );
+    }
+    Ok(())
+}
+
+fn set_list_from_env(target: &mut Vec<String>, name: &str) {
+    if let Ok(value) = env::var(name) {
+        *target = split_list(&value);
+    }
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn is_http_url(value: &str) -> bool {
+    value.starts_with("http://") || value.starts_with("https://")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    // Config::load reads process-wide environment variables, so those tests run one at a time
+    static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+    // Variables the tests below assert on; cleared first so the caller's shell can't leak in
+    const TEST_ENV: [&str; 10] = [
+        "CONFIG_FILE", "HOST", "PORT", "WORKERS", "DATABASE_URL", "JWT_ALGORITHM", "JWT_SECRET", "MAIL_TRANSPORT",
+        "LOGIN_MAX_ATTEMPTS", "TOTP_ENCRYPTION_KEY",
+    ];
+
+    fn write_file(name: &str, content: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("config-test-{}-{name}.toml", std::process::id()));
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn load_with(file: &str, content: &str, vars: &[(&str, &str)]) -> anyhow::Result<Config> {
+        let _guard = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        for name in TEST_ENV {
+            env::remove_var(name);
+        }
+        env::set_var("CONFIG_FILE", write_file(file, content));
+        for (name, value) in vars {
+            env::set_var(name, value);
+        }
+        let result = Config::load();
+        for name in TEST_ENV.iter().copied().chain(vars.iter().map(|(name, _)| *name)) {
+            env::remove_var(name);
+        }
+        result
+    }
+
+    fn valid() -> Config {
+        let mut config = Config::default();
+        config.database.url = "postgres://localhost/test".to_string();
+        config.jwt.secret = Some("secret".to_string());
+        config.mail.transport = Some(MailTransport::Log);
+        config.mfa.totp_encryption_key = Some(TOTP_KEY.to_string());
+        config
+    }
+
+    const TOTP_KEY: &str = "MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=";
+
+    fn error(config: &Config) -> String {
+        config.validate().unwrap_err().to_string()
+    }
+
+    const REQUIRED: &str = r#"
+[database]
+url = "postgres://file/db"
+
+[jwt]
+secret = "file-secret"
+
+[mail]
+transport = "log"
+
+[mfa]
+totp_encryption_key = "MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY="
+"#;
+
+    #[test]
+    fn defaults_are_valid_once_required_keys_are_set() {
+        let config = valid();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.server.host, "127.0.0.1");
+        assert_eq!(config.server.port, 8080);
+        assert_eq!(config.jwt.algorithm, "HS256");
+    }
+
+    #[test]
+    fn file_overrides_defaults() {
+        let content = format!("[server]\nhost = \"0.0.0.0\"\nport = 9000\n{REQUIRED}");
+        let config = load_with("file", &content, &[]).unwrap();
+        assert_eq!(config.server.host, "0.0.0.0");
+        assert_eq!(config.server.port, 9000);
+        assert_eq!(config.server.keep_alive_secs, 5);
+        assert_eq!(config.database.url, "postgres://file/db");
+    }
+
+    #[test]
+    fn env_overrides_file() {
+        let content = format!("[server]\nhost = \"0.0.0.0\"\nport = 9000\n{REQUIRED}");
+        let config = load_with(
+            "env",
+            &content,
+            &[("PORT", "9100"), ("DATABASE_URL", "postgres://env/db"), ("LOGIN_MAX_ATTEMPTS", "3")],
+        )
+        .unwrap();
+        assert_eq!(config.server.port, 9100);
+        assert_eq!(config.server.host, "0.0.0.0");
+        assert_eq!(config.database.url, "postgres://env/db");
+        assert_eq!(config.jwt.secret.as_deref(), Some("file-secret"));
+        assert_eq!(config.login_throttle.max_account_attempts, 3);
+    }
+
+    #[test]
+    fn invalid_env_value_names_the_variable() {
+        let error = load_with("invalid-env", REQUIRED, &[("PORT", "abc")]).err().unwrap();
+        assert_eq!(error.to_string(), "PORT: invalid value 'abc'");
+    }
+
+    #[test]
+    fn unknown_file_key_is_rejected() {
+        let content = format!("[server]\nprot = 9000\n{REQUIRED}");
+        let error = load_with("unknown", &content, &[]).err().unwrap().to_string();
+        assert!(error.starts_with("Invalid config file"), "{error}");
+        assert!(error.contains("unknown field `prot`"), "{error}");
+    }
+
+    #[test]
+    fn missing_mail_transport_is_rejected() {
+        let content = REQUIRED.replace("[mail]\ntransport = \"log\"\n", "");
+        let error = load_with("no-mail", &content, &[])
+            .err()
+            .unwrap();
+        assert_eq!(error.to_string(), "mail.transport (MAIL_TRANSPORT) must be set to smtp, file or log");
+    }
+
+    #[test]
+    fn totp_encryption_key_is_required_and_checked() {
+        let mut config = valid();
+        config.mfa.totp_encryption_key = None;
+        assert_eq!(
+            error(&config),
+            "mfa.totp_encryption_key (TOTP_ENCRYPTION_KEY) must be set, e.g. `openssl rand -base64 32`"
+        );
+        config.mfa.totp_encryption_key = Some("c2hvcnQ=".to_string());
+        assert_eq!(error(&config), "mfa.totp_encryption_key (TOTP_ENCRYPTION_KEY): key must be 32 bytes, got 5");
+    }
+
+    #[test]
+    fn missing_database_url_is_rejected() {
+        let mut config = valid();
+        config.database.url = String::new();
+        assert_eq!(error(&config), "database.url (DATABASE_URL) must be set");
+    }
+
+    #[test]
+    fn hs256_requires_secret() {
+        let mut config = valid();
+        config.jwt.secret = None;
+        assert_eq!(error(&config), "jwt.secret (JWT_SECRET) must be set when jwt.algorithm is HS256");
+    }
+
+    #[test]
+    fn asymmetric_algorithms_require_key_id_and_key_files() {
+        for algorithm in ["RS256", "EdDSA"] {
+            let mut config = valid();
+            config.jwt.algorithm = algorithm.to_string();
+            assert_eq!(error(&config), format!("jwt.key_id (JWT_KEY_ID) must be set when jwt.algorithm is {algorithm}"));
+
+            config.jwt.key_id = Some("2025-06".to_string());
+            config.jwt.private_key_path = Some("keys/2025-06.pem".into());
+            assert_eq!(
+                error(&config),
+                format!("jwt.private_key_path and jwt.public_key_path (JWT_PRIVATE_KEY_PATH / JWT_PUBLIC_KEY_PATH) must be set when jwt.algorithm is {algorithm}")
+            );
+
+            config.jwt.public_key_path = Some("keys/2025-06.pub.pem".into());
+            config.jwt.secret = None;
+            assert!(config.validate().is_ok());
+        }
+    }
+
+    #[test]
+    fn unknown_algorithm_is_rejected() {
+        let mut config = valid();
+        config.jwt.algorithm = "none".to_string();
+        assert_eq!(error(&config), "jwt.algorithm (JWT_ALGORITHM) must be HS256, RS256 or EdDSA, got 'none'");
+    }
+
+    #[test]
+    fn legacy_hs256_window_needs_asymmetric_algorithm_and_secret() {
+        let mut config = valid();
+        config.jwt.accept_legacy_hs256_until = Some(Utc::now());
+        assert_eq!(
+            error(&config),
+            "jwt.accept_legacy_hs256_until (JWT_ACCEPT_LEGACY_HS256_UNTIL) only applies when jwt.algorithm is RS256 or EdDSA"
+        );
+
+        config.jwt.algorithm = "EdDSA".to_string();
+        config.jwt.key_id = Some("2025-06".to_string());
+        config.jwt.private_key_path = Some("keys/2025-06.pem".into());
+        config.jwt.public_key_path = Some("keys/2025-06.pub.pem".into());
+        config.jwt.secret = None;
+        assert_eq!(
+            error(&config),
+            "jwt.accept_legacy_hs256_until (JWT_ACCEPT_LEGACY_HS256_UNTIL) requires jwt.secret (JWT_SECRET)"
+        );
+    }
+
+    #[test]
+    fn smtp_requires_host() {
+        let mut config = valid();
+        config.mail.transport = Some(MailTransport::Smtp);
+        assert_eq!(error(&config), "mail.smtp_host (SMTP_HOST) must be set when mail.transport is smtp");
+    }
+
+    #[test]
+    fn oidc_default_role_must_be_creator() {
+        let mut config = valid();
+        config.oidc.default_role = "admin".to_string();
+        assert_eq!(error(&config), "oidc.default_role (OIDC_DEFAULT_ROLE) must be 'creator', got 'admin'");
+    }
+
+    #[test]
+    fn oidc_issuer_requires_client_and_redirect() {
+        let mut config = valid();
+        config.oidc.issuer_url = Some("https://accounts.example.com".to_string());
+        assert_eq!(error(&config), "oidc.client_id (OIDC_CLIENT_ID) must be set when oidc.issuer_url is set");
+        config.oidc.client_id = Some("api".to_string());
+        assert_eq!(error(&config), "oidc.redirect_url (OIDC_REDIRECT_URL) must be set when oidc.issuer_url is set");
+    }
+
+    #[test]
+    fn zero_workers_is_rejected() {
+        let mut config = valid();
+        config.server.workers = Some(0);
+        assert_eq!(error(&config), "server.workers (WORKERS) must be at least 1");
+    }
+}
@@ -0,0 +1,344 @@
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{Duration, Utc};
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, Set,
+    SqlErr, TransactionTrait,
+};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::client_ip::client_ip;
+use crate::config::Config;
+use crate::controllers::api_key_controller::revoke_api_keys;
+use crate::controllers::session_controller::revoke_sessions;
+use crate::dtos::auth_dto::{ChangeEmailRequest, ChangePasswordRequest, ConfirmEmailChangeRequest};
+use crate::logging;
+use crate::mailer::{Email, Mailer};
+use crate::middleware::auth::get_user_from_request;
+use crate::models::{creator, email_change_token, user};
+use crate::password;
+use crate::security_events;
+use crate::tokens::{generate_token, hash_token};
+
+#[tracing::instrument(skip_all)]
+pub async fn change_password(
+    http_req: HttpRequest,
+    db: web::Data<DatabaseConnection>,
+    req: web::Json<ChangePasswordRequest>,
+) -> Result<HttpResponse> {
+    let db = db.get_ref();
+    let claims = get_user_from_request(&http_req)?;
+    let user = find_current_user(db, &claims.sub).await?;
+
+    if !password_matches(&req.current_password, &user).await? {
+        return Err(actix_web::error::ErrorUnauthorized("Current password is incorrect"));
+    }
+
+    let password_hash = password::hash(&req.new_password)
+        .await
+        .map_err(|e| {
+            log::error!(error:% = e; "Password hashing error");
+            actix_web::error::ErrorInternalServerError("Password hashing error")
+        })?;
+
+    let txn = db.begin().await.map_err(|e| {
+        log::error!(error:% = e; "Database error");
+        actix_web::error::ErrorInternalServerError("Database error")
+    })?;
+
+    let now = Utc::now();
+    user::Entity::update_many()
+        .col_expr(user::Column::PasswordHash, Expr::value(password_hash))
+        .col_expr(user::Column::UpdatedAt, Expr::value(now))
+        .filter(user::Column::Id.eq(user.id))
+        .exec(&txn)
+        .await
+        .map_err(|e| {
+            log::error!(error:% = e; "Password update error");
+            actix_web::error::ErrorInternalServerError("Password update error")
+        })?;
+
+    // Sign out everywhere except the session making this request. API keys were minted on the
+    // strength of the old password, so they go too.
+    let current = claims.sid.as_deref().and_then(|sid| sid.parse::<Uuid>().ok());
+    let revoked_sessions = revoke_sessions(&txn, user.id, current).await.map_err(|e| {
+        log::error!(error:% = e; "Database error");
+        actix_web::error::ErrorInternalServerError("Database error")
+    })?;
+    let revoked_api_keys = revoke_api_keys(&txn, user.id).await.map_err(|e| {
+        log::error!(error:% = e; "Database error");
+        actix_web::error::ErrorInternalServerError("Database error")
+    })?;
+
+    txn.commit().await.map_err(|e| {
+        log::error!(error:% = e; "Database error");
+        actix_web::error::ErrorInternalServerError("Database error")
+    })?;
+
+    let ip = client_ip(&http_req);
+    security_events::record(
+        db,
+        Some(user.id),
+        security_events::PASSWORD_CHANGED,
+        ip.as_deref(),
+        Some(json!({
+            "revoked_sessions": revoked_sessions,
+            "revoked_api_keys": revoked_api_keys
+        })),
+    )
+    .await;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "message": "Password has been changed",
+        "revoked_sessions": revoked_sessions,
+        "revoked_api_keys": revoked_api_keys
+    })))
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn request_email_change(
+    http_req: HttpRequest,
+    db: web::Data<DatabaseConnection>,
+    config: web::Data<Config>,
+    mailer: web::Data<dyn Mailer>,
+    req: web::Json<ChangeEmailRequest>,
+) -> Result<HttpResponse> {
+    let db = db.get_ref();
+    let claims = get_user_from_request(&http_req)?;
+    let user = find_current_user(db, &claims.sub).await?;
+
+    if !password_matches(&req.current_password, &user).await? {
+        return Err(actix_web::error::ErrorUnauthorized("Current password is incorrect"));
+    }
+
+    if req.new_email == user.email {
+        return Err(actix_web::error::ErrorBadRequest("New email must differ from the current one"));
+    }
+
+    let taken = user::Entity::find()
+        .filter(user::Column::Email.eq(&req.new_email))
+        .one(db)
+        .await
+        .map_err(|e| {
+            log::error!(error:% = e; "Database error");
+            actix_web::error::ErrorInternalServerError("Database error")
+        })?;
+
+    if taken.is_some() {
+        return Ok(email_taken());
+    }
+
+    let token = generate_token();
+    let now = Utc::now();
+    let ttl_hours = config.tokens.email_change_hours;
+
+    email_change_token::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        user_id: Set(user.id),
+        new_email: Set(req.new_email.clone()),
+        token_hash: Set(hash_token(&token)),
+        expires_at: Set((now + Duration::hours(ttl_hours)).into()),
+        used_at: Set(None),
+        created_at: Set(now.into()),
+    }
+    .insert(db)
+    .await
+    .map_err(|e| {
+        log::error!(error:% = e; "Email change token creation error");
+        actix_web::error::ErrorInternalServerError("Email change token creation error")
+    })?;
+
+    let ip = client_ip(&http_req);
+    security_events::record(
+        db,
+        Some(user.id),
+        security_events::EMAIL_CHANGE_REQUESTED,
+        ip.as_deref(),
+        Some(json!({ "new_email": req.new_email })),
+    )
+    .await;
+
+    // The link goes to the new address so only its owner can complete the change
+    let app_url = &config.app_url;
+    let email = Email {
+        to: req.new_email.clone(),
+        subject: "Confirm your new email address".to_string(),
+        body: format!(
+            "Use the link below to confirm this address for your account. It expires in {ttl_hours} hours.\n\n{app_url}/confirm-email-change?token={token}\n"
+        ),
+    };
+    let mailer = mailer.into_inner();
+    logging::spawn(async move {
+        if let Err(e) = mailer.send(email).await {
+            log::error!(error:% = e; "Mail delivery error");
+        }
+    });
+
+    Ok(HttpResponse::Accepted().json(json!({
+        "message": "A confirmation link has been sent to the new email address"
+    })))
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn confirm_email_change(
+    http_req: HttpRequest,
+    db: web::Data<DatabaseConnection>,
+    mailer: web::Data<dyn Mailer>,
+    req: web::Json<ConfirmEmailChangeRequest>,
+) -> Result<HttpResponse> {
+    let db = db.get_ref();
+    let token_hash = hash_token(&req.token);
+
+    let txn = db.begin().await.map_err(|e| {
+        log::error!(error:% = e; "Database error");
+        actix_web::error::ErrorInternalServerError("Database error")
+    })?;
+
+    let now = Utc::now();
+    let change = email_change_token::Entity::find()
+        .filter(email_change_token::Column::TokenHash.eq(token_hash))
+        .filter(email_change_token::Column::UsedAt.is_null())
+        .filter(email_change_token::Column::ExpiresAt.gt(now))
+        .one(&txn)
+        .await
+        .map_err(|e| {
+            log::error!(error:% = e; "Database error");
+            actix_web::error::ErrorInternalServerError("Database error")
+        })?
+        .ok_or_else(|| actix_web::error::ErrorBadRequest("Invalid or expired email change token"))?;
+
+    // Consume this token and any other pending changes for the same user
+    email_change_token::Entity::update_many()
+        .col_expr(email_change_token::Column::UsedAt, Expr::value(now))
+        .filter(email_change_token::Column::UserId.eq(change.user_id))
+        .filter(email_change_token::Column::UsedAt.is_null())
+        .exec(&txn)
+        .await
+        .map_err(|e| {
+            log::error!(error:% = e; "Database error");
+            actix_web::error::ErrorInternalServerError("Database error")
+        })?;
+
+    let user = user::Entity::find_by_id(change.user_id)
+        .one(&txn)
+        .await
+        .map_err(|e| {
+            log::error!(error:% = e; "Database error");
+            actix_web::error::ErrorInternalServerError("Database error")
+        })?
+        .ok_or_else(|| actix_web::error::ErrorBadRequest("Invalid or expired email change token"))?;
+
+    // Someone may have registered the address since the change was requested;
+    // the unique constraint on users.email is the final arbiter
+    let updated = user::Entity::update_many()
+        .col_expr(user::Column::Email, Expr::value(change.new_email.clone()))
+        .col_expr(user::Column::EmailVerifiedAt, Expr::value(now))
+        .col_expr(user::Column::UpdatedAt, Expr::value(now))
+        .filter(user::Column::Id.eq(user.id))
+        .exec(&txn)
+        .await;
+    match updated {
+        Ok(_) => {}
+        Err(e) if is_unique_violation(&e) => return Ok(email_taken()),
+        Err(e) => {
+            log::error!(error:% = e; "Email update error");
+            return Err(actix_web::error::ErrorInternalServerError("Email update error"));
+        }
+    }
+
+    // The linked creator profile follows the account; creators.email is unique as well
+    let updated = creator::Entity::update_many()
+        .col_expr(creator::Column::Email, Expr::value(change.new_email.clone()))
+        .col_expr(creator::Column::UpdatedAt, Expr::value(now))
+        .filter(creator::Column::UserId.eq(user.id))
+        .exec(&txn)
+        .await;
+    match updated {
+        Ok(_) => {}
+        Err(e) if is_unique_violation(&e) => return Ok(email_taken()),
+        Err(e) => {
+            log::error!(error:% = e; "Creator update error");
+            return Err(actix_web::error::ErrorInternalServerError("Creator update error"));
+        }
+    }
+
+    // Existing tokens carry the old address. The link is opened without a session, so every
+    // session is signed out, as after a password reset.
+    let revoked_sessions = revoke_sessions(&txn, user.id, None).await.map_err(|e| {
+        log::error!(error:% = e; "Database error");
+        actix_web::error::ErrorInternalServerError("Database error")
+    })?;
+
+    txn.commit().await.map_err(|e| {
+        log::error!(error:% = e; "Database error");
+        actix_web::error::ErrorInternalServerError("Database error")
+    })?;
+
+    let ip = client_ip(&http_req);
+    security_events::record(
+        db,
+        Some(user.id),
+        security_events::EMAIL_CHANGED,
+        ip.as_deref(),
+        Some(json!({
+            "old_email": user.email,
+            "new_email": change.new_email,
+            "revoked_sessions": revoked_sessions
+        })),
+    )
+    .await;
+
+    // Let the previous owner of the address know, in case this wasn't them
+    let email = Email {
+        to: user.email.clone(),
+        subject: "Your email address was changed".to_string(),
+        body: format!(
+            "The email address on your account was changed to {}. If you did not make this change, contact support immediately.\n",
+            change.new_email
+        ),
+    };
+    let mailer = mailer.into_inner();
+    logging::spawn(async move {
+        if let Err(e) = mailer.send(email).await {
+            log::error!(error:% = e; "Mail delivery error");
+        }
+    });
+
+    Ok(HttpResponse::Ok().json(json!({
+        "message": "Email address has been changed",
+        "revoked_sessions": revoked_sessions
+    })))
+}
+
+async fn find_current_user(db: &DatabaseConnection, sub: &str) -> Result<user::Model, actix_web::Error> {
+    let user_id: Uuid = sub
+        .parse()
+        .map_err(|_| actix_web::error::ErrorUnauthorized("Invalid user ID"))?;
+
+    user::Entity::find_by_id(user_id)
+        .one(db)
+        .await
+        .map_err(|e| {
+            log::error!(error:% = e; "Database error");
+            actix_web::error::ErrorInternalServerError("Database error")
+        })?
+        .ok_or_else(|| actix_web::error::ErrorUnauthorized("User not found"))
+}
+
+async fn password_matches(password: &str, user: &user::Model) -> Result<bool, actix_web::Error> {
+    password::verify(password, &user.password_hash).await.map_err(|e| {
+        log::error!(error:% = e; "Password verification error");
+        actix_web::error::ErrorInternalServerError("Password verification error")
+    })
+}
+
+pub fn is_unique_violation(err: &DbErr) -> bool {
+    matches!(err.sql_err(), Some(SqlErr::UniqueConstraintViolation(_)))
+}
+
+pub fn email_taken() -> HttpResponse {
+    HttpResponse::Conflict().json(json!({
+        "error": "Email is already in use"
+    }))
+}
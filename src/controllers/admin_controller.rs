@@ -0,0 +1,245 @@
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{Duration, Utc};
+use opentelemetry::trace::{SpanId, Status};
+use sea_orm::sea_query::Expr;
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::client_ip::client_ip;
+use crate::config::Config;
+use crate::controllers::session_controller::start_session;
+use crate::dtos::auth_dto::{ImpersonationResponse, UserInfo};
+use crate::dtos::trace_dto::{SpanResponse, TraceQuery};
+use crate::jwt;
+use crate::login_throttle::reset_account;
+use crate::middleware::auth::{get_user_from_request, Actor, Claims};
+use crate::models::{session, user};
+use crate::password;
+use crate::security_events;
+use crate::telemetry;
+
+#[tracing::instrument(skip_all)]
+pub async fn unlock_user(
+    req: HttpRequest,
+    db: web::Data<DatabaseConnection>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse> {
+    let db = db.get_ref();
+    let admin = get_user_from_request(&req)?;
+    let user_id = path.into_inner();
+
+    let user = user::Entity::find_by_id(user_id)
+        .one(db)
+        .await
+        .map_err(|e| {
+            log::error!(error:% = e; "Database error");
+            actix_web::error::ErrorInternalServerError("Database error")
+        })?;
+
+    if user.is_none() {
+        return Ok(HttpResponse::NotFound().body("User not found"));
+    }
+
+    reset_account(db, user_id).await.map_err(|e| {
+        log::error!(error:% = e; "Database error");
+        actix_web::error::ErrorInternalServerError("Database error")
+    })?;
+
+    let ip = client_ip(&req);
+    security_events::record(
+        db,
+        Some(user_id),
+        security_events::ACCOUNT_UNLOCKED,
+        ip.as_deref(),
+        Some(json!({ "unlocked_by": admin.sub })),
+    )
+    .await;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "message": "Account unlocked"
+    })))
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn impersonate_user(
+    req: HttpRequest,
+    db: web::Data<DatabaseConnection>,
+    config: web::Data<Config>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse> {
+    let db = db.get_ref();
+    let admin = get_user_from_request(&req)?;
+    let user_id = path.into_inner();
+
+    let user = user::Entity::find_by_id(user_id)
+        .one(db)
+        .await
+        .map_err(|e| {
+            log::error!(error:% = e; "Database error");
+            actix_web::error::ErrorInternalServerError("Database error")
+        })?;
+
+    let Some(user) = user else {
+        return Ok(HttpResponse::NotFound().body("User not found"));
+    };
+
+    // Impersonating another admin would only be a way around their audit trail
+    if user.role == "admin" {
+        return Err(actix_web::error::ErrorForbidden("Admins cannot be impersonated"));
+    }
+
+    let admin_id: Uuid = admin
+        .sub
+        .parse()
+        .map_err(|_| actix_web::error::ErrorUnauthorized("Invalid user ID"))?;
+    let lifetime = Duration::minutes(config.tokens.impersonation_minutes);
+    let expires_at = Utc::now() + lifetime;
+
+    // The session shows up in the user's session list marked with the admin, and revoking
+    // it (there or through DELETE /api/admin/impersonations/{id}) ends the impersonation
+    let session = start_session(db, &req, user.id, lifetime, Some(admin_id)).await?;
+    let claims = Claims {
+        sub: user.id.to_string(),
+        email: user.email.clone(),
+        role: user.role.clone(),
+        exp: expires_at.timestamp() as usize,
+        mfa: admin.mfa,
+        scopes: None,
+        sid: Some(session.id.to_string()),
+        act: Some(Actor {
+            sub: admin.sub.clone(),
+            email: admin.email.clone(),
+        }),
+    };
+    let token = jwt::encode(&claims).map_err(|e| {
+        log::error!(error:% = e; "JWT encoding error");
+        actix_web::error::ErrorInternalServerError("JWT encoding error")
+    })?;
+
+    let ip = client_ip(&req);
+    security_events::record(
+        db,
+        Some(user.id),
+        security_events::IMPERSONATION_STARTED,
+        ip.as_deref(),
+        Some(json!({
+            "actor": admin.sub,
+            "actor_email": admin.email,
+            "session_id": session.id,
+            "expires_at": expires_at
+        })),
+    )
+    .await;
+
+    Ok(HttpResponse::Ok().json(ImpersonationResponse {
+        token,
+        session_id: session.id,
+        expires_at,
+        user: UserInfo {
+            id: user.id,
+            email: user.email,
+            role: user.role,
+        },
+    }))
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn end_impersonation(
+    req: HttpRequest,
+    db: web::Data<DatabaseConnection>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse> {
+    let db = db.get_ref();
+    let admin = get_user_from_request(&req)?;
+    let session_id = path.into_inner();
+
+    let revoked = session::Entity::update_many()
+        .col_expr(session::Column::RevokedAt, Expr::value(Utc::now()))
+        .filter(session::Column::Id.eq(session_id))
+        .filter(session::Column::ImpersonatorId.is_not_null())
+        .filter(session::Column::RevokedAt.is_null())
+        .exec_with_returning(db)
+        .await
+        .map_err(|e| {
+            log::error!(error:% = e; "Database error");
+            actix_web::error::ErrorInternalServerError("Database error")
+        })?;
+
+    let Some(session) = revoked.into_iter().next() else {
+        return Ok(HttpResponse::NotFound().body("Impersonation session not found"));
+    };
+
+    let ip = client_ip(&req);
+    security_events::record(
+        db,
+        Some(session.user_id),
+        security_events::IMPERSONATION_ENDED,
+        ip.as_deref(),
+        Some(json!({
+            "session_id": session.id,
+            "actor": session.impersonator_id,
+            "ended_by": admin.sub
+        })),
+    )
+    .await;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// Queue and run times of the password hashing pool, to size PASSWORD_HASH_CONCURRENCY
+#[tracing::instrument(skip_all)]
+pub async fn password_hashing_stats() -> HttpResponse {
+    HttpResponse::Ok().json(password::stats())
+}
+
+// Spans held by the in-memory exporter (OTEL_TRACES_EXPORTER=memory), oldest first
+#[tracing::instrument(skip_all)]
+pub async fn list_traces(query: web::Query<TraceQuery>) -> Result<HttpResponse> {
+    let spans = telemetry::finished_spans()
+        .ok_or_else(|| actix_web::error::ErrorNotFound("In-memory trace exporter is not enabled"))?;
+
+    let spans: Vec<SpanResponse> = spans
+        .into_iter()
+        .filter(|span| {
+            query
+                .trace_id
+                .as_deref()
+                .is_none_or(|trace_id| span.span_context.trace_id().to_string() == trace_id)
+        })
+        .map(|span| SpanResponse {
+            trace_id: span.span_context.trace_id().to_string(),
+            span_id: span.span_context.span_id().to_string(),
+            parent_span_id: (span.parent_span_id != SpanId::INVALID).then(|| span.parent_span_id.to_string()),
+            name: span.name.to_string(),
+            kind: format!("{:?}", span.span_kind).to_lowercase(),
+            start_time: span.start_time.into(),
+            duration_ms: span
+                .end_time
+                .duration_since(span.start_time)
+                .unwrap_or_default()
+                .as_secs_f64()
+                * 1000.0,
+            status: match span.status {
+                Status::Unset => "unset".to_string(),
+                Status::Ok => "ok".to_string(),
+                Status::Error { description } => format!("error: {description}"),
+            },
+            attributes: span
+                .attributes
+                .iter()
+                .map(|kv| (kv.key.to_string(), kv.value.to_string()))
+                .collect(),
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(spans))
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn clear_traces() -> Result<HttpResponse> {
+    telemetry::finished_spans()
+        .ok_or_else(|| actix_web::error::ErrorNotFound("In-memory trace exporter is not enabled"))?;
+    telemetry::reset_finished_spans();
+    Ok(HttpResponse::NoContent().finish())
+}
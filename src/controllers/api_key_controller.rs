@@ -0,0 +1,130 @@
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::Utc;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr, EntityTrait,
+    QueryFilter, QueryOrder, Set,
+};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::dtos::api_key_dto::{ApiKeyResponse, CreateApiKeyRequest, CreatedApiKeyResponse};
+use crate::middleware::auth::{get_user_from_request, API_KEY_PREFIX, API_KEY_SCOPES};
+use crate::models::api_key;
+use crate::tokens::{generate_token, hash_token};
+
+#[tracing::instrument(skip_all)]
+pub async fn create_api_key(
+    req: HttpRequest,
+    db: web::Data<DatabaseConnection>,
+    config: web::Data<Config>,
+    json: web::Json<CreateApiKeyRequest>,
+) -> Result<HttpResponse> {
+    let user_id = current_user_id(&req)?;
+
+    // Keys skip the per-request 2FA check, so roles that need 2FA must pass it to create one
+    let claims = get_user_from_request(&req)?;
+    if config.mfa.required_for_role(&claims.role) && !claims.mfa {
+        return Err(actix_web::error::ErrorForbidden("Two-factor authentication required"));
+    }
+
+    if json.scopes.is_empty() || json.scopes.iter().any(|scope| !API_KEY_SCOPES.contains(&scope.as_str())) {
+        return Ok(HttpResponse::BadRequest().json(json!({
+            "error": format!("Scopes must be a non-empty subset of: {}", API_KEY_SCOPES.join(", "))
+        })));
+    }
+
+    let now = Utc::now();
+    if json.expires_at.is_some_and(|expires_at| expires_at <= now) {
+        return Ok(HttpResponse::BadRequest().json(json!({
+            "error": "expires_at must be in the future"
+        })));
+    }
+
+    let key = format!("{API_KEY_PREFIX}{}", generate_token());
+
+    let api_key = api_key::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        user_id: Set(user_id),
+        name: Set(json.name.clone()),
+        prefix: Set(key[..API_KEY_PREFIX.len() + 8].to_string()),
+        key_hash: Set(hash_token(&key)),
+        scopes: Set(json.scopes.join(" ")),
+        expires_at: Set(json.expires_at),
+        last_used_at: Set(None),
+        created_at: Set(now.into()),
+    }
+    .insert(db.get_ref())
+    .await
+    .map_err(|e| {
+        log::error!(error:% = e; "API key creation error");
+        actix_web::error::ErrorInternalServerError("API key creation error")
+    })?;
+
+    Ok(HttpResponse::Created().json(CreatedApiKeyResponse {
+        api_key: api_key.into(),
+        key,
+    }))
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn list_api_keys(
+    req: HttpRequest,
+    db: web::Data<DatabaseConnection>,
+) -> Result<HttpResponse> {
+    let user_id = current_user_id(&req)?;
+
+    let api_keys = api_key::Entity::find()
+        .filter(api_key::Column::UserId.eq(user_id))
+        .order_by_desc(api_key::Column::CreatedAt)
+        .all(db.get_ref())
+        .await
+        .map_err(|e| {
+            log::error!(error:% = e; "Database error");
+            actix_web::error::ErrorInternalServerError("Database error")
+        })?;
+
+    let response: Vec<ApiKeyResponse> = api_keys.into_iter().map(Into::into).collect();
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn delete_api_key(
+    req: HttpRequest,
+    db: web::Data<DatabaseConnection>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse> {
+    let user_id = current_user_id(&req)?;
+
+    let deleted = api_key::Entity::delete_many()
+        .filter(api_key::Column::Id.eq(path.into_inner()))
+        .filter(api_key::Column::UserId.eq(user_id))
+        .exec(db.get_ref())
+        .await
+        .map_err(|e| {
+            log::error!(error:% = e; "Database error");
+            actix_web::error::ErrorInternalServerError("Database error")
+        })?;
+
+    if deleted.rows_affected == 0 {
+        return Ok(HttpResponse::NotFound().body("API key not found"));
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// Deletes every API key of `user_id`, e.g. after its password was reset; returns how many
+pub async fn revoke_api_keys<C: ConnectionTrait>(db: &C, user_id: Uuid) -> Result<u64, DbErr> {
+    let deleted = api_key::Entity::delete_many()
+        .filter(api_key::Column::UserId.eq(user_id))
+        .exec(db)
+        .await?;
+    Ok(deleted.rows_affected)
+}
+
+fn current_user_id(req: &HttpRequest) -> Result<Uuid, actix_web::Error> {
+    get_user_from_request(req)?
+        .sub
+        .parse()
+        .map_err(|_| actix_web::error::ErrorUnauthorized("Invalid user ID"))
+}
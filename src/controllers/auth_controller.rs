@@ -1,17 +1,43 @@
 use actix_web::{web, HttpRequest, HttpResponse, Result};
-use bcrypt::{hash, verify, DEFAULT_COST};
 use chrono::{Duration, Utc};
-use jsonwebtoken::{encode, EncodingKey, Header};
-use sea_orm::{DatabaseConnection, EntityTrait, QueryFilter, ColumnTrait, ActiveModelTrait, Set};
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    DatabaseConnection, EntityTrait, QueryFilter, ColumnTrait, ActiveModelTrait, ConnectionTrait, Set,
+    TransactionTrait,
+};
 use serde_json::json;
 use uuid::Uuid;
 
-use crate::dtos::auth_dto::{LoginRequest, RegisterRequest, AuthResponse, UserInfo, CurrentUserResponse};
+use crate::client_ip::client_ip;
+use crate::config::Config;
+use crate::dtos::auth_dto::{
+    LoginRequest, RegisterRequest, AuthResponse, UserInfo, CurrentUserResponse,
+    ForgotPasswordRequest, ResetPasswordRequest,
+};
+use crate::dtos::two_factor_dto::MfaChallengeResponse;
+use crate::controllers::email_verification_controller::send_verification_email;
+use crate::controllers::api_key_controller::revoke_api_keys;
+use crate::controllers::session_controller::{revoke_sessions, start_session};
+use crate::controllers::two_factor_controller::generate_mfa_token;
+use crate::jwt;
+use crate::logging;
+use crate::login_throttle::{
+    account_retry_after, handle_failure, reset_account, too_many_attempts, LoginThrottle,
+};
+use crate::mailer::{Email, Mailer};
+use crate::metrics::{self, Metrics};
 use crate::middleware::auth::Claims;
-use crate::models::user;
+use crate::models::{password_reset_token, session, user};
+use crate::password::{self, PendingRehashes};
+use crate::security_events;
+use crate::tokens::{generate_token, hash_token};
 
+#[tracing::instrument(skip_all)]
 pub async fn register(
+    http_req: HttpRequest,
     db: web::Data<DatabaseConnection>,
+    config: web::Data<Config>,
+    mailer: web::Data<dyn Mailer>,
     req: web::Json<RegisterRequest>,
 ) -> Result<HttpResponse> {
     let db = db.get_ref();
@@ -22,7 +48,7 @@ pub async fn register(
         .one(db)
         .await
         .map_err(|e| {
-            eprintln!("Database error: {}", e);
+            log::error!(error:% = e; "Database error");
             actix_web::error::ErrorInternalServerError("Database error")
         })?;
 
@@ -33,9 +59,10 @@ pub async fn register(
     }
 
     // Hash password
-    let password_hash = hash(&req.password, DEFAULT_COST)
+    let password_hash = password::hash(&req.password)
+        .await
         .map_err(|e| {
-            eprintln!("Password hashing error: {}", e);
+            log::error!(error:% = e; "Password hashing error");
             actix_web::error::ErrorInternalServerError("Password hashing error")
         })?;
 
@@ -48,6 +75,12 @@ pub async fn register(
         email: Set(req.email.clone()),
         password_hash: Set(password_hash),
         role: Set(req.role.clone()),
+        email_verified_at: Set(None),
+        totp_secret: Set(None),
+        totp_enabled_at: Set(None),
+        totp_last_step: Set(None),
+        failed_login_attempts: Set(0),
+        locked_until: Set(None),
         created_at: Set(now.into()),
         updated_at: Set(now.into()),
     };
@@ -56,12 +89,14 @@ pub async fn register(
         .insert(db)
         .await
         .map_err(|e| {
-            eprintln!("User creation error: {}", e);
+            log::error!(error:% = e; "User creation error");
             actix_web::error::ErrorInternalServerError("User creation error")
         })?;
 
+    send_verification_email(db, &config, mailer.into_inner(), user.id, &user.email).await?;
+
     // Generate JWT token
-    let token = generate_jwt(&user)?;
+    let token = issue_token(db, &config, &http_req, &user, false).await?;
 
     let response = AuthResponse {
         token,
@@ -75,11 +110,24 @@ pub async fn register(
     Ok(HttpResponse::Created().json(response))
 }
 
+#[tracing::instrument(skip_all)]
 pub async fn login(
+    http_req: HttpRequest,
     db: web::Data<DatabaseConnection>,
+    config: web::Data<Config>,
+    throttle: web::Data<LoginThrottle>,
+    metrics: web::Data<Metrics>,
+    pending_rehashes: web::Data<PendingRehashes>,
     req: web::Json<LoginRequest>,
 ) -> Result<HttpResponse> {
     let db = db.get_ref();
+    let ip = client_ip(&http_req);
+    let ip = ip.as_deref();
+
+    if let Some(retry_after) = ip.and_then(|ip| throttle.ip_retry_after(ip)) {
+        metrics.record_login(metrics::LOGIN_LOCKED);
+        return Ok(too_many_attempts(retry_after));
+    }
 
     // Find user by email
     let user = user::Entity::find()
@@ -87,26 +135,60 @@ pub async fn login(
         .one(db)
         .await
         .map_err(|e| {
-            eprintln!("Database error: {}", e);
+            log::error!(error:% = e; "Database error");
             actix_web::error::ErrorInternalServerError("Database error")
-        })?
-        .ok_or_else(|| {
-            actix_web::error::ErrorUnauthorized("Invalid email or password")
         })?;
 
+    let Some(user) = user else {
+        metrics.record_login(metrics::LOGIN_FAILURE);
+        return handle_failure(db, &throttle, None, ip, "Invalid email or password").await;
+    };
+
+    // Locked accounts are rejected before the password is even checked
+    if let Some(retry_after) = account_retry_after(&user) {
+        metrics.record_login(metrics::LOGIN_LOCKED);
+        return Ok(too_many_attempts(retry_after));
+    }
+
     // Verify password
-    let is_valid = verify(&req.password, &user.password_hash)
+    let is_valid = password::verify(&req.password, &user.password_hash)
+        .await
         .map_err(|e| {
-            eprintln!("Password verification error: {}", e);
+            log::error!(error:% = e; "Password verification error");
             actix_web::error::ErrorInternalServerError("Password verification error")
         })?;
 
     if !is_valid {
-        return Err(actix_web::error::ErrorUnauthorized("Invalid email or password"));
+        metrics.record_login(metrics::LOGIN_FAILURE);
+        return handle_failure(db, &throttle, Some(&user), ip, "Invalid email or password").await;
+    }
+
+    // Upgrade hashes from an older algorithm or cost while we have the plaintext
+    let rehash = match password::needs_rehash(&user.password_hash) {
+        true => upgraded_hash(&req.password).await,
+        false => None,
+    };
+
+    // Second step required: hand out a short-lived challenge instead of a session token.
+    // Nothing below counts as a successful login until the code is verified.
+    if user.totp_enabled_at.is_some() {
+        if let Some(password_hash) = rehash {
+            pending_rehashes.insert(user.id, password_hash);
+        }
+        let mfa_token = generate_mfa_token(&config, &user)?;
+        return Ok(HttpResponse::Ok().json(MfaChallengeResponse {
+            mfa_required: true,
+            mfa_token,
+        }));
+    }
+
+    if let Some(password_hash) = rehash {
+        store_rehash(db, user.id, password_hash).await;
     }
+    complete_login(db, &user, ip, &metrics, "password").await?;
 
     // Generate JWT token
-    let token = generate_jwt(&user)?;
+    let token = issue_token(db, &config, &http_req, &user, false).await?;
 
     let response = AuthResponse {
         token,
@@ -120,6 +202,7 @@ pub async fn login(
     Ok(HttpResponse::Ok().json(response))
 }
 
+#[tracing::instrument(skip_all)]
 pub async fn current_user(
     req: HttpRequest,
     db: web::Data<DatabaseConnection>,
@@ -133,32 +216,261 @@ pub async fn current_user(
         id: user.id,
         email: user.email,
         role: user.role,
+        email_verified: user.email_verified_at.is_some(),
     };
 
     Ok(HttpResponse::Ok().json(response))
 }
 
-fn generate_jwt(user: &user::Model) -> Result<String, actix_web::Error> {
-    let secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-    let expiration = Utc::now()
-        .checked_add_signed(Duration::hours(24))
-        .expect("valid timestamp")
-        .timestamp() as usize;
+#[tracing::instrument(skip_all)]
+pub async fn forgot_password(
+    db: web::Data<DatabaseConnection>,
+    config: web::Data<Config>,
+    mailer: web::Data<dyn Mailer>,
+    req: web::Json<ForgotPasswordRequest>,
+) -> Result<HttpResponse> {
+    let db = db.get_ref();
+
+    // Same response whether or not the email exists
+    let response = HttpResponse::Accepted().json(json!({
+        "message": "If an account with that email exists, a password reset link has been sent"
+    }));
 
+    let user = user::Entity::find()
+        .filter(user::Column::Email.eq(&req.email))
+        .one(db)
+        .await
+        .map_err(|e| {
+            log::error!(error:% = e; "Database error");
+            actix_web::error::ErrorInternalServerError("Database error")
+        })?;
+
+    let Some(user) = user else {
+        return Ok(response);
+    };
+
+    let token = generate_token();
+    let now = Utc::now();
+    let ttl_minutes = config.tokens.password_reset_minutes;
+
+    password_reset_token::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        user_id: Set(user.id),
+        token_hash: Set(hash_token(&token)),
+        expires_at: Set((now + Duration::minutes(ttl_minutes)).into()),
+        used_at: Set(None),
+        created_at: Set(now.into()),
+    }
+    .insert(db)
+    .await
+    .map_err(|e| {
+        log::error!(error:% = e; "Password reset token creation error");
+        actix_web::error::ErrorInternalServerError("Password reset token creation error")
+    })?;
+
+    let app_url = &config.app_url;
+    let email = Email {
+        to: user.email,
+        subject: "Reset your password".to_string(),
+        body: format!(
+            "Use the link below to reset your password. It expires in {ttl_minutes} minutes.\n\n{app_url}/reset-password?token={token}\n"
+        ),
+    };
+
+    // Deliver in the background so response timing doesn't reveal whether the email exists
+    let mailer = mailer.into_inner();
+    logging::spawn(async move {
+        if let Err(e) = mailer.send(email).await {
+            log::error!(error:% = e; "Mail delivery error");
+        }
+    });
+
+    Ok(response)
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn reset_password(
+    http_req: HttpRequest,
+    db: web::Data<DatabaseConnection>,
+    req: web::Json<ResetPasswordRequest>,
+) -> Result<HttpResponse> {
+    let db = db.get_ref();
+    let token_hash = hash_token(&req.token);
+
+    let password_hash = password::hash(&req.new_password)
+        .await
+        .map_err(|e| {
+            log::error!(error:% = e; "Password hashing error");
+            actix_web::error::ErrorInternalServerError("Password hashing error")
+        })?;
+
+    let txn = db.begin().await.map_err(|e| {
+        log::error!(error:% = e; "Database error");
+        actix_web::error::ErrorInternalServerError("Database error")
+    })?;
+
+    let now = Utc::now();
+    let reset_token = password_reset_token::Entity::find()
+        .filter(password_reset_token::Column::TokenHash.eq(token_hash))
+        .filter(password_reset_token::Column::UsedAt.is_null())
+        .filter(password_reset_token::Column::ExpiresAt.gt(now))
+        .one(&txn)
+        .await
+        .map_err(|e| {
+            log::error!(error:% = e; "Database error");
+            actix_web::error::ErrorInternalServerError("Database error")
+        })?
+        .ok_or_else(|| actix_web::error::ErrorBadRequest("Invalid or expired reset token"))?;
+
+    // Consume this token and any other outstanding ones for the same user
+    let consumed = password_reset_token::Entity::update_many()
+        .col_expr(password_reset_token::Column::UsedAt, Expr::value(now))
+        .filter(password_reset_token::Column::UserId.eq(reset_token.user_id))
+        .filter(password_reset_token::Column::UsedAt.is_null())
+        .exec(&txn)
+        .await
+        .map_err(|e| {
+            log::error!(error:% = e; "Database error");
+            actix_web::error::ErrorInternalServerError("Database error")
+        })?;
+
+    if consumed.rows_affected == 0 {
+        return Err(actix_web::error::ErrorBadRequest("Invalid or expired reset token"));
+    }
+
+    user::Entity::update_many()
+        .col_expr(user::Column::PasswordHash, Expr::value(password_hash))
+        .col_expr(user::Column::UpdatedAt, Expr::value(now))
+        .filter(user::Column::Id.eq(reset_token.user_id))
+        .exec(&txn)
+        .await
+        .map_err(|e| {
+            log::error!(error:% = e; "Password update error");
+            actix_web::error::ErrorInternalServerError("Password update error")
+        })?;
+
+    // Whoever knew the old password (or held a key minted with it) is locked out, and the
+    // real owner is no longer stuck behind a lockout the attacker caused
+    let revoked_sessions = revoke_sessions(&txn, reset_token.user_id, None).await.map_err(|e| {
+        log::error!(error:% = e; "Database error");
+        actix_web::error::ErrorInternalServerError("Database error")
+    })?;
+    let revoked_api_keys = revoke_api_keys(&txn, reset_token.user_id).await.map_err(|e| {
+        log::error!(error:% = e; "Database error");
+        actix_web::error::ErrorInternalServerError("Database error")
+    })?;
+    reset_account(&txn, reset_token.user_id).await.map_err(|e| {
+        log::error!(error:% = e; "Database error");
+        actix_web::error::ErrorInternalServerError("Database error")
+    })?;
+
+    let ip = client_ip(&http_req);
+    security_events::record(
+        &txn,
+        Some(reset_token.user_id),
+        security_events::PASSWORD_RESET,
+        ip.as_deref(),
+        Some(json!({
+            "revoked_sessions": revoked_sessions,
+            "revoked_api_keys": revoked_api_keys
+        })),
+    )
+    .await;
+
+    txn.commit().await.map_err(|e| {
+        log::error!(error:% = e; "Database error");
+        actix_web::error::ErrorInternalServerError("Database error")
+    })?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "message": "Password has been reset"
+    })))
+}
+
+// Bookkeeping for a login that passed every factor: clears failed attempts, records the
+// event and the metric. Runs after the second factor for 2FA users, so a password alone
+// neither resets the lockout counter nor shows up as a successful login.
+pub async fn complete_login(
+    db: &DatabaseConnection,
+    user: &user::Model,
+    ip: Option<&str>,
+    metrics: &Metrics,
+    method: &str,
+) -> Result<(), actix_web::Error> {
+    if user.failed_login_attempts > 0 || user.locked_until.is_some() {
+        reset_account(db, user.id).await.map_err(|e| {
+            log::error!(error:% = e; "Database error");
+            actix_web::error::ErrorInternalServerError("Database error")
+        })?;
+    }
+    security_events::record(
+        db,
+        Some(user.id),
+        security_events::LOGIN_SUCCEEDED,
+        ip,
+        Some(json!({ "method": method })),
+    )
+    .await;
+    metrics.record_login(metrics::LOGIN_SUCCESS);
+    Ok(())
+}
+
+// Best effort: a failed upgrade leaves the old (still valid) hash in place
+async fn upgraded_hash(plaintext: &str) -> Option<String> {
+    password::hash(plaintext)
+        .await
+        .map_err(|e| log::warn!(error:% = e; "Password rehash error"))
+        .ok()
+}
+
+pub async fn store_rehash(db: &DatabaseConnection, user_id: Uuid, password_hash: String) {
+    let updated = user::Entity::update_many()
+        .col_expr(user::Column::PasswordHash, Expr::value(password_hash))
+        .filter(user::Column::Id.eq(user_id))
+        .exec(db)
+        .await;
+    if let Err(e) = updated {
+        log::warn!(error:% = e; "Password rehash error");
+    }
+}
+
+// Public keys for verifying our tokens (empty when signing with a shared HS256 secret)
+#[tracing::instrument(skip_all)]
+pub async fn jwks() -> HttpResponse {
+    HttpResponse::Ok().json(jwt::jwks())
+}
+
+// Starts a new session and returns a JWT bound to it
+#[tracing::instrument(skip_all)]
+pub async fn issue_token<C: ConnectionTrait>(
+    db: &C,
+    config: &Config,
+    req: &HttpRequest,
+    user: &user::Model,
+    mfa: bool,
+) -> Result<String, actix_web::Error> {
+    let session = start_session(db, req, user.id, Duration::hours(config.tokens.session_hours), None).await?;
+    generate_jwt(user, mfa, &session)
+}
+
+pub fn generate_jwt(
+    user: &user::Model,
+    mfa: bool,
+    session: &session::Model,
+) -> Result<String, actix_web::Error> {
     let claims = Claims {
         sub: user.id.to_string(),
         email: user.email.clone(),
         role: user.role.clone(),
-        exp: expiration,
+        exp: session.expires_at.timestamp() as usize,
+        mfa,
+        scopes: None,
+        sid: Some(session.id.to_string()),
+        act: None,
     };
 
-    encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(secret.as_ref()),
-    )
-    .map_err(|e| {
-        eprintln!("JWT encoding error: {}", e);
+    jwt::encode(&claims).map_err(|e| {
+        log::error!(error:% = e; "JWT encoding error");
         actix_web::error::ErrorInternalServerError("JWT encoding error")
     })
-} 
\ No newline at end of file
+}
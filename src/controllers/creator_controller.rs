@@ -1,14 +1,18 @@
-use actix_web::{web, HttpResponse, Responder};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
 use chrono::Utc;
-use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set};
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, Set};
 use uuid::Uuid;
 
+use crate::controllers::account_controller::{email_taken, is_unique_violation};
+use crate::etag;
+use crate::patch;
 use crate::models::creator;
 use crate::models::creator::Entity as CreatorEntity;
 use crate::models::game::Entity as GameEntity;
 
 use crate::dtos::{CreateCreator, UpdateCreator};
 
+#[tracing::instrument(skip_all)]
 pub async fn create_creator(
     db: web::Data<DatabaseConnection>,
     json: web::Json<CreateCreator>,
@@ -18,16 +22,19 @@ pub async fn create_creator(
         first_name: Set(json.first_name.clone()),
         last_name: Set(json.last_name.clone()),
         email: Set(json.email.clone()),
+        user_id: Set(None),
         created_at: Set(Utc::now()),
         updated_at: Set(Utc::now()),
     };
 
     match new_creator.insert(db.get_ref()).await {
-        Ok(creator) => HttpResponse::Created().json(creator),
+        Ok(creator) => etag::created(format!("/api/creators/{}", creator.id), &creator),
+        Err(err) if is_unique_violation(&err) => email_taken(),
         Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
     }
 }
 
+#[tracing::instrument(skip_all)]
 pub async fn get_all_creators(db: web::Data<DatabaseConnection>) -> impl Responder {
     match CreatorEntity::find().all(db.get_ref()).await {
         Ok(creators) => HttpResponse::Ok().json(creators),
@@ -35,7 +42,9 @@ pub async fn get_all_creators(db: web::Data<DatabaseConnection>) -> impl Respond
     }
 }
 
+#[tracing::instrument(skip_all)]
 pub async fn get_creator_by_id(
+    req: HttpRequest,
     db: web::Data<DatabaseConnection>,
     path: web::Path<Uuid>,
 ) -> impl Responder {
@@ -43,53 +52,108 @@ pub async fn get_creator_by_id(
         .one(db.get_ref())
         .await
     {
-        Ok(Some(creator)) => HttpResponse::Ok().json(creator),
+        Ok(Some(creator)) => {
+            let etag = etag::entity_tag(&creator);
+            if etag::is_fresh(&req, &etag) {
+                return etag::not_modified(etag);
+            }
+            etag::ok(&creator)
+        }
         Ok(None) => HttpResponse::NotFound().body("Creator not found"),
         Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
     }
 }
 
+// PUT: every editable field is replaced
+#[tracing::instrument(skip_all)]
 pub async fn update_creator(
+    req: HttpRequest,
     db: web::Data<DatabaseConnection>,
     path: web::Path<Uuid>,
     json: web::Json<UpdateCreator>,
+) -> impl Responder {
+    let id = path.into_inner();
+    match CreatorEntity::find_by_id(id).one(db.get_ref()).await {
+        Ok(Some(model)) => replace_creator(&req, db.get_ref(), model, json.into_inner()).await,
+        Ok(None) => HttpResponse::NotFound().body("Creator not found"),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// PATCH: merge patch or JSON patch applied to the current creator
+#[tracing::instrument(skip_all)]
+pub async fn patch_creator(
+    req: HttpRequest,
+    db: web::Data<DatabaseConnection>,
+    path: web::Path<Uuid>,
+    body: web::Bytes,
 ) -> impl Responder {
     let id = path.into_inner();
     match CreatorEntity::find_by_id(id).one(db.get_ref()).await {
         Ok(Some(model)) => {
-            let mut active_model: creator::ActiveModel = model.into();
+            let read_only = ["id", "user_id", "created_at", "updated_at"];
+            let fields = match patch::apply(&req, &model, &body, &read_only) {
+                Ok(fields) => fields,
+                Err(err) => return err.response(),
+            };
+            replace_creator(&req, db.get_ref(), model, fields).await
+        }
+        Ok(None) => HttpResponse::NotFound().body("Creator not found"),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
 
-            if let Some(first_name) = &json.first_name {
-                active_model.first_name = Set(first_name.clone());
-            }
-            if let Some(last_name) = &json.last_name {
-                active_model.last_name = Set(last_name.clone());
-            }
-            if let Some(email) = &json.email {
-                active_model.email = Set(email.clone());
-            }
+async fn replace_creator(
+    req: &HttpRequest,
+    db: &DatabaseConnection,
+    model: creator::Model,
+    fields: UpdateCreator,
+) -> HttpResponse {
+    if !etag::precondition_holds(req, &etag::entity_tag(&model)) {
+        return etag::precondition_failed();
+    }
+    if let Err(message) = fields.validate() {
+        return HttpResponse::UnprocessableEntity().body(message);
+    }
 
-            active_model.updated_at = Set(Utc::now());
+    let version = model.updated_at;
+    let mut active_model: creator::ActiveModel = model.into();
+    active_model.first_name = Set(fields.first_name);
+    active_model.last_name = Set(fields.last_name);
+    active_model.email = Set(fields.email);
+    active_model.updated_at = Set(Utc::now());
 
-            match active_model.update(db.get_ref()).await {
-                Ok(updated) => HttpResponse::Ok().json(updated),
-                Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
-            }
-        }
-        Ok(None) => HttpResponse::NotFound().body("Creator not found"),
+    // With If-Match, a concurrent write since our read must not be overwritten
+    let mut update = CreatorEntity::update(active_model);
+    if etag::has_precondition(req) {
+        update = update.filter(creator::Column::UpdatedAt.eq(version));
+    }
+    match update.exec(db).await {
+        Ok(updated) => etag::ok(&updated),
+        Err(DbErr::RecordNotUpdated) => etag::precondition_failed(),
+        Err(err) if is_unique_violation(&err) => email_taken(),
         Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
     }
 }
 
+#[tracing::instrument(skip_all)]
 pub async fn delete_creator(
+    req: HttpRequest,
     db: web::Data<DatabaseConnection>,
     path: web::Path<Uuid>,
 ) -> impl Responder {
     let id = path.into_inner();
     match CreatorEntity::find_by_id(id).one(db.get_ref()).await {
         Ok(Some(model)) => {
-            let active_model: creator::ActiveModel = model.into();
-            match active_model.delete(db.get_ref()).await {
+            if !etag::precondition_holds(&req, &etag::entity_tag(&model)) {
+                return etag::precondition_failed();
+            }
+            let mut delete = CreatorEntity::delete_by_id(id);
+            if etag::has_precondition(&req) {
+                delete = delete.filter(creator::Column::UpdatedAt.eq(model.updated_at));
+            }
+            match delete.exec(db.get_ref()).await {
+                Ok(result) if result.rows_affected == 0 => etag::precondition_failed(),
                 Ok(_) => HttpResponse::NoContent().finish(),
                 Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
             }
@@ -99,6 +163,7 @@ pub async fn delete_creator(
     }
 }
 
+#[tracing::instrument(skip_all)]
 pub async fn get_games_by_creator(
     db: web::Data<DatabaseConnection>,
     path: web::Path<Uuid>,
@@ -0,0 +1,158 @@
+use actix_web::{web, HttpResponse, Result};
+use chrono::{Duration, Utc};
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, EntityTrait, QueryFilter,
+    Set, TransactionTrait,
+};
+use serde_json::json;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::dtos::auth_dto::{ResendVerificationRequest, VerifyEmailRequest};
+use crate::logging;
+use crate::mailer::{Email, Mailer};
+use crate::models::{email_verification_token, user};
+use crate::tokens::{generate_token, hash_token};
+
+#[tracing::instrument(skip_all)]
+pub async fn verify_email(
+    db: web::Data<DatabaseConnection>,
+    req: web::Json<VerifyEmailRequest>,
+) -> Result<HttpResponse> {
+    let db = db.get_ref();
+    let token_hash = hash_token(&req.token);
+
+    let txn = db.begin().await.map_err(|e| {
+        log::error!(error:% = e; "Database error");
+        actix_web::error::ErrorInternalServerError("Database error")
+    })?;
+
+    let now = Utc::now();
+    let verification = email_verification_token::Entity::find()
+        .filter(email_verification_token::Column::TokenHash.eq(token_hash))
+        .filter(email_verification_token::Column::UsedAt.is_null())
+        .filter(email_verification_token::Column::ExpiresAt.gt(now))
+        .one(&txn)
+        .await
+        .map_err(|e| {
+            log::error!(error:% = e; "Database error");
+            actix_web::error::ErrorInternalServerError("Database error")
+        })?
+        .ok_or_else(|| actix_web::error::ErrorBadRequest("Invalid or expired verification token"))?;
+
+    email_verification_token::Entity::update_many()
+        .col_expr(email_verification_token::Column::UsedAt, Expr::value(now))
+        .filter(email_verification_token::Column::Id.eq(verification.id))
+        .exec(&txn)
+        .await
+        .map_err(|e| {
+            log::error!(error:% = e; "Database error");
+            actix_web::error::ErrorInternalServerError("Database error")
+        })?;
+
+    // Only verify the address the token was issued for
+    let verified = user::Entity::update_many()
+        .col_expr(user::Column::EmailVerifiedAt, Expr::value(now))
+        .col_expr(user::Column::UpdatedAt, Expr::value(now))
+        .filter(user::Column::Id.eq(verification.user_id))
+        .filter(user::Column::Email.eq(verification.email))
+        .exec(&txn)
+        .await
+        .map_err(|e| {
+            log::error!(error:% = e; "Database error");
+            actix_web::error::ErrorInternalServerError("Database error")
+        })?;
+
+    if verified.rows_affected == 0 {
+        return Err(actix_web::error::ErrorBadRequest("Invalid or expired verification token"));
+    }
+
+    txn.commit().await.map_err(|e| {
+        log::error!(error:% = e; "Database error");
+        actix_web::error::ErrorInternalServerError("Database error")
+    })?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "message": "Email address has been verified"
+    })))
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn resend_verification(
+    db: web::Data<DatabaseConnection>,
+    config: web::Data<Config>,
+    mailer: web::Data<dyn Mailer>,
+    req: web::Json<ResendVerificationRequest>,
+) -> Result<HttpResponse> {
+    let db = db.get_ref();
+
+    // Same response whether or not the email exists or is already verified
+    let response = HttpResponse::Accepted().json(json!({
+        "message": "If an unverified account with that email exists, a verification link has been sent"
+    }));
+
+    let user = user::Entity::find()
+        .filter(user::Column::Email.eq(&req.email))
+        .filter(user::Column::EmailVerifiedAt.is_null())
+        .one(db)
+        .await
+        .map_err(|e| {
+            log::error!(error:% = e; "Database error");
+            actix_web::error::ErrorInternalServerError("Database error")
+        })?;
+
+    if let Some(user) = user {
+        send_verification_email(db, &config, mailer.into_inner(), user.id, &user.email).await?;
+    }
+
+    Ok(response)
+}
+
+// Issues a verification token for `email` and mails the link in the background
+#[tracing::instrument(skip_all)]
+pub async fn send_verification_email<C: ConnectionTrait>(
+    db: &C,
+    config: &Config,
+    mailer: Arc<dyn Mailer>,
+    user_id: Uuid,
+    email: &str,
+) -> Result<(), actix_web::Error> {
+    let token = generate_token();
+    let now = Utc::now();
+    let ttl_hours = config.tokens.email_verification_hours;
+
+    email_verification_token::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        user_id: Set(user_id),
+        email: Set(email.to_string()),
+        token_hash: Set(hash_token(&token)),
+        expires_at: Set((now + Duration::hours(ttl_hours)).into()),
+        used_at: Set(None),
+        created_at: Set(now.into()),
+    }
+    .insert(db)
+    .await
+    .map_err(|e| {
+        log::error!(error:% = e; "Verification token creation error");
+        actix_web::error::ErrorInternalServerError("Verification token creation error")
+    })?;
+
+    let app_url = &config.app_url;
+    let email = Email {
+        to: email.to_string(),
+        subject: "Verify your email address".to_string(),
+        body: format!(
+            "Use the link below to verify your email address. It expires in {ttl_hours} hours.\n\n{app_url}/verify-email?token={token}\n"
+        ),
+    };
+
+    logging::spawn(async move {
+        if let Err(e) = mailer.send(email).await {
+            log::error!(error:% = e; "Mail delivery error");
+        }
+    });
+
+    Ok(())
+}
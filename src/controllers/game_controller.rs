@@ -1,15 +1,18 @@
 use actix_web::{web, HttpRequest, HttpResponse, Responder};
 use chrono::Utc;
-use sea_orm::{ActiveModelTrait, DatabaseConnection, EntityTrait, Set};
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, Set};
 use uuid::Uuid;
 
 use crate::models::creator::Entity as CreatorEntity;
 use crate::models::game;
 use crate::models::game::Entity as GameEntity;
+use crate::etag;
+use crate::patch;
 use crate::middleware::auth::get_user_from_request;
 
 use crate::dtos::{CreateGame, UpdateGame};
 
+#[tracing::instrument(skip_all)]
 pub async fn create_game(
     req: HttpRequest,
     db: web::Data<DatabaseConnection>,
@@ -32,11 +35,12 @@ pub async fn create_game(
     };
 
     match new_game.insert(db.get_ref()).await {
-        Ok(game) => HttpResponse::Created().json(game),
+        Ok(game) => etag::created(format!("/api/games/{}", game.id), &game),
         Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
     }
 }
 
+#[tracing::instrument(skip_all)]
 pub async fn list_games(db: web::Data<DatabaseConnection>) -> impl Responder {
     match GameEntity::find().all(db.get_ref()).await {
         Ok(games) => HttpResponse::Ok().json(games),
@@ -44,59 +48,122 @@ pub async fn list_games(db: web::Data<DatabaseConnection>) -> impl Responder {
     }
 }
 
-pub async fn get_game(db: web::Data<DatabaseConnection>, path: web::Path<Uuid>) -> impl Responder {
+#[tracing::instrument(skip_all)]
+pub async fn get_game(
+    req: HttpRequest,
+    db: web::Data<DatabaseConnection>,
+    path: web::Path<Uuid>,
+) -> impl Responder {
     let id = path.into_inner();
     match GameEntity::find_by_id(id).one(db.get_ref()).await {
-        Ok(Some(game)) => HttpResponse::Ok().json(game),
+        Ok(Some(game)) => {
+            let etag = etag::entity_tag(&game);
+            if etag::is_fresh(&req, &etag) {
+                return etag::not_modified(etag);
+            }
+            etag::ok(&game)
+        }
         Ok(None) => HttpResponse::NotFound().body("Game not found"),
         Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
     }
 }
 
+// PUT: every editable field is replaced
+#[tracing::instrument(skip_all)]
 pub async fn update_game(
+    req: HttpRequest,
     db: web::Data<DatabaseConnection>,
     path: web::Path<Uuid>,
     json: web::Json<UpdateGame>,
+) -> impl Responder {
+    let game_id = path.into_inner();
+    match GameEntity::find_by_id(game_id).one(db.get_ref()).await {
+        Ok(Some(model)) => replace_game(&req, db.get_ref(), model, json.into_inner()).await,
+        Ok(None) => HttpResponse::NotFound().body("Game not found"),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// PATCH: merge patch or JSON patch applied to the current game
+#[tracing::instrument(skip_all)]
+pub async fn patch_game(
+    req: HttpRequest,
+    db: web::Data<DatabaseConnection>,
+    path: web::Path<Uuid>,
+    body: web::Bytes,
 ) -> impl Responder {
     let game_id = path.into_inner();
     match GameEntity::find_by_id(game_id).one(db.get_ref()).await {
         Ok(Some(model)) => {
-            let mut active_model: game::ActiveModel = model.into();
+            let fields = match patch::apply(&req, &model, &body, &["id", "created_at", "updated_at"]) {
+                Ok(fields) => fields,
+                Err(err) => return err.response(),
+            };
+            replace_game(&req, db.get_ref(), model, fields).await
+        }
+        Ok(None) => HttpResponse::NotFound().body("Game not found"),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
 
-            if let Some(name) = &json.name {
-                active_model.name = Set(name.clone());
-            }
-            if let Some(description) = &json.description {
-                active_model.description = Set(description.clone());
-            }
-            if let Some(genre) = &json.genre {
-                active_model.genre = Set(genre.clone());
-            }
-            if let Some(creator_id) = json.creator_id {
-                active_model.creator_id = Set(creator_id);
-            }
+async fn replace_game(
+    req: &HttpRequest,
+    db: &DatabaseConnection,
+    model: game::Model,
+    fields: UpdateGame,
+) -> HttpResponse {
+    if !etag::precondition_holds(req, &etag::entity_tag(&model)) {
+        return etag::precondition_failed();
+    }
+    if let Err(message) = fields.validate() {
+        return HttpResponse::UnprocessableEntity().body(message);
+    }
+    if fields.creator_id != model.creator_id {
+        match CreatorEntity::find_by_id(fields.creator_id).one(db).await {
+            Ok(Some(_)) => {}
+            Ok(None) => return HttpResponse::UnprocessableEntity().body("creator_id does not match any creator"),
+            Err(err) => return HttpResponse::InternalServerError().body(err.to_string()),
+        }
+    }
 
-            active_model.updated_at = Set(Utc::now());
+    let version = model.updated_at;
+    let mut active_model: game::ActiveModel = model.into();
+    active_model.name = Set(fields.name);
+    active_model.description = Set(fields.description);
+    active_model.genre = Set(fields.genre);
+    active_model.creator_id = Set(fields.creator_id);
+    active_model.updated_at = Set(Utc::now());
 
-            match active_model.update(db.get_ref()).await {
-                Ok(updated) => HttpResponse::Ok().json(updated),
-                Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
-            }
-        }
-        Ok(None) => HttpResponse::NotFound().body("Game not found"),
+    // With If-Match, a concurrent write since our read must not be overwritten
+    let mut update = GameEntity::update(active_model);
+    if etag::has_precondition(req) {
+        update = update.filter(game::Column::UpdatedAt.eq(version));
+    }
+    match update.exec(db).await {
+        Ok(updated) => etag::ok(&updated),
+        Err(DbErr::RecordNotUpdated) => etag::precondition_failed(),
         Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
     }
 }
 
+#[tracing::instrument(skip_all)]
 pub async fn delete_game(
+    req: HttpRequest,
     db: web::Data<DatabaseConnection>,
     path: web::Path<Uuid>,
 ) -> impl Responder {
     let game_id = path.into_inner();
     match GameEntity::find_by_id(game_id).one(db.get_ref()).await {
         Ok(Some(model)) => {
-            let active_model: game::ActiveModel = model.into();
-            match active_model.delete(db.get_ref()).await {
+            if !etag::precondition_holds(&req, &etag::entity_tag(&model)) {
+                return etag::precondition_failed();
+            }
+            let mut delete = GameEntity::delete_by_id(game_id);
+            if etag::has_precondition(&req) {
+                delete = delete.filter(game::Column::UpdatedAt.eq(model.updated_at));
+            }
+            match delete.exec(db.get_ref()).await {
+                Ok(result) if result.rows_affected == 0 => etag::precondition_failed(),
                 Ok(_) => HttpResponse::NoContent().finish(),
                 Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
             }
@@ -106,6 +173,7 @@ pub async fn delete_game(
     }
 }
 
+#[tracing::instrument(skip_all)]
 pub async fn get_game_with_creator(
     db: web::Data<DatabaseConnection>,
     path: web::Path<Uuid>,
@@ -0,0 +1,96 @@
+use actix_web::{web, HttpResponse};
+use migration::{Migrator, MigratorTrait};
+use sea_orm::{ConnectionTrait, DatabaseConnection, DbBackend, Statement};
+use std::collections::HashSet;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use crate::dtos::health_dto::{ComponentHealth, HealthChecks, HealthResponse, HealthStatus};
+
+// Each dependency check gets this long before the instance is reported not ready
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+// Liveness only says the process is serving requests; it never touches dependencies
+#[tracing::instrument(skip_all)]
+pub async fn live() -> HttpResponse {
+    HttpResponse::Ok().json(HealthResponse {
+        status: HealthStatus::Up,
+        checks: None,
+    })
+}
+
+// Readiness: PostgreSQL answers and every migration known to this build has been applied
+#[tracing::instrument(skip_all)]
+pub async fn ready(db: web::Data<DatabaseConnection>) -> HttpResponse {
+    let db = db.get_ref();
+
+    let (database, migrations) = futures_util::join!(
+        run_check(async {
+            db.ping().await.map_err(|e| e.to_string())?;
+            Ok(Vec::new())
+        }),
+        run_check(pending_migrations(db)),
+    );
+
+    let is_ready = matches!(database.status, HealthStatus::Up)
+        && matches!(migrations.status, HealthStatus::Up);
+    let response = HealthResponse {
+        status: if is_ready { HealthStatus::Up } else { HealthStatus::Down },
+        checks: Some(HealthChecks { database, migrations }),
+    };
+
+    if is_ready {
+        HttpResponse::Ok().json(response)
+    } else {
+        HttpResponse::ServiceUnavailable().json(response)
+    }
+}
+
+// A check is down when it errors, times out, or reports pending items
+async fn run_check<F>(check: F) -> ComponentHealth
+where
+    F: Future<Output = Result<Vec<String>, String>>,
+{
+    let started_at = Instant::now();
+    let result = tokio::time::timeout(CHECK_TIMEOUT, check).await;
+    let latency_ms = (started_at.elapsed().as_secs_f64() * 100_000.0).round() / 100.0;
+
+    let (error, pending) = match result {
+        Ok(Ok(pending)) if pending.is_empty() => (None, pending),
+        Ok(Ok(pending)) => (Some(format!("{} pending migration(s)", pending.len())), pending),
+        Ok(Err(e)) => (Some(e), Vec::new()),
+        Err(_) => (Some(format!("timed out after {}ms", CHECK_TIMEOUT.as_millis())), Vec::new()),
+    };
+
+    ComponentHealth {
+        status: if error.is_none() { HealthStatus::Up } else { HealthStatus::Down },
+        latency_ms,
+        error,
+        pending,
+    }
+}
+
+// Compares the migrations compiled into the `migration` crate with the ones recorded in its
+// bookkeeping table; the query goes through our own connection, not sea-orm-migration's
+async fn pending_migrations(db: &DatabaseConnection) -> Result<Vec<String>, String> {
+    let table = Migrator::migration_table_name().to_string();
+    let rows = db
+        .query_all(Statement::from_string(
+            DbBackend::Postgres,
+            format!("SELECT version FROM \"{table}\""),
+        ))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let applied = rows
+        .iter()
+        .map(|row| row.try_get::<String>("", "version"))
+        .collect::<Result<HashSet<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    Ok(Migrator::migrations()
+        .iter()
+        .map(|migration| migration.name().to_string())
+        .filter(|name| !applied.contains(name))
+        .collect())
+}
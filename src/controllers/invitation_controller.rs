@@ -0,0 +1,282 @@
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{Duration, Utc};
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set,
+    TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::controllers::account_controller::is_unique_violation;
+use crate::controllers::auth_controller::issue_token;
+use crate::dtos::auth_dto::{AuthResponse, UserInfo};
+use crate::dtos::invitation_dto::{AcceptInviteRequest, CreateInvitationRequest, InvitationResponse};
+use crate::jwt;
+use crate::middleware::auth::get_user_from_request;
+use crate::models::{creator, invitation, user};
+use crate::password;
+
+const INVITE_AUDIENCE: &str = "invite";
+const ALLOWED_ROLES: [&str; 2] = ["admin", "creator"];
+
+// Invite tokens carry `jti` instead of `sub` and an `aud`, so they can never
+// be decoded as regular auth `Claims` by the middleware.
+#[derive(Debug, Serialize, Deserialize)]
+struct InviteClaims {
+    jti: String, // invitation id
+    email: String,
+    role: String,
+    aud: String,
+    exp: usize,
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn create_invitation(
+    req: HttpRequest,
+    db: web::Data<DatabaseConnection>,
+    config: web::Data<Config>,
+    json: web::Json<CreateInvitationRequest>,
+) -> Result<HttpResponse> {
+    let db = db.get_ref();
+    let claims = get_user_from_request(&req)?;
+    let invited_by: Uuid = claims
+        .sub
+        .parse()
+        .map_err(|_| actix_web::error::ErrorUnauthorized("Invalid user ID"))?;
+
+    if !ALLOWED_ROLES.contains(&json.role.as_str()) {
+        return Ok(HttpResponse::BadRequest().json(json!({
+            "error": "Role must be one of: admin, creator"
+        })));
+    }
+
+    let existing_user = user::Entity::find()
+        .filter(user::Column::Email.eq(&json.email))
+        .one(db)
+        .await
+        .map_err(|e| {
+            log::error!(error:% = e; "Database error");
+            actix_web::error::ErrorInternalServerError("Database error")
+        })?;
+
+    if existing_user.is_some() {
+        return Ok(HttpResponse::Conflict().json(json!({
+            "error": "User with this email already exists"
+        })));
+    }
+
+    let now = Utc::now();
+    let expires_at = now + Duration::hours(config.tokens.invite_hours);
+
+    let invitation = invitation::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        email: Set(json.email.clone()),
+        role: Set(json.role.clone()),
+        invited_by: Set(invited_by),
+        expires_at: Set(expires_at.into()),
+        accepted_at: Set(None),
+        created_at: Set(now.into()),
+    }
+    .insert(db)
+    .await
+    .map_err(|e| {
+        log::error!(error:% = e; "Invitation creation error");
+        actix_web::error::ErrorInternalServerError("Invitation creation error")
+    })?;
+
+    let token = generate_invite_token(&invitation)?;
+
+    Ok(HttpResponse::Created().json(InvitationResponse {
+        id: invitation.id,
+        email: invitation.email,
+        role: invitation.role,
+        token,
+        expires_at: invitation.expires_at,
+    }))
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn accept_invite(
+    req: HttpRequest,
+    db: web::Data<DatabaseConnection>,
+    config: web::Data<Config>,
+    json: web::Json<AcceptInviteRequest>,
+) -> Result<HttpResponse> {
+    let db = db.get_ref();
+    let claims = decode_invite_token(&json.token)?;
+    let invitation_id: Uuid = claims
+        .jti
+        .parse()
+        .map_err(|_| actix_web::error::ErrorBadRequest("Invalid invite token"))?;
+
+    let password_hash = password::hash(&json.password).await.map_err(|e| {
+        log::error!(error:% = e; "Password hashing error");
+        actix_web::error::ErrorInternalServerError("Password hashing error")
+    })?;
+
+    let txn = db.begin().await.map_err(|e| {
+        log::error!(error:% = e; "Database error");
+        actix_web::error::ErrorInternalServerError("Database error")
+    })?;
+
+    // Claim the invitation atomically so a token can only ever be used once
+    let now = Utc::now();
+    let claimed = invitation::Entity::update_many()
+        .col_expr(invitation::Column::AcceptedAt, Expr::value(now))
+        .filter(invitation::Column::Id.eq(invitation_id))
+        .filter(invitation::Column::AcceptedAt.is_null())
+        .filter(invitation::Column::ExpiresAt.gt(now))
+        .exec(&txn)
+        .await
+        .map_err(|e| {
+            log::error!(error:% = e; "Database error");
+            actix_web::error::ErrorInternalServerError("Database error")
+        })?;
+
+    if claimed.rows_affected != 1 {
+        return Ok(HttpResponse::Gone().json(json!({
+            "error": "Invitation has already been used or has expired"
+        })));
+    }
+
+    let invitation = invitation::Entity::find_by_id(invitation_id)
+        .one(&txn)
+        .await
+        .map_err(|e| {
+            log::error!(error:% = e; "Database error");
+            actix_web::error::ErrorInternalServerError("Database error")
+        })?
+        .ok_or_else(|| actix_web::error::ErrorBadRequest("Invalid invite token"))?;
+
+    let existing_user = user::Entity::find()
+        .filter(user::Column::Email.eq(&invitation.email))
+        .one(&txn)
+        .await
+        .map_err(|e| {
+            log::error!(error:% = e; "Database error");
+            actix_web::error::ErrorInternalServerError("Database error")
+        })?;
+
+    if existing_user.is_some() {
+        return Ok(HttpResponse::Conflict().json(json!({
+            "error": "User with this email already exists"
+        })));
+    }
+
+    // An admin may have created the creator profile before inviting its owner
+    let existing_creator = creator::Entity::find()
+        .filter(creator::Column::Email.eq(&invitation.email))
+        .one(&txn)
+        .await
+        .map_err(|e| {
+            log::error!(error:% = e; "Database error");
+            actix_web::error::ErrorInternalServerError("Database error")
+        })?;
+
+    if existing_creator.as_ref().is_some_and(|creator| creator.user_id.is_some()) {
+        return Ok(creator_taken());
+    }
+
+    let user = user::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        email: Set(invitation.email.clone()),
+        password_hash: Set(password_hash),
+        role: Set(invitation.role.clone()),
+        // Accepting the emailed invite already proves ownership of the address
+        email_verified_at: Set(Some(now.into())),
+        totp_secret: Set(None),
+        totp_enabled_at: Set(None),
+        totp_last_step: Set(None),
+        failed_login_attempts: Set(0),
+        locked_until: Set(None),
+        created_at: Set(now.into()),
+        updated_at: Set(now.into()),
+    }
+    .insert(&txn)
+    .await
+    .map_err(|e| {
+        log::error!(error:% = e; "User creation error");
+        actix_web::error::ErrorInternalServerError("User creation error")
+    })?;
+
+    let saved = match existing_creator {
+        Some(existing) => {
+            let mut active_creator: creator::ActiveModel = existing.into();
+            active_creator.first_name = Set(json.first_name.clone());
+            active_creator.last_name = Set(json.last_name.clone());
+            active_creator.user_id = Set(Some(user.id));
+            active_creator.updated_at = Set(now);
+            active_creator.update(&txn).await
+        }
+        None => {
+            creator::ActiveModel {
+                id: Set(Uuid::new_v4()),
+                first_name: Set(json.first_name.clone()),
+                last_name: Set(json.last_name.clone()),
+                email: Set(invitation.email.clone()),
+                user_id: Set(Some(user.id)),
+                created_at: Set(now),
+                updated_at: Set(now),
+            }
+            .insert(&txn)
+            .await
+        }
+    };
+    match saved {
+        Ok(_) => {}
+        // A creator with this email was added since the lookup; caught by idx-creators-email,
+        // so this needs migration m20250615_000001_add_unique_email_to_creators
+        Err(e) if is_unique_violation(&e) => return Ok(creator_taken()),
+        Err(e) => {
+            log::error!(error:% = e; "Creator creation error");
+            return Err(actix_web::error::ErrorInternalServerError("Creator creation error"));
+        }
+    }
+
+    txn.commit().await.map_err(|e| {
+        log::error!(error:% = e; "Database error");
+        actix_web::error::ErrorInternalServerError("Database error")
+    })?;
+
+    let token = issue_token(db, &config, &req, &user, false).await?;
+
+    let response = AuthResponse {
+        token,
+        user: UserInfo {
+            id: user.id,
+            email: user.email,
+            role: user.role,
+        },
+    };
+
+    Ok(HttpResponse::Created().json(response))
+}
+
+fn creator_taken() -> HttpResponse {
+    HttpResponse::Conflict().json(json!({
+        "error": "A creator profile with this email belongs to another account"
+    }))
+}
+
+fn generate_invite_token(invitation: &invitation::Model) -> Result<String, actix_web::Error> {
+    let claims = InviteClaims {
+        jti: invitation.id.to_string(),
+        email: invitation.email.clone(),
+        role: invitation.role.clone(),
+        aud: INVITE_AUDIENCE.to_string(),
+        exp: invitation.expires_at.timestamp() as usize,
+    };
+
+    jwt::encode(&claims).map_err(|e| {
+        log::error!(error:% = e; "JWT encoding error");
+        actix_web::error::ErrorInternalServerError("JWT encoding error")
+    })
+}
+
+fn decode_invite_token(token: &str) -> Result<InviteClaims, actix_web::Error> {
+    jwt::decode::<InviteClaims>(token, Some(INVITE_AUDIENCE))
+        .map_err(|_| actix_web::error::ErrorBadRequest("Invalid or expired invite token"))
+}
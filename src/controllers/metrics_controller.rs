@@ -0,0 +1,20 @@
+use actix_web::{web, HttpResponse, Result};
+use sea_orm::DatabaseConnection;
+
+use crate::metrics::Metrics;
+
+// Prometheus text exposition format
+#[tracing::instrument(skip_all)]
+pub async fn export(
+    db: web::Data<DatabaseConnection>,
+    metrics: web::Data<Metrics>,
+) -> Result<HttpResponse> {
+    let body = metrics.render(db.get_ref()).map_err(|e| {
+        log::error!(error:% = e; "Metrics encoding error");
+        actix_web::error::ErrorInternalServerError("Metrics encoding error")
+    })?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(prometheus::TEXT_FORMAT)
+        .body(body))
+}
@@ -1,3 +1,13 @@
+pub mod account_controller;
+pub mod admin_controller;
+pub mod api_key_controller;
 pub mod creator_controller;
 pub mod game_controller;
+pub mod health_controller;
 pub mod auth_controller;
+pub mod email_verification_controller;
+pub mod invitation_controller;
+pub mod metrics_controller;
+pub mod oidc_controller;
+pub mod session_controller;
+pub mod two_factor_controller;
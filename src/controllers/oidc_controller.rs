@@ -0,0 +1,302 @@
+use actix_web::{http::header, web, HttpRequest, HttpResponse, Result};
+use chrono::{Duration, Utc};
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set,
+    TransactionTrait,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::client_ip::client_ip;
+use crate::config::Config;
+use crate::controllers::auth_controller::{complete_login, issue_token};
+use crate::controllers::two_factor_controller::generate_mfa_token;
+use crate::dtos::auth_dto::{AuthResponse, UserInfo};
+use crate::dtos::two_factor_dto::MfaChallengeResponse;
+use crate::login_throttle::{account_retry_after, too_many_attempts};
+use crate::metrics::{self, Metrics};
+use crate::oidc::{ExternalIdentity, OidcProvider, STATE_COOKIE};
+use crate::models::{sso_login_code, user, user_identity};
+use crate::password;
+use crate::tokens::{generate_token, hash_token};
+
+#[derive(Deserialize)]
+pub struct OidcCallbackQuery {
+    pub code: String,
+    pub state: String,
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn login(oidc: Option<web::Data<OidcProvider>>) -> Result<HttpResponse> {
+    let oidc = oidc.ok_or_else(|| actix_web::error::ErrorNotFound("SSO is not configured"))?;
+
+    let (url, state_cookie) = oidc.authorize_url().await.map_err(|e| {
+        log::error!(error:% = e; "OIDC discovery error");
+        actix_web::error::ErrorBadGateway("Identity provider unavailable")
+    })?;
+
+    Ok(HttpResponse::Found()
+        .insert_header((header::LOCATION, url))
+        .cookie(state_cookie)
+        .finish())
+}
+
+#[derive(Deserialize)]
+pub struct OidcExchangeRequest {
+    pub code: String,
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn callback(
+    req: HttpRequest,
+    db: web::Data<DatabaseConnection>,
+    config: web::Data<Config>,
+    metrics: web::Data<Metrics>,
+    oidc: Option<web::Data<OidcProvider>>,
+    query: web::Query<OidcCallbackQuery>,
+) -> Result<HttpResponse> {
+    let db = db.get_ref();
+    let oidc = oidc.ok_or_else(|| actix_web::error::ErrorNotFound("SSO is not configured"))?;
+
+    let state_cookie = req.cookie(STATE_COOKIE);
+    let identity = oidc
+        .exchange(&query.code, &query.state, state_cookie.as_ref().map(|cookie| cookie.value()))
+        .await
+        .map_err(|e| {
+            log::error!(error:% = e; "OIDC login error");
+            actix_web::error::ErrorUnauthorized("SSO login failed")
+        })?;
+
+    let user = find_or_provision_user(db, &identity, &oidc.config.default_role).await?;
+
+    // Browser flows get a short-lived one-time code instead of a token in the URL, which
+    // would end up in history and logs; the frontend trades it in via POST /oidc/exchange
+    if let Some(frontend_url) = &oidc.config.frontend_redirect_url {
+        let code = create_login_code(db, &config, user.id).await?;
+        return Ok(HttpResponse::Found()
+            .insert_header((header::LOCATION, format!("{frontend_url}#code={code}")))
+            .cookie(oidc.clear_state_cookie())
+            .finish());
+    }
+
+    let mut response = finish_login(db, &config, &req, &metrics, user).await?;
+    response.add_cookie(&oidc.clear_state_cookie())?;
+    Ok(response)
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn exchange(
+    http_req: HttpRequest,
+    db: web::Data<DatabaseConnection>,
+    config: web::Data<Config>,
+    metrics: web::Data<Metrics>,
+    req: web::Json<OidcExchangeRequest>,
+) -> Result<HttpResponse> {
+    let db = db.get_ref();
+
+    // Marking the code used and reading it back in one statement keeps it single-use under races
+    let now = Utc::now();
+    let code = sso_login_code::Entity::update_many()
+        .col_expr(sso_login_code::Column::UsedAt, Expr::value(now))
+        .filter(sso_login_code::Column::CodeHash.eq(hash_token(&req.code)))
+        .filter(sso_login_code::Column::UsedAt.is_null())
+        .filter(sso_login_code::Column::ExpiresAt.gt(now))
+        .exec_with_returning(db)
+        .await
+        .map_err(|e| {
+            log::error!(error:% = e; "Database error");
+            actix_web::error::ErrorInternalServerError("Database error")
+        })?
+        .pop()
+        .ok_or_else(|| actix_web::error::ErrorUnauthorized("Invalid or expired code"))?;
+
+    let user = user::Entity::find_by_id(code.user_id)
+        .one(db)
+        .await
+        .map_err(|e| {
+            log::error!(error:% = e; "Database error");
+            actix_web::error::ErrorInternalServerError("Database error")
+        })?
+        .ok_or_else(|| actix_web::error::ErrorUnauthorized("User not found"))?;
+
+    finish_login(db, &config, &http_req, &metrics, user).await
+}
+
+async fn create_login_code(
+    db: &DatabaseConnection,
+    config: &Config,
+    user_id: Uuid,
+) -> Result<String, actix_web::Error> {
+    let code = generate_token();
+    let now = Utc::now();
+
+    sso_login_code::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        user_id: Set(user_id),
+        code_hash: Set(hash_token(&code)),
+        expires_at: Set((now + Duration::minutes(config.tokens.sso_login_code_minutes)).into()),
+        used_at: Set(None),
+        created_at: Set(now.into()),
+    }
+    .insert(db)
+    .await
+    .map_err(|e| {
+        log::error!(error:% = e; "Database error");
+        actix_web::error::ErrorInternalServerError("Database error")
+    })?;
+
+    Ok(code)
+}
+
+// The identity provider replaces the password, not the rest of the login policy: locked
+// accounts stay locked and 2FA users get the same challenge as after a password login
+async fn finish_login(
+    db: &DatabaseConnection,
+    config: &Config,
+    req: &HttpRequest,
+    metrics: &Metrics,
+    user: user::Model,
+) -> Result<HttpResponse> {
+    if let Some(retry_after) = account_retry_after(&user) {
+        metrics.record_login(metrics::LOGIN_LOCKED);
+        return Ok(too_many_attempts(retry_after));
+    }
+
+    if user.totp_enabled_at.is_some() {
+        let mfa_token = generate_mfa_token(config, &user)?;
+        return Ok(HttpResponse::Ok().json(MfaChallengeResponse {
+            mfa_required: true,
+            mfa_token,
+        }));
+    }
+
+    let ip = client_ip(req);
+    complete_login(db, &user, ip.as_deref(), metrics, "oidc").await?;
+
+    let token = issue_token(db, config, req, &user, false).await?;
+
+    let response = AuthResponse {
+        token,
+        user: UserInfo {
+            id: user.id,
+            email: user.email,
+            role: user.role,
+        },
+    };
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+// Resolves the external subject to a local user: an existing link first, then an
+// existing account with the same email if both sides verified it, otherwise a new account
+async fn find_or_provision_user(
+    db: &DatabaseConnection,
+    identity: &ExternalIdentity,
+    default_role: &str,
+) -> Result<user::Model, actix_web::Error> {
+    let linked = user_identity::Entity::find()
+        .filter(user_identity::Column::Issuer.eq(&identity.issuer))
+        .filter(user_identity::Column::Subject.eq(&identity.subject))
+        .one(db)
+        .await
+        .map_err(|e| {
+            log::error!(error:% = e; "Database error");
+            actix_web::error::ErrorInternalServerError("Database error")
+        })?;
+
+    if let Some(linked) = linked {
+        return user::Entity::find_by_id(linked.user_id)
+            .one(db)
+            .await
+            .map_err(|e| {
+                log::error!(error:% = e; "Database error");
+                actix_web::error::ErrorInternalServerError("Database error")
+            })?
+            .ok_or_else(|| actix_web::error::ErrorUnauthorized("User not found"));
+    }
+
+    // Never link or create accounts from an unverified address
+    let email = match &identity.email {
+        Some(email) if identity.email_verified => email.clone(),
+        _ => {
+            return Err(actix_web::error::ErrorForbidden(
+                "Identity provider did not supply a verified email",
+            ))
+        }
+    };
+
+    let txn = db.begin().await.map_err(|e| {
+        log::error!(error:% = e; "Database error");
+        actix_web::error::ErrorInternalServerError("Database error")
+    })?;
+
+    let now = Utc::now();
+    let existing_user = user::Entity::find()
+        .filter(user::Column::Email.eq(&email))
+        .one(&txn)
+        .await
+        .map_err(|e| {
+            log::error!(error:% = e; "Database error");
+            actix_web::error::ErrorInternalServerError("Database error")
+        })?;
+
+    let user = match existing_user {
+        Some(user) if user.email_verified_at.is_some() => user,
+        // Whoever registered the address first may not own it (account pre-hijacking), so the
+        // owner has to prove it through the local verification email before SSO can take over
+        Some(_) => {
+            return Err(actix_web::error::ErrorConflict(
+                "An account with this email exists but is not verified; verify it before signing in with SSO",
+            ))
+        }
+        None => {
+            // SSO-only accounts get an unguessable password nobody knows
+            let password_hash = password::hash(&generate_token()).await.map_err(|e| {
+                log::error!(error:% = e; "Password hashing error");
+                actix_web::error::ErrorInternalServerError("Password hashing error")
+            })?;
+
+            user::ActiveModel {
+                id: Set(Uuid::new_v4()),
+                email: Set(email),
+                password_hash: Set(password_hash),
+                role: Set(default_role.to_string()),
+                email_verified_at: Set(Some(now.into())),
+                totp_secret: Set(None),
+                totp_enabled_at: Set(None),
+                totp_last_step: Set(None),
+                failed_login_attempts: Set(0),
+                locked_until: Set(None),
+                created_at: Set(now.into()),
+                updated_at: Set(now.into()),
+            }
+            .insert(&txn)
+            .await
+            .map_err(|e| {
+                log::error!(error:% = e; "User creation error");
+                actix_web::error::ErrorInternalServerError("User creation error")
+            })?
+        }
+    };
+
+    user_identity::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        user_id: Set(user.id),
+        issuer: Set(identity.issuer.clone()),
+        subject: Set(identity.subject.clone()),
+        created_at: Set(now.into()),
+    }
+    .insert(&txn)
+    .await
+    .map_err(|e| {
+        log::error!(error:% = e; "Identity link error");
+        actix_web::error::ErrorInternalServerError("Identity link error")
+    })?;
+
+    txn.commit().await.map_err(|e| {
+        log::error!(error:% = e; "Database error");
+        actix_web::error::ErrorInternalServerError("Database error")
+    })?;
+
+    Ok(user)
+}
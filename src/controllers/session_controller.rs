@@ -0,0 +1,137 @@
+use actix_web::{http::header, web, HttpRequest, HttpResponse, Result};
+use chrono::{Duration, Utc};
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr, EntityTrait,
+    QueryFilter, QueryOrder, Set,
+};
+use uuid::Uuid;
+
+use crate::client_ip::client_ip;
+use crate::dtos::session_dto::SessionResponse;
+use crate::middleware::auth::get_user_from_request;
+use crate::models::session;
+
+#[tracing::instrument(skip_all)]
+pub async fn list_sessions(
+    req: HttpRequest,
+    db: web::Data<DatabaseConnection>,
+) -> Result<HttpResponse> {
+    let claims = get_user_from_request(&req)?;
+    let user_id: Uuid = claims
+        .sub
+        .parse()
+        .map_err(|_| actix_web::error::ErrorUnauthorized("Invalid user ID"))?;
+    let current_session = claims.sid.as_deref().and_then(|sid| sid.parse::<Uuid>().ok());
+
+    let sessions = session::Entity::find()
+        .filter(session::Column::UserId.eq(user_id))
+        .filter(session::Column::RevokedAt.is_null())
+        .filter(session::Column::ExpiresAt.gt(Utc::now()))
+        .order_by_desc(session::Column::LastSeenAt)
+        .all(db.get_ref())
+        .await
+        .map_err(|e| {
+            log::error!(error:% = e; "Database error");
+            actix_web::error::ErrorInternalServerError("Database error")
+        })?;
+
+    let response: Vec<SessionResponse> = sessions
+        .into_iter()
+        .map(|session| SessionResponse {
+            current: Some(session.id) == current_session,
+            id: session.id,
+            user_agent: session.user_agent,
+            ip_address: session.ip_address,
+            created_at: session.created_at,
+            last_seen_at: session.last_seen_at,
+            expires_at: session.expires_at,
+            impersonator_id: session.impersonator_id,
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn delete_session(
+    req: HttpRequest,
+    db: web::Data<DatabaseConnection>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse> {
+    let user_id: Uuid = get_user_from_request(&req)?
+        .sub
+        .parse()
+        .map_err(|_| actix_web::error::ErrorUnauthorized("Invalid user ID"))?;
+
+    let revoked = session::Entity::update_many()
+        .col_expr(session::Column::RevokedAt, Expr::value(Utc::now()))
+        .filter(session::Column::Id.eq(path.into_inner()))
+        .filter(session::Column::UserId.eq(user_id))
+        .filter(session::Column::RevokedAt.is_null())
+        .exec(db.get_ref())
+        .await
+        .map_err(|e| {
+            log::error!(error:% = e; "Database error");
+            actix_web::error::ErrorInternalServerError("Database error")
+        })?;
+
+    if revoked.rows_affected == 0 {
+        return Ok(HttpResponse::NotFound().body("Session not found"));
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// Records a new login session for `user_id` with the client's user agent and IP.
+// `impersonator` is the admin when the session belongs to an impersonation token.
+#[tracing::instrument(skip_all)]
+pub async fn start_session<C: ConnectionTrait>(
+    db: &C,
+    req: &HttpRequest,
+    user_id: Uuid,
+    lifetime: Duration,
+    impersonator: Option<Uuid>,
+) -> Result<session::Model, actix_web::Error> {
+    let now = Utc::now();
+    let user_agent = req
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let ip_address = client_ip(req);
+
+    session::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        user_id: Set(user_id),
+        user_agent: Set(user_agent),
+        ip_address: Set(ip_address),
+        created_at: Set(now.into()),
+        last_seen_at: Set(now.into()),
+        expires_at: Set((now + lifetime).into()),
+        revoked_at: Set(None),
+        impersonator_id: Set(impersonator),
+    }
+    .insert(db)
+    .await
+    .map_err(|e| {
+        log::error!(error:% = e; "Session creation error");
+        actix_web::error::ErrorInternalServerError("Session creation error")
+    })
+}
+
+// Signs `user_id` out of every active session except `keep`; returns how many were revoked
+pub async fn revoke_sessions<C: ConnectionTrait>(
+    db: &C,
+    user_id: Uuid,
+    keep: Option<Uuid>,
+) -> Result<u64, DbErr> {
+    let mut sessions = session::Entity::update_many()
+        .col_expr(session::Column::RevokedAt, Expr::value(Utc::now()))
+        .filter(session::Column::UserId.eq(user_id))
+        .filter(session::Column::RevokedAt.is_null());
+    if let Some(keep) = keep {
+        sessions = sessions.filter(session::Column::Id.ne(keep));
+    }
+    Ok(sessions.exec(db).await?.rows_affected)
+}
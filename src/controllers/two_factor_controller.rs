@@ -0,0 +1,576 @@
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{Duration, Utc};
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, ConnectionTrait, DatabaseConnection, EntityTrait, QueryFilter, Set,
+    TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::time::{SystemTime, UNIX_EPOCH};
+use totp_rs::{Algorithm, Secret, TOTP};
+use uuid::Uuid;
+
+use crate::client_ip::client_ip;
+use crate::config::Config;
+use crate::controllers::auth_controller::{complete_login, issue_token, store_rehash};
+use crate::dtos::auth_dto::{AuthResponse, UserInfo};
+use crate::dtos::two_factor_dto::{
+    MfaLoginRequest, RecoveryCodesResponse, TotpConfirmRequest, TotpEnrollmentResponse, TotpReauthRequest,
+};
+use crate::encryption;
+use crate::jwt;
+use crate::login_throttle::{
+    account_retry_after, handle_failure, too_many_attempts, LoginThrottle,
+};
+use crate::metrics::{self, Metrics};
+use crate::middleware::auth::get_current_user;
+use crate::models::{recovery_code, user};
+use crate::password::{self, PendingRehashes};
+use crate::security_events;
+use crate::tokens::{generate_token, hash_token};
+
+const MFA_AUDIENCE: &str = "mfa";
+const TOTP_STEP_SECS: u64 = 30;
+const RECOVERY_CODE_COUNT: usize = 10;
+
+// Short-lived token proving the password step of a two-step login succeeded
+#[derive(Debug, Serialize, Deserialize)]
+struct MfaClaims {
+    sub: String, // user id
+    aud: String,
+    exp: usize,
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn enroll(
+    req: HttpRequest,
+    db: web::Data<DatabaseConnection>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse> {
+    let db = db.get_ref();
+    let user = get_current_user(&req, db).await?;
+
+    if user.totp_enabled_at.is_some() {
+        return Ok(HttpResponse::Conflict().json(json!({
+            "error": "Two-factor authentication is already enabled"
+        })));
+    }
+
+    let secret = Secret::generate_secret().to_encoded().to_string();
+    let totp = build_totp(&config.mfa.totp_issuer, &secret, &user.email)?;
+    let otpauth_uri = totp.get_url();
+    let sealed = encryption::totp().seal(user.id, &secret).map_err(|e| {
+        log::error!(error:% = e; "TOTP secret encryption error");
+        actix_web::error::ErrorInternalServerError("TOTP secret encryption error")
+    })?;
+
+    // Stored as pending until the user confirms a code from their authenticator
+    let mut active_user: user::ActiveModel = user.into();
+    active_user.totp_secret = Set(Some(sealed));
+    active_user.updated_at = Set(Utc::now().into());
+    active_user.update(db).await.map_err(|e| {
+        log::error!(error:% = e; "User update error");
+        actix_web::error::ErrorInternalServerError("User update error")
+    })?;
+
+    Ok(HttpResponse::Ok().json(TotpEnrollmentResponse {
+        secret,
+        otpauth_uri,
+    }))
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn confirm(
+    req: HttpRequest,
+    db: web::Data<DatabaseConnection>,
+    config: web::Data<Config>,
+    json: web::Json<TotpConfirmRequest>,
+) -> Result<HttpResponse> {
+    let db = db.get_ref();
+    let user = get_current_user(&req, db).await?;
+
+    if user.totp_enabled_at.is_some() {
+        return Ok(HttpResponse::Conflict().json(json!({
+            "error": "Two-factor authentication is already enabled"
+        })));
+    }
+
+    let secret = stored_secret(&user)?
+        .ok_or_else(|| actix_web::error::ErrorBadRequest("Two-factor enrollment has not been started"))?;
+
+    let Some(step) = totp_step(&config.mfa.totp_issuer, &secret, &user.email, &json.code)? else {
+        return Err(actix_web::error::ErrorBadRequest("Invalid authentication code"));
+    };
+
+    let txn = db.begin().await.map_err(|e| {
+        log::error!(error:% = e; "Database error");
+        actix_web::error::ErrorInternalServerError("Database error")
+    })?;
+
+    let now = Utc::now();
+    let user_id = user.id;
+    let mut active_user: user::ActiveModel = user.into();
+    active_user.totp_enabled_at = Set(Some(now.into()));
+    // The code used to confirm cannot be used again to log in
+    active_user.totp_last_step = Set(Some(step as i64));
+    active_user.updated_at = Set(now.into());
+    active_user.update(&txn).await.map_err(|e| {
+        log::error!(error:% = e; "User update error");
+        actix_web::error::ErrorInternalServerError("User update error")
+    })?;
+
+    let recovery_codes = replace_recovery_codes(&txn, user_id).await?;
+
+    txn.commit().await.map_err(|e| {
+        log::error!(error:% = e; "Database error");
+        actix_web::error::ErrorInternalServerError("Database error")
+    })?;
+
+    // Recovery codes are only ever shown here (and on regeneration)
+    Ok(HttpResponse::Ok().json(RecoveryCodesResponse { recovery_codes }))
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn verify_login(
+    req: HttpRequest,
+    db: web::Data<DatabaseConnection>,
+    config: web::Data<Config>,
+    throttle: web::Data<LoginThrottle>,
+    metrics: web::Data<Metrics>,
+    pending_rehashes: web::Data<PendingRehashes>,
+    json: web::Json<MfaLoginRequest>,
+) -> Result<HttpResponse> {
+    let db = db.get_ref();
+    let ip = client_ip(&req);
+    let ip = ip.as_deref();
+
+    if let Some(retry_after) = ip.and_then(|ip| throttle.ip_retry_after(ip)) {
+        metrics.record_login(metrics::LOGIN_LOCKED);
+        return Ok(too_many_attempts(retry_after));
+    }
+    let claims = decode_mfa_token(&json.mfa_token)?;
+    let user_id: Uuid = claims
+        .sub
+        .parse()
+        .map_err(|_| actix_web::error::ErrorUnauthorized("Invalid MFA token"))?;
+
+    let user = user::Entity::find_by_id(user_id)
+        .one(db)
+        .await
+        .map_err(|e| {
+            log::error!(error:% = e; "Database error");
+            actix_web::error::ErrorInternalServerError("Database error")
+        })?
+        .ok_or_else(|| actix_web::error::ErrorUnauthorized("Invalid MFA token"))?;
+
+    if let Some(retry_after) = account_retry_after(&user) {
+        metrics.record_login(metrics::LOGIN_LOCKED);
+        return Ok(too_many_attempts(retry_after));
+    }
+
+    let secret = match (stored_secret(&user)?, user.totp_enabled_at) {
+        (Some(secret), Some(_)) => secret,
+        _ => return Err(actix_web::error::ErrorUnauthorized("Invalid MFA token")),
+    };
+
+    let is_valid = verify_second_factor(
+        db,
+        &config,
+        &user,
+        &secret,
+        json.code.as_deref(),
+        json.recovery_code.as_deref(),
+    )
+    .await?;
+
+    if !is_valid {
+        metrics.record_login(metrics::LOGIN_FAILURE);
+        return handle_failure(db, &throttle, Some(&user), ip, "Invalid authentication code").await;
+    }
+
+    if let Some(password_hash) = pending_rehashes.take(user.id) {
+        store_rehash(db, user.id, password_hash).await;
+    }
+    let method = match json.code {
+        Some(_) => "totp",
+        None => "recovery_code",
+    };
+    complete_login(db, &user, ip, &metrics, method).await?;
+
+    let token = issue_token(db, &config, &req, &user, true).await?;
+
+    let response = AuthResponse {
+        token,
+        user: UserInfo {
+            id: user.id,
+            email: user.email,
+            role: user.role,
+        },
+    };
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+// Turns 2FA off. Needs the password and a current code, so a stolen session alone can't do it.
+#[tracing::instrument(skip_all)]
+pub async fn disable(
+    req: HttpRequest,
+    db: web::Data<DatabaseConnection>,
+    config: web::Data<Config>,
+    json: web::Json<TotpReauthRequest>,
+) -> Result<HttpResponse> {
+    let db = db.get_ref();
+    let user = get_current_user(&req, db).await?;
+
+    // Checked first so a refused request does not use up a code
+    if config.mfa.required_for_role(&user.role) {
+        return Err(actix_web::error::ErrorForbidden("Two-factor authentication is required for your role"));
+    }
+    reauthenticate(db, &config, &user, &json).await?;
+
+    let txn = db.begin().await.map_err(|e| {
+        log::error!(error:% = e; "Database error");
+        actix_web::error::ErrorInternalServerError("Database error")
+    })?;
+
+    let user_id = user.id;
+    let mut active_user: user::ActiveModel = user.into();
+    active_user.totp_secret = Set(None);
+    active_user.totp_enabled_at = Set(None);
+    active_user.totp_last_step = Set(None);
+    active_user.updated_at = Set(Utc::now().into());
+    active_user.update(&txn).await.map_err(|e| {
+        log::error!(error:% = e; "User update error");
+        actix_web::error::ErrorInternalServerError("User update error")
+    })?;
+
+    recovery_code::Entity::delete_many()
+        .filter(recovery_code::Column::UserId.eq(user_id))
+        .exec(&txn)
+        .await
+        .map_err(|e| {
+            log::error!(error:% = e; "Database error");
+            actix_web::error::ErrorInternalServerError("Database error")
+        })?;
+
+    txn.commit().await.map_err(|e| {
+        log::error!(error:% = e; "Database error");
+        actix_web::error::ErrorInternalServerError("Database error")
+    })?;
+
+    let ip = client_ip(&req);
+    security_events::record(db, Some(user_id), security_events::TWO_FACTOR_DISABLED, ip.as_deref(), None).await;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "message": "Two-factor authentication has been disabled"
+    })))
+}
+
+// Replaces all recovery codes, e.g. once most of them are used up
+#[tracing::instrument(skip_all)]
+pub async fn regenerate_recovery_codes(
+    req: HttpRequest,
+    db: web::Data<DatabaseConnection>,
+    config: web::Data<Config>,
+    json: web::Json<TotpReauthRequest>,
+) -> Result<HttpResponse> {
+    let db = db.get_ref();
+    let user = get_current_user(&req, db).await?;
+    reauthenticate(db, &config, &user, &json).await?;
+
+    let txn = db.begin().await.map_err(|e| {
+        log::error!(error:% = e; "Database error");
+        actix_web::error::ErrorInternalServerError("Database error")
+    })?;
+    let recovery_codes = replace_recovery_codes(&txn, user.id).await?;
+    txn.commit().await.map_err(|e| {
+        log::error!(error:% = e; "Database error");
+        actix_web::error::ErrorInternalServerError("Database error")
+    })?;
+
+    let ip = client_ip(&req);
+    security_events::record(
+        db,
+        Some(user.id),
+        security_events::RECOVERY_CODES_REGENERATED,
+        ip.as_deref(),
+        None,
+    )
+    .await;
+
+    Ok(HttpResponse::Ok().json(RecoveryCodesResponse { recovery_codes }))
+}
+
+// Encrypts TOTP secrets stored in plaintext before TOTP_ENCRYPTION_KEY existed; runs at startup
+pub async fn encrypt_legacy_secrets(db: &DatabaseConnection) -> anyhow::Result<()> {
+    let users = user::Entity::find()
+        .filter(user::Column::TotpSecret.is_not_null())
+        .all(db)
+        .await?;
+
+    let mut encrypted = 0;
+    for user in users {
+        let Some(secret) = user.totp_secret.filter(|secret| !encryption::is_sealed(secret)) else {
+            continue;
+        };
+        let sealed = encryption::totp().seal(user.id, &secret)?;
+        // Only if it is still the plaintext we read, in case another instance got there first
+        user::Entity::update_many()
+            .col_expr(user::Column::TotpSecret, Expr::value(sealed))
+            .filter(user::Column::Id.eq(user.id))
+            .filter(user::Column::TotpSecret.eq(secret))
+            .exec(db)
+            .await?;
+        encrypted += 1;
+    }
+
+    if encrypted > 0 {
+        log::info!(count = encrypted; "Encrypted stored TOTP secrets");
+    }
+    Ok(())
+}
+
+pub fn generate_mfa_token(config: &Config, user: &user::Model) -> Result<String, actix_web::Error> {
+    let expiration = Utc::now()
+        .checked_add_signed(Duration::minutes(config.tokens.mfa_challenge_minutes))
+        .expect("valid timestamp")
+        .timestamp() as usize;
+
+    let claims = MfaClaims {
+        sub: user.id.to_string(),
+        aud: MFA_AUDIENCE.to_string(),
+        exp: expiration,
+    };
+
+    jwt::encode(&claims).map_err(|e| {
+        log::error!(error:% = e; "JWT encoding error");
+        actix_web::error::ErrorInternalServerError("JWT encoding error")
+    })
+}
+
+fn decode_mfa_token(token: &str) -> Result<MfaClaims, actix_web::Error> {
+    jwt::decode::<MfaClaims>(token, Some(MFA_AUDIENCE))
+        .map_err(|_| actix_web::error::ErrorUnauthorized("Invalid or expired MFA token"))
+}
+
+// Decrypts `users.totp_secret`. Plaintext values from before encryption are still accepted
+// until `encrypt_legacy_secrets` has rewritten them.
+fn stored_secret(user: &user::Model) -> Result<Option<String>, actix_web::Error> {
+    let Some(stored) = &user.totp_secret else {
+        return Ok(None);
+    };
+    if !encryption::is_sealed(stored) {
+        return Ok(Some(stored.clone()));
+    }
+    encryption::totp().open(user.id, stored).map(Some).map_err(|e| {
+        log::error!(error:% = e; "TOTP secret decryption error");
+        actix_web::error::ErrorInternalServerError("TOTP secret error")
+    })
+}
+
+// Checks a TOTP code (one-time per time step) or consumes an unused recovery code
+async fn verify_second_factor(
+    db: &DatabaseConnection,
+    config: &Config,
+    user: &user::Model,
+    secret: &str,
+    code: Option<&str>,
+    recovery_code: Option<&str>,
+) -> Result<bool, actix_web::Error> {
+    match (code, recovery_code) {
+        (Some(code), _) => match totp_step(&config.mfa.totp_issuer, secret, &user.email, code)? {
+            Some(step) => accept_totp_step(db, user.id, step).await,
+            None => Ok(false),
+        },
+        (None, Some(recovery_code)) => {
+            // Consuming the code in a single statement keeps it one-time even under races
+            let consumed = recovery_code::Entity::update_many()
+                .col_expr(recovery_code::Column::UsedAt, Expr::value(Utc::now()))
+                .filter(recovery_code::Column::UserId.eq(user.id))
+                .filter(recovery_code::Column::CodeHash.eq(hash_token(&normalize_recovery_code(recovery_code))))
+                .filter(recovery_code::Column::UsedAt.is_null())
+                .exec(db)
+                .await
+                .map_err(|e| {
+                    log::error!(error:% = e; "Database error");
+                    actix_web::error::ErrorInternalServerError("Database error")
+                })?;
+            Ok(consumed.rows_affected == 1)
+        }
+        (None, None) => Err(actix_web::error::ErrorBadRequest(
+            "Either code or recovery_code is required",
+        )),
+    }
+}
+
+// Password plus a second factor, for changes to an account's 2FA
+async fn reauthenticate(
+    db: &DatabaseConnection,
+    config: &Config,
+    user: &user::Model,
+    json: &TotpReauthRequest,
+) -> Result<(), actix_web::Error> {
+    let secret = match (stored_secret(user)?, user.totp_enabled_at) {
+        (Some(secret), Some(_)) => secret,
+        _ => return Err(actix_web::error::ErrorBadRequest("Two-factor authentication is not enabled")),
+    };
+
+    let password_matches = password::verify(&json.password, &user.password_hash).await.map_err(|e| {
+        log::error!(error:% = e; "Password verification error");
+        actix_web::error::ErrorInternalServerError("Password verification error")
+    })?;
+    if !password_matches {
+        return Err(actix_web::error::ErrorUnauthorized("Current password is incorrect"));
+    }
+
+    let code = json.code.as_deref();
+    if !verify_second_factor(db, config, user, &secret, code, json.recovery_code.as_deref()).await? {
+        return Err(actix_web::error::ErrorUnauthorized("Invalid authentication code"));
+    }
+    Ok(())
+}
+
+// Deletes the user's recovery codes and stores a fresh set, returning them in plaintext
+async fn replace_recovery_codes<C: ConnectionTrait>(db: &C, user_id: Uuid) -> Result<Vec<String>, actix_web::Error> {
+    recovery_code::Entity::delete_many()
+        .filter(recovery_code::Column::UserId.eq(user_id))
+        .exec(db)
+        .await
+        .map_err(|e| {
+            log::error!(error:% = e; "Database error");
+            actix_web::error::ErrorInternalServerError("Database error")
+        })?;
+
+    let now = Utc::now();
+    let mut recovery_codes = Vec::with_capacity(RECOVERY_CODE_COUNT);
+    for _ in 0..RECOVERY_CODE_COUNT {
+        let code = generate_recovery_code();
+        recovery_code::ActiveModel {
+            id: Set(Uuid::new_v4()),
+            user_id: Set(user_id),
+            code_hash: Set(hash_token(&normalize_recovery_code(&code))),
+            used_at: Set(None),
+            created_at: Set(now.into()),
+        }
+        .insert(db)
+        .await
+        .map_err(|e| {
+            log::error!(error:% = e; "Recovery code creation error");
+            actix_web::error::ErrorInternalServerError("Recovery code creation error")
+        })?;
+        recovery_codes.push(code);
+    }
+    Ok(recovery_codes)
+}
+
+// Skew is handled by `totp_step`, so the TOTP itself only ever checks one exact step
+fn build_totp(issuer: &str, secret: &str, email: &str) -> Result<TOTP, actix_web::Error> {
+    let secret_bytes = Secret::Encoded(secret.to_string()).to_bytes().map_err(|e| {
+        log::error!(error:? = e; "TOTP secret error");
+        actix_web::error::ErrorInternalServerError("TOTP secret error")
+    })?;
+
+    let issuer = Some(issuer.to_string());
+    TOTP::new(Algorithm::SHA1, 6, 0, TOTP_STEP_SECS, secret_bytes, issuer, email.to_string()).map_err(|e| {
+        log::error!(error:% = e; "TOTP setup error");
+        actix_web::error::ErrorInternalServerError("TOTP setup error")
+    })
+}
+
+// The time step `code` was generated for, allowing one step of clock skew either way
+fn totp_step(issuer: &str, secret: &str, email: &str, code: &str) -> Result<Option<u64>, actix_web::Error> {
+    let totp = build_totp(issuer, secret, email)?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_err(|e| {
+        log::error!(error:% = e; "System time error");
+        actix_web::error::ErrorInternalServerError("System time error")
+    })?;
+    let current = now.as_secs() / TOTP_STEP_SECS;
+    let code = code.trim();
+
+    Ok((current.saturating_sub(1)..=current + 1).find(|step| totp.check(code, step * TOTP_STEP_SECS)))
+}
+
+// Records `step` as the last one used, unless it (or a later one) already was. A single
+// conditional update keeps a code one-time even when two logins race with it.
+async fn accept_totp_step(db: &DatabaseConnection, user_id: Uuid, step: u64) -> Result<bool, actix_web::Error> {
+    let step = step as i64;
+    let accepted = user::Entity::update_many()
+        .col_expr(user::Column::TotpLastStep, Expr::value(step))
+        .filter(user::Column::Id.eq(user_id))
+        .filter(
+            Condition::any()
+                .add(user::Column::TotpLastStep.is_null())
+                .add(user::Column::TotpLastStep.lt(step)),
+        )
+        .exec(db)
+        .await
+        .map_err(|e| {
+            log::error!(error:% = e; "Database error");
+            actix_web::error::ErrorInternalServerError("Database error")
+        })?;
+
+    Ok(accepted.rows_affected == 1)
+}
+
+// Formatted as xxxx-xxxx-xxxx-xxxx for readability
+fn generate_recovery_code() -> String {
+    let raw = &generate_token()[..16];
+    format!("{}-{}-{}-{}", &raw[0..4], &raw[4..8], &raw[8..12], &raw[12..16])
+}
+
+fn normalize_recovery_code(code: &str) -> String {
+    code.trim().replace('-', "").to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP";
+
+    fn code_at_step_offset(offset: i64) -> String {
+        let totp = build_totp("Test", SECRET, "user@example.com").unwrap();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        totp.generate((now + offset * TOTP_STEP_SECS as i64) as u64)
+    }
+
+    fn check(code: &str) -> Option<u64> {
+        totp_step("Test", SECRET, "user@example.com", code).unwrap()
+    }
+
+    #[test]
+    fn accepts_current_code() {
+        assert!(check(&code_at_step_offset(0)).is_some());
+        assert!(check(&format!(" {} ", code_at_step_offset(0))).is_some());
+    }
+
+    #[test]
+    fn allows_one_step_of_skew() {
+        let current = check(&code_at_step_offset(0)).unwrap();
+        // Near a step boundary the neighbouring code may also be the current one
+        assert!(check(&code_at_step_offset(-1)).is_some_and(|step| step + 1 >= current));
+        assert!(check(&code_at_step_offset(1)).is_some_and(|step| step <= current + 1));
+    }
+
+    #[test]
+    fn rejects_codes_outside_the_window() {
+        assert_eq!(check(&code_at_step_offset(-3)), None);
+        assert_eq!(check(&code_at_step_offset(3)), None);
+        assert_eq!(check("not-a-code"), None);
+    }
+
+    #[test]
+    fn invalid_secret_is_an_error() {
+        assert!(build_totp("Test", "not base32!", "user@example.com").is_err());
+    }
+
+    #[test]
+    fn recovery_codes_are_grouped_and_normalized() {
+        let code = generate_recovery_code();
+        assert_eq!(code.len(), 19);
+        assert_eq!(code.matches('-').count(), 3);
+        assert_ne!(generate_recovery_code(), code);
+        assert_eq!(normalize_recovery_code(" ABCD-ef12-3456-7890 "), "abcdef1234567890");
+    }
+}
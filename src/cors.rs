@@ -0,0 +1,288 @@
+use actix_cors::Cors;
+use actix_web::http::{header::HeaderName, Method};
+use std::str::FromStr;
+
+use crate::config::CorsConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CorsPreset {
+    // Any localhost/127.0.0.1 port is allowed too, credentials on, short preflight cache
+    Development,
+    // Only the configured origins; `*` may not be combined with credentials
+    Production,
+}
+
+impl FromStr for CorsPreset {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "development" => Ok(Self::Development),
+            "production" => Ok(Self::Production),
+            _ => Err(()),
+        }
+    }
+}
+
+// `*`, an exact origin (`https://app.example.com`) or a wildcard subdomain
+// (`https://*.example.com`, which does not match the bare `https://example.com`)
+#[derive(Debug, Clone)]
+pub enum OriginPattern {
+    Any,
+    Exact(String),
+    Subdomain { scheme: String, suffix: String },
+    Localhost,
+}
+
+impl OriginPattern {
+    pub fn parse(pattern: &str) -> anyhow::Result<Self> {
+        if pattern == "*" {
+            return Ok(Self::Any);
+        }
+
+        let (scheme, host) = pattern
+            .split_once("://")
+            .filter(|(scheme, _)| *scheme == "http" || *scheme == "https")
+            .ok_or_else(|| anyhow::anyhow!("'{pattern}' must be \"*\" or an http(s) origin"))?;
+        if host.is_empty() || host.contains('/') {
+            anyhow::bail!("'{pattern}' must be an origin without a path");
+        }
+
+        match host.strip_prefix("*.") {
+            Some(suffix) if !suffix.is_empty() && !suffix.contains('*') => Ok(Self::Subdomain {
+                scheme: scheme.to_string(),
+                suffix: format!(".{suffix}"),
+            }),
+            Some(_) => anyhow::bail!("'{pattern}' has an invalid wildcard"),
+            None if host.contains('*') => {
+                anyhow::bail!("'{pattern}': wildcards are only allowed as the leftmost label, e.g. https://*.example.com")
+            }
+            None => Ok(Self::Exact(pattern.to_string())),
+        }
+    }
+
+    pub fn matches(&self, origin: &str) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Exact(exact) => origin.eq_ignore_ascii_case(exact),
+            Self::Subdomain { scheme, suffix } => origin
+                .strip_prefix(scheme.as_str())
+                .and_then(|rest| rest.strip_prefix("://"))
+                .is_some_and(|host| {
+                    host.len() > suffix.len()
+                        && host.to_ascii_lowercase().ends_with(&suffix.to_ascii_lowercase())
+                }),
+            Self::Localhost => ["http://localhost", "http://127.0.0.1"].iter().any(|base| {
+                origin.strip_prefix(base).is_some_and(|rest| {
+                    rest.is_empty()
+                        || rest
+                            .strip_prefix(':')
+                            .is_some_and(|port| !port.is_empty() && port.bytes().all(|b| b.is_ascii_digit()))
+                })
+            }),
+        }
+    }
+}
+
+impl CorsConfig {
+    pub fn origin_patterns(&self) -> anyhow::Result<Vec<OriginPattern>> {
+        let mut patterns = self
+            .allowed_origins
+            .iter()
+            .map(|origin| OriginPattern::parse(origin))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        if self.preset == CorsPreset::Development {
+            patterns.push(OriginPattern::Localhost);
+        }
+        Ok(patterns)
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let origins = self
+            .origin_patterns()
+            .map_err(|e| anyhow::anyhow!("cors.allowed_origins (CORS_ALLOWED_ORIGINS): {e}"))?;
+        if self.preset == CorsPreset::Production
+            && self.credentials_allowed()
+            && origins.iter().any(|origin| matches!(origin, OriginPattern::Any))
+        {
+            anyhow::bail!("cors.allowed_origins may not contain \"*\" when credentials are allowed in production");
+        }
+        for method in &self.allowed_methods {
+            Method::from_bytes(method.as_bytes())
+                .map_err(|_| anyhow::anyhow!("cors.allowed_methods: invalid method '{method}'"))?;
+        }
+        for name in self.allowed_headers.iter().chain(&self.expose_headers) {
+            HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| anyhow::anyhow!("cors: invalid header name '{name}'"))?;
+        }
+        Ok(())
+    }
+
+    pub fn credentials_allowed(&self) -> bool {
+        self.allow_credentials
+            .unwrap_or(self.preset == CorsPreset::Development)
+    }
+
+    pub fn preflight_max_age(&self) -> usize {
+        self.max_age_secs.unwrap_or(match self.preset {
+            CorsPreset::Development => 60,
+            CorsPreset::Production => 3600,
+        })
+    }
+}
+
+// Builds the CORS middleware; the config has already been validated at startup
+pub fn middleware(config: &CorsConfig) -> Cors {
+    let patterns = config.origin_patterns().unwrap_or_default();
+
+    let cors = Cors::default()
+        .allowed_origin_fn(move |origin, _| {
+            origin
+                .to_str()
+                .is_ok_and(|origin| patterns.iter().any(|pattern| pattern.matches(origin)))
+        })
+        .allowed_methods(config.allowed_methods.iter().map(String::as_str))
+        .allowed_headers(config.allowed_headers.iter().map(String::as_str))
+        .expose_headers(config.expose_headers.iter().map(String::as_str))
+        .max_age(config.preflight_max_age());
+
+    if config.credentials_allowed() {
+        cors.supports_credentials()
+    } else {
+        cors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{dev::ServiceResponse, http::header, http::StatusCode, test as actix_test, web, App, HttpResponse};
+
+    fn pattern(value: &str) -> OriginPattern {
+        OriginPattern::parse(value).unwrap()
+    }
+
+    fn config(preset: CorsPreset, origins: &[&str]) -> CorsConfig {
+        CorsConfig {
+            preset,
+            allowed_origins: origins.iter().map(|origin| origin.to_string()).collect(),
+            ..CorsConfig::default()
+        }
+    }
+
+    #[test]
+    fn exact_origin_matches_case_insensitively_only_itself() {
+        let exact = pattern("https://app.example.com");
+        assert!(exact.matches("https://app.example.com"));
+        assert!(exact.matches("HTTPS://APP.EXAMPLE.COM"));
+        assert!(!exact.matches("http://app.example.com"));
+        assert!(!exact.matches("https://app.example.com.evil.com"));
+    }
+
+    #[test]
+    fn subdomain_wildcard_does_not_match_the_bare_domain() {
+        let wildcard = pattern("https://*.example.com");
+        assert!(wildcard.matches("https://app.example.com"));
+        assert!(wildcard.matches("https://a.b.example.com"));
+        assert!(!wildcard.matches("https://example.com"));
+        assert!(!wildcard.matches("https://evilexample.com"));
+        assert!(!wildcard.matches("http://app.example.com"));
+    }
+
+    #[test]
+    fn ports_are_part_of_the_origin() {
+        assert!(pattern("http://localhost:3000").matches("http://localhost:3000"));
+        assert!(!pattern("http://localhost:3000").matches("http://localhost:4000"));
+        // A wildcard pattern without a port only covers the default port
+        assert!(!pattern("https://*.example.com").matches("https://app.example.com:8443"));
+    }
+
+    #[test]
+    fn development_allows_any_localhost_port() {
+        let localhost = OriginPattern::Localhost;
+        assert!(localhost.matches("http://localhost"));
+        assert!(localhost.matches("http://localhost:5173"));
+        assert!(localhost.matches("http://127.0.0.1:8080"));
+        assert!(!localhost.matches("http://localhost:"));
+        assert!(!localhost.matches("http://localhost.evil.com"));
+        assert!(!localhost.matches("https://localhost:3000"));
+
+        let patterns = config(CorsPreset::Development, &[]).origin_patterns().unwrap();
+        assert!(patterns.iter().any(|pattern| pattern.matches("http://localhost:5173")));
+        let patterns = config(CorsPreset::Production, &[]).origin_patterns().unwrap();
+        assert!(!patterns.iter().any(|pattern| pattern.matches("http://localhost:5173")));
+    }
+
+    #[test]
+    fn rejects_malformed_patterns() {
+        assert!(OriginPattern::parse("example.com").is_err());
+        assert!(OriginPattern::parse("https://example.com/path").is_err());
+        assert!(OriginPattern::parse("https://app.*.example.com").is_err());
+        assert!(OriginPattern::parse("https://*.").is_err());
+    }
+
+    #[test]
+    fn credentials_with_any_origin_are_rejected_in_production() {
+        let mut production = config(CorsPreset::Production, &["*"]);
+        assert!(production.validate().is_ok());
+        production.allow_credentials = Some(true);
+        assert!(production.validate().is_err());
+
+        let development = config(CorsPreset::Development, &["*"]);
+        assert!(development.credentials_allowed());
+        assert!(development.validate().is_ok());
+    }
+
+    async fn preflight(config: &CorsConfig, origin: &str) -> ServiceResponse {
+        let app = actix_test::init_service(
+            App::new()
+                .wrap(middleware(config))
+                .route("/api/games", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+        let req = actix_test::TestRequest::default()
+            .method(Method::OPTIONS)
+            .uri("/api/games")
+            .insert_header((header::ORIGIN, origin))
+            .insert_header((header::ACCESS_CONTROL_REQUEST_METHOD, "GET"))
+            .to_request();
+        actix_test::call_service(&app, req).await.map_into_boxed_body()
+    }
+
+    #[actix_web::test]
+    async fn preflight_allows_configured_origins_only() {
+        let config = config(CorsPreset::Production, &["https://app.example.com"]);
+
+        let res = preflight(&config, "https://app.example.com").await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(),
+            "https://app.example.com"
+        );
+        assert!(res.headers().get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS).is_none());
+
+        let res = preflight(&config, "https://evil.example.org").await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert!(res.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+    }
+
+    #[actix_web::test]
+    async fn preflight_max_age_follows_preset_and_override() {
+        let production = config(CorsPreset::Production, &["https://app.example.com"]);
+        let res = preflight(&production, "https://app.example.com").await;
+        assert_eq!(res.headers().get(header::ACCESS_CONTROL_MAX_AGE).unwrap(), "3600");
+
+        let development = config(CorsPreset::Development, &[]);
+        let res = preflight(&development, "http://localhost:5173").await;
+        assert_eq!(res.headers().get(header::ACCESS_CONTROL_MAX_AGE).unwrap(), "60");
+        assert_eq!(res.headers().get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS).unwrap(), "true");
+
+        let overridden = CorsConfig {
+            max_age_secs: Some(600),
+            ..production
+        };
+        let res = preflight(&overridden, "https://app.example.com").await;
+        assert_eq!(res.headers().get(header::ACCESS_CONTROL_MAX_AGE).unwrap(), "600");
+    }
+}
@@ -1,7 +1,16 @@
-use sea_orm::{Database, DatabaseConnection, DbErr};
-use std::env;
+use sea_orm::{ConnectOptions, Database, DatabaseConnection, DbErr};
+use std::time::Duration;
 
-pub async fn connect() -> Result<DatabaseConnection, DbErr> {
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    Database::connect(&database_url).await
+use crate::config::DatabaseConfig;
+
+pub async fn connect(config: &DatabaseConfig) -> Result<DatabaseConnection, DbErr> {
+    let mut options = ConnectOptions::new(config.url.clone());
+    options
+        .max_connections(config.max_connections)
+        .min_connections(config.min_connections)
+        .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+        .idle_timeout(Duration::from_secs(config.idle_timeout_secs))
+        // Every statement is logged; keep them out of the default `info` output
+        .sqlx_logging_level(log::LevelFilter::Debug);
+    Database::connect(options).await
 }
@@ -7,9 +7,22 @@ pub struct CreateCreator {
     pub email: String,
 }
 
+// Full replacement for PUT, and the result a PATCH must produce
 #[derive(Deserialize)]
 pub struct UpdateCreator {
-    pub first_name: Option<String>,
-    pub last_name: Option<String>,
-    pub email: Option<String>,
+    pub first_name: String,
+    pub last_name: String,
+    pub email: String,
+}
+
+impl UpdateCreator {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.first_name.trim().is_empty() || self.last_name.trim().is_empty() {
+            return Err("first_name and last_name must not be empty".to_string());
+        }
+        if !self.email.contains('@') {
+            return Err("email must be an email address".to_string());
+        }
+        Ok(())
+    }
 }
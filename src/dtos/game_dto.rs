@@ -9,10 +9,23 @@ pub struct CreateGame {
     pub creator_id: Uuid,
 }
 
+// Full replacement for PUT, and the result a PATCH must produce
 #[derive(Deserialize)]
 pub struct UpdateGame {
-    pub name: Option<String>,
-    pub description: Option<String>,
-    pub genre: Option<String>,
-    pub creator_id: Option<Uuid>,
+    pub name: String,
+    pub description: String,
+    pub genre: String,
+    pub creator_id: Uuid,
+}
+
+impl UpdateGame {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("name must not be empty".to_string());
+        }
+        if self.genre.trim().is_empty() {
+            return Err("genre must not be empty".to_string());
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,26 @@
+use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub struct CreateInvitationRequest {
+    pub email: String,
+    pub role: String,
+}
+
+#[derive(Serialize)]
+pub struct InvitationResponse {
+    pub id: Uuid,
+    pub email: String,
+    pub role: String,
+    pub token: String,
+    pub expires_at: DateTime<FixedOffset>,
+}
+
+#[derive(Deserialize)]
+pub struct AcceptInviteRequest {
+    pub token: String,
+    pub password: String,
+    pub first_name: String,
+    pub last_name: String,
+}
@@ -1,6 +1,12 @@
 pub mod creator_dto;
 pub mod game_dto;
+pub mod health_dto;
 pub mod auth_dto;
+pub mod api_key_dto;
+pub mod invitation_dto;
+pub mod session_dto;
+pub mod trace_dto;
+pub mod two_factor_dto;
 
 pub use creator_dto::{CreateCreator, UpdateCreator};
 pub use game_dto::{CreateGame, UpdateGame};
@@ -0,0 +1,16 @@
+use chrono::{DateTime, FixedOffset};
+use serde::Serialize;
+use uuid::Uuid;
+
+#[derive(Serialize)]
+pub struct SessionResponse {
+    pub id: Uuid,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub created_at: DateTime<FixedOffset>,
+    pub last_seen_at: DateTime<FixedOffset>,
+    pub expires_at: DateTime<FixedOffset>,
+    pub current: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub impersonator_id: Option<Uuid>, // an admin is acting as this user through the session
+}
@@ -0,0 +1,21 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Deserialize)]
+pub struct TraceQuery {
+    pub trace_id: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct SpanResponse {
+    pub trace_id: String,
+    pub span_id: String,
+    pub parent_span_id: Option<String>,
+    pub name: String,
+    pub kind: String,
+    pub start_time: DateTime<Utc>,
+    pub duration_ms: f64,
+    pub status: String,
+    pub attributes: BTreeMap<String, String>,
+}
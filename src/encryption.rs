@@ -0,0 +1,158 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::sync::OnceLock;
+use uuid::Uuid;
+
+use crate::config::MfaConfig;
+
+// Marks values sealed by `SecretBox`; anything else in the column is a legacy plaintext value
+const SEALED_PREFIX: &str = "v1:";
+
+static TOTP_KEY: OnceLock<SecretBox> = OnceLock::new();
+
+// AES-256-GCM for secrets that have to be read back (TOTP seeds), unlike passwords and tokens
+// which are only ever hashed. Each value is bound to its owner's id, so a ciphertext copied
+// onto another user's row does not decrypt.
+pub struct SecretBox {
+    key: LessSafeKey,
+    rng: SystemRandom,
+}
+
+impl SecretBox {
+    // `encoded` is 32 random bytes, base64 encoded (e.g. `openssl rand -base64 32`)
+    pub fn from_base64(encoded: &str) -> anyhow::Result<Self> {
+        let bytes = STANDARD
+            .decode(encoded.trim())
+            .map_err(|_| anyhow::anyhow!("key is not valid base64"))?;
+        let key = UnboundKey::new(&AES_256_GCM, &bytes)
+            .map_err(|_| anyhow::anyhow!("key must be 32 bytes, got {}", bytes.len()))?;
+
+        Ok(Self {
+            key: LessSafeKey::new(key),
+            rng: SystemRandom::new(),
+        })
+    }
+
+    pub fn seal(&self, owner: Uuid, plaintext: &str) -> anyhow::Result<String> {
+        let mut nonce = [0u8; NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .map_err(|_| anyhow::anyhow!("random nonce generation failed"))?;
+
+        let mut data = plaintext.as_bytes().to_vec();
+        self.key
+            .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(owner.as_bytes()), &mut data)
+            .map_err(|_| anyhow::anyhow!("encryption failed"))?;
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&data);
+        Ok(format!("{SEALED_PREFIX}{}", STANDARD.encode(sealed)))
+    }
+
+    pub fn open(&self, owner: Uuid, sealed: &str) -> anyhow::Result<String> {
+        let encoded = sealed
+            .strip_prefix(SEALED_PREFIX)
+            .ok_or_else(|| anyhow::anyhow!("value is not encrypted"))?;
+        let bytes = STANDARD
+            .decode(encoded)
+            .map_err(|_| anyhow::anyhow!("encrypted value is not valid base64"))?;
+        if bytes.len() < NONCE_LEN {
+            anyhow::bail!("encrypted value is too short");
+        }
+
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| anyhow::anyhow!("invalid nonce"))?;
+        let mut data = ciphertext.to_vec();
+        let plaintext = self
+            .key
+            .open_in_place(nonce, Aad::from(owner.as_bytes()), &mut data)
+            .map_err(|_| anyhow::anyhow!("decryption failed (wrong key or owner)"))?;
+
+        Ok(String::from_utf8(plaintext.to_vec())?)
+    }
+}
+
+pub fn is_sealed(value: &str) -> bool {
+    value.starts_with(SEALED_PREFIX)
+}
+
+pub fn init(config: &MfaConfig) -> anyhow::Result<()> {
+    let key = config
+        .totp_encryption_key
+        .as_deref()
+        .ok_or_else(|| anyhow::anyhow!("TOTP_ENCRYPTION_KEY must be set"))?;
+    let secret_box = SecretBox::from_base64(key)?;
+    TOTP_KEY
+        .set(secret_box)
+        .map_err(|_| anyhow::anyhow!("TOTP encryption key already initialized"))
+}
+
+// Key for `users.totp_secret`
+pub fn totp() -> &'static SecretBox {
+    TOTP_KEY.get().expect("TOTP encryption key not initialized")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=";
+
+    #[test]
+    fn round_trips_for_the_same_owner() {
+        let secret_box = SecretBox::from_base64(KEY).unwrap();
+        let owner = Uuid::new_v4();
+        let sealed = secret_box.seal(owner, "JBSWY3DPEHPK3PXP").unwrap();
+        assert!(is_sealed(&sealed));
+        assert!(!sealed.contains("JBSWY3DPEHPK3PXP"));
+        assert_eq!(secret_box.open(owner, &sealed).unwrap(), "JBSWY3DPEHPK3PXP");
+    }
+
+    #[test]
+    fn nonce_is_random() {
+        let secret_box = SecretBox::from_base64(KEY).unwrap();
+        let owner = Uuid::new_v4();
+        assert_ne!(secret_box.seal(owner, "secret").unwrap(), secret_box.seal(owner, "secret").unwrap());
+    }
+
+    #[test]
+    fn value_moved_to_another_owner_does_not_open() {
+        let secret_box = SecretBox::from_base64(KEY).unwrap();
+        let sealed = secret_box.seal(Uuid::new_v4(), "secret").unwrap();
+        assert!(secret_box.open(Uuid::new_v4(), &sealed).is_err());
+    }
+
+    #[test]
+    fn wrong_key_does_not_open() {
+        let owner = Uuid::new_v4();
+        let sealed = SecretBox::from_base64(KEY).unwrap().seal(owner, "secret").unwrap();
+        let other = SecretBox::from_base64(&STANDARD.encode([7u8; 32])).unwrap();
+        assert!(other.open(owner, &sealed).is_err());
+    }
+
+    #[test]
+    fn tampered_value_does_not_open() {
+        let secret_box = SecretBox::from_base64(KEY).unwrap();
+        let owner = Uuid::new_v4();
+        let sealed = secret_box.seal(owner, "secret").unwrap();
+        let mut bytes = STANDARD.decode(&sealed[SEALED_PREFIX.len()..]).unwrap();
+        *bytes.last_mut().unwrap() ^= 1;
+        let tampered = format!("{SEALED_PREFIX}{}", STANDARD.encode(bytes));
+        assert!(secret_box.open(owner, &tampered).is_err());
+    }
+
+    #[test]
+    fn plaintext_is_not_sealed() {
+        let secret_box = SecretBox::from_base64(KEY).unwrap();
+        assert!(!is_sealed("JBSWY3DPEHPK3PXP"));
+        assert!(secret_box.open(Uuid::new_v4(), "JBSWY3DPEHPK3PXP").is_err());
+    }
+
+    #[test]
+    fn rejects_short_or_invalid_keys() {
+        assert!(SecretBox::from_base64("not base64!").is_err());
+        assert!(SecretBox::from_base64(&STANDARD.encode([0u8; 16])).is_err());
+    }
+}
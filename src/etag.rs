@@ -0,0 +1,121 @@
+use actix_web::http::header::{self, EntityTag, Header, IfMatch, IfNoneMatch};
+use actix_web::{HttpRequest, HttpResponse};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+// Strong validator over the JSON representation, so it changes with any field (updated_at included)
+pub fn entity_tag<T: Serialize>(model: &T) -> EntityTag {
+    let body = serde_json::to_vec(model).unwrap_or_default();
+    EntityTag::new_strong(hex::encode(&Sha256::digest(&body)[..16]))
+}
+
+// 200 with the model as JSON and its ETag
+pub fn ok<T: Serialize>(model: &T) -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header(header::ETag(entity_tag(model)))
+        .json(model)
+}
+
+// 201 for a new resource at `location`, with its ETag so the client can edit it right away
+pub fn created<T: Serialize>(location: String, model: &T) -> HttpResponse {
+    HttpResponse::Created()
+        .insert_header((header::LOCATION, location))
+        .insert_header(header::ETag(entity_tag(model)))
+        .json(model)
+}
+
+// GET with If-None-Match: the client's copy is still current (weak comparison, RFC 9110 13.1.2)
+pub fn is_fresh(req: &HttpRequest, etag: &EntityTag) -> bool {
+    match IfNoneMatch::parse(req) {
+        Ok(IfNoneMatch::Any) => true,
+        Ok(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(etag)),
+        Err(_) => false,
+    }
+}
+
+pub fn not_modified(etag: EntityTag) -> HttpResponse {
+    HttpResponse::NotModified().insert_header(header::ETag(etag)).finish()
+}
+
+// The client sent If-Match, so the write must only apply to the version it has seen
+pub fn has_precondition(req: &HttpRequest) -> bool {
+    req.headers().contains_key(header::IF_MATCH)
+}
+
+// PUT/DELETE with If-Match: the client edited the current version (strong comparison).
+// Requests without the header are unconditional.
+pub fn precondition_holds(req: &HttpRequest, etag: &EntityTag) -> bool {
+    if !has_precondition(req) {
+        return true;
+    }
+    match IfMatch::parse(req) {
+        Ok(IfMatch::Any) => true,
+        Ok(IfMatch::Items(tags)) => tags.iter().any(|tag| tag.strong_eq(etag)),
+        Err(_) => false,
+    }
+}
+
+pub fn precondition_failed() -> HttpResponse {
+    HttpResponse::PreconditionFailed().body("Resource has been modified (If-Match does not match the current ETag)")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+    use serde_json::json;
+
+    fn request(name: header::HeaderName, value: &str) -> HttpRequest {
+        TestRequest::get().insert_header((name, value)).to_http_request()
+    }
+
+    fn current() -> EntityTag {
+        entity_tag(&json!({ "id": 1, "name": "Tetris" }))
+    }
+
+    #[test]
+    fn entity_tag_is_strong_and_follows_the_content() {
+        let etag = current();
+        assert!(!etag.weak);
+        assert_eq!(etag, current());
+        assert_ne!(etag, entity_tag(&json!({ "id": 1, "name": "Tetris 2" })));
+    }
+
+    #[test]
+    fn if_none_match_star_matches_any_version() {
+        assert!(is_fresh(&request(header::IF_NONE_MATCH, "*"), &current()));
+    }
+
+    #[test]
+    fn if_none_match_uses_weak_comparison() {
+        let tag = current().tag().to_string();
+        assert!(is_fresh(&request(header::IF_NONE_MATCH, &format!("\"{tag}\"")), &current()));
+        assert!(is_fresh(&request(header::IF_NONE_MATCH, &format!("W/\"{tag}\"")), &current()));
+        assert!(is_fresh(&request(header::IF_NONE_MATCH, &format!("\"other\", W/\"{tag}\"")), &current()));
+        assert!(!is_fresh(&request(header::IF_NONE_MATCH, "\"other\""), &current()));
+        assert!(!is_fresh(&TestRequest::get().to_http_request(), &current()));
+    }
+
+    #[test]
+    fn if_match_uses_strong_comparison() {
+        let tag = current().tag().to_string();
+        assert!(precondition_holds(&request(header::IF_MATCH, &format!("\"{tag}\"")), &current()));
+        assert!(precondition_holds(&request(header::IF_MATCH, &format!("\"other\", \"{tag}\"")), &current()));
+        assert!(precondition_holds(&request(header::IF_MATCH, "*"), &current()));
+        // A weak validator never satisfies If-Match, so the write gets 412
+        assert!(!precondition_holds(&request(header::IF_MATCH, &format!("W/\"{tag}\"")), &current()));
+        assert!(!precondition_holds(&request(header::IF_MATCH, "\"other\""), &current()));
+        assert_eq!(precondition_failed().status(), actix_web::http::StatusCode::PRECONDITION_FAILED);
+    }
+
+    #[test]
+    fn requests_without_if_match_are_unconditional() {
+        let req = TestRequest::put().to_http_request();
+        assert!(!has_precondition(&req));
+        assert!(precondition_holds(&req, &current()));
+        // A malformed header is still a precondition, and it cannot hold
+        let req = request(header::IF_MATCH, "not-a-tag");
+        assert!(has_precondition(&req));
+        assert!(!precondition_holds(&req, &current()));
+    }
+}
@@ -0,0 +1,111 @@
+use chrono::{SecondsFormat, Utc};
+use log::kv::{self, Key, Value, VisitSource, VisitValue};
+use serde_json::{json, Map};
+use std::future::Future;
+use std::io::Write;
+
+use crate::telemetry;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+// One JSON object per line on stderr. The level filter comes from RUST_LOG (default `info`).
+// Key-values passed to the log macros (`log::info!(user_id = id; "...")`) become fields.
+pub fn init() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
+        .format(|buf, record| {
+            let mut line = Map::new();
+            line.insert(
+                "timestamp".to_string(),
+                json!(Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)),
+            );
+            line.insert("level".to_string(), json!(record.level().as_str()));
+            line.insert("target".to_string(), json!(record.target()));
+            line.insert("message".to_string(), json!(record.args().to_string()));
+            if let Some(request_id) = current_request_id() {
+                line.insert("request_id".to_string(), json!(request_id));
+            }
+            if let Some((trace_id, span_id)) = telemetry::current_trace_ids() {
+                line.insert("trace_id".to_string(), json!(trace_id));
+                line.insert("span_id".to_string(), json!(span_id));
+            }
+            let _ = record.key_values().visit(&mut Fields(&mut line));
+
+            writeln!(buf, "{}", serde_json::Value::Object(line))
+        })
+        .init();
+}
+
+// Runs `future` with `request_id` attached to every log line it emits
+pub async fn with_request_id<F: Future>(request_id: String, future: F) -> F::Output {
+    REQUEST_ID.scope(request_id, future).await
+}
+
+// `actix_web::rt::spawn` that keeps the current request ID for background work such as mail delivery
+pub fn spawn<F: Future + 'static>(future: F) {
+    match current_request_id() {
+        Some(request_id) => {
+            actix_web::rt::spawn(with_request_id(request_id, future));
+        }
+        None => {
+            actix_web::rt::spawn(future);
+        }
+    }
+}
+
+// Set while a request is being handled; work moved to another thread or task does not inherit it
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+struct Fields<'a>(&'a mut Map<String, serde_json::Value>);
+
+impl<'kvs> VisitSource<'kvs> for Fields<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        let mut field = Field(serde_json::Value::Null);
+        value.visit(&mut field)?;
+        self.0.insert(key.to_string(), field.0);
+        Ok(())
+    }
+}
+
+// Keeps numbers and booleans typed; `None` becomes null and anything else its Display form
+struct Field(serde_json::Value);
+
+impl<'v> VisitValue<'v> for Field {
+    fn visit_any(&mut self, value: Value) -> Result<(), kv::Error> {
+        self.0 = json!(value.to_string());
+        Ok(())
+    }
+
+    fn visit_null(&mut self) -> Result<(), kv::Error> {
+        self.0 = serde_json::Value::Null;
+        Ok(())
+    }
+
+    fn visit_u64(&mut self, value: u64) -> Result<(), kv::Error> {
+        self.0 = json!(value);
+        Ok(())
+    }
+
+    fn visit_i64(&mut self, value: i64) -> Result<(), kv::Error> {
+        self.0 = json!(value);
+        Ok(())
+    }
+
+    fn visit_f64(&mut self, value: f64) -> Result<(), kv::Error> {
+        self.0 = json!(value);
+        Ok(())
+    }
+
+    fn visit_bool(&mut self, value: bool) -> Result<(), kv::Error> {
+        self.0 = json!(value);
+        Ok(())
+    }
+
+    fn visit_str(&mut self, value: &str) -> Result<(), kv::Error> {
+        self.0 = json!(value);
+        Ok(())
+    }
+}
@@ -0,0 +1,286 @@
+use actix_web::{http::header, HttpResponse};
+use chrono::{DateTime, FixedOffset, Utc};
+use sea_orm::sea_query::Expr;
+use sea_orm::{ColumnTrait, ConnectionTrait, DbErr, EntityTrait, QueryFilter};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+use crate::config::LoginThrottleConfig;
+use crate::models::user;
+use crate::security_events;
+
+// Thresholds for failed logins, from `Config.login_throttle`
+#[derive(Clone, Debug)]
+pub struct LockoutPolicy {
+    pub max_account_attempts: u32,
+    pub max_ip_attempts: u32,
+    pub ip_window: Duration,
+    pub base_lockout: Duration,
+    pub max_lockout: Duration,
+}
+
+impl LockoutPolicy {
+    pub fn new(config: &LoginThrottleConfig) -> Self {
+        Self {
+            max_account_attempts: config.max_account_attempts,
+            max_ip_attempts: config.max_ip_attempts,
+            ip_window: Duration::from_secs(config.ip_window_secs),
+            base_lockout: Duration::from_secs(config.lockout_base_secs),
+            max_lockout: Duration::from_secs(config.lockout_max_secs),
+        }
+    }
+
+    // Exponential backoff: base, 2x base, 4x base, ... once `max` failures are reached
+    pub fn lockout_for(&self, failures: u32, max: u32) -> Option<Duration> {
+        if failures < max {
+            return None;
+        }
+        let exponent = (failures - max).min(16);
+        Some(self.base_lockout.saturating_mul(1 << exponent).min(self.max_lockout))
+    }
+}
+
+struct IpState {
+    failures: u32,
+    window_start: Instant,
+    blocked_until: Option<Instant>,
+}
+
+// Per-IP failure tracking, kept in memory for this process
+pub struct LoginThrottle {
+    policy: LockoutPolicy,
+    ips: Mutex<HashMap<String, IpState>>,
+}
+
+impl LoginThrottle {
+    pub fn new(policy: LockoutPolicy) -> Self {
+        Self {
+            policy,
+            ips: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn policy(&self) -> &LockoutPolicy {
+        &self.policy
+    }
+
+    // Remaining block time for `ip`, if it is currently blocked
+    pub fn ip_retry_after(&self, ip: &str) -> Option<Duration> {
+        let ips = self.ips.lock().unwrap();
+        let blocked_until = ips.get(ip)?.blocked_until?;
+        blocked_until.checked_duration_since(Instant::now())
+    }
+
+    // Returns the block duration when this failure blocks the IP
+    pub fn record_ip_failure(&self, ip: &str) -> Option<Duration> {
+        let now = Instant::now();
+        let mut ips = self.ips.lock().unwrap();
+        ips.retain(|_, state| {
+            now.duration_since(state.window_start) < self.policy.ip_window
+                || state.blocked_until.is_some_and(|until| until > now)
+        });
+
+        let state = ips.entry(ip.to_string()).or_insert(IpState {
+            failures: 0,
+            window_start: now,
+            blocked_until: None,
+        });
+        state.failures += 1;
+
+        let lockout = self
+            .policy
+            .lockout_for(state.failures, self.policy.max_ip_attempts);
+        if let Some(lockout) = lockout {
+            state.blocked_until = Some(now + lockout);
+        }
+        lockout
+    }
+}
+
+pub fn account_retry_after(user: &user::Model) -> Option<Duration> {
+    let locked_until = user.locked_until?;
+    (locked_until.with_timezone(&Utc) - Utc::now()).to_std().ok()
+}
+
+// Increments the account's failure counter, locking it once the threshold is hit.
+// The lock is computed from the count the increment returned, so parallel failures each see
+// their own attempt number, and an existing lock is only ever extended, never shortened.
+pub async fn record_account_failure<C: ConnectionTrait>(
+    db: &C,
+    user: &user::Model,
+    policy: &LockoutPolicy,
+) -> Result<Option<DateTime<FixedOffset>>, DbErr> {
+    let updated = user::Entity::update_many()
+        .col_expr(
+            user::Column::FailedLoginAttempts,
+            Expr::col(user::Column::FailedLoginAttempts).add(1),
+        )
+        .filter(user::Column::Id.eq(user.id))
+        .exec_with_returning(db)
+        .await?;
+    let Some(updated) = updated.into_iter().next() else {
+        return Ok(None);
+    };
+
+    let failures = updated.failed_login_attempts.max(0) as u32;
+    let Some(lockout) = policy
+        .lockout_for(failures, policy.max_account_attempts)
+        .and_then(|lockout| chrono::Duration::from_std(lockout).ok())
+    else {
+        return Ok(None);
+    };
+    let locked_until = DateTime::<FixedOffset>::from(Utc::now() + lockout);
+
+    let locked = user::Entity::update_many()
+        .col_expr(
+            user::Column::LockedUntil,
+            Expr::cust_with_values("GREATEST(\"locked_until\", $1)", [locked_until]),
+        )
+        .filter(user::Column::Id.eq(user.id))
+        .exec_with_returning(db)
+        .await?;
+
+    Ok(locked.into_iter().next().and_then(|user| user.locked_until))
+}
+
+pub async fn reset_account<C: ConnectionTrait>(db: &C, user_id: Uuid) -> Result<(), DbErr> {
+    user::Entity::update_many()
+        .col_expr(user::Column::FailedLoginAttempts, Expr::value(0))
+        .col_expr(
+            user::Column::LockedUntil,
+            Expr::value(Option::<DateTime<FixedOffset>>::None),
+        )
+        .filter(user::Column::Id.eq(user_id))
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
+
+pub fn too_many_attempts(retry_after: Duration) -> HttpResponse {
+    let seconds = retry_after.as_secs().max(1);
+    HttpResponse::TooManyRequests()
+        .insert_header((header::RETRY_AFTER, seconds.to_string()))
+        .json(json!({
+            "error": "Too many failed login attempts, try again later",
+            "retry_after": seconds
+        }))
+}
+
+// Records a failed login for the account (if known) and the client IP.
+// Answers 429 when this failure triggered a lockout, otherwise 401 with `message`.
+pub async fn handle_failure<C: ConnectionTrait>(
+    db: &C,
+    throttle: &LoginThrottle,
+    user: Option<&user::Model>,
+    ip: Option<&str>,
+    message: &'static str,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user.map(|user| user.id);
+    security_events::record(db, user_id, security_events::LOGIN_FAILED, ip, None).await;
+
+    let mut retry_after = None;
+
+    if let Some(ip) = ip {
+        if let Some(lockout) = throttle.record_ip_failure(ip) {
+            security_events::record(
+                db,
+                None,
+                security_events::IP_BLOCKED,
+                Some(ip),
+                Some(json!({ "seconds": lockout.as_secs() })),
+            )
+            .await;
+            retry_after = Some(lockout);
+        }
+    }
+
+    if let Some(user) = user {
+        let locked_until = record_account_failure(db, user, throttle.policy())
+            .await
+            .map_err(|e| {
+                log::error!(error:% = e; "Database error");
+                actix_web::error::ErrorInternalServerError("Database error")
+            })?;
+
+        if let Some(locked_until) = locked_until {
+            security_events::record(
+                db,
+                user_id,
+                security_events::ACCOUNT_LOCKED,
+                ip,
+                Some(json!({ "locked_until": locked_until })),
+            )
+            .await;
+            let lockout = (locked_until.with_timezone(&Utc) - Utc::now())
+                .to_std()
+                .unwrap_or_default();
+            retry_after = retry_after.max(Some(lockout));
+        }
+    }
+
+    match retry_after {
+        Some(retry_after) => Ok(too_many_attempts(retry_after)),
+        None => Err(actix_web::error::ErrorUnauthorized(message)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> LockoutPolicy {
+        LockoutPolicy {
+            max_account_attempts: 5,
+            max_ip_attempts: 20,
+            ip_window: Duration::from_secs(900),
+            base_lockout: Duration::from_secs(60),
+            max_lockout: Duration::from_secs(3600),
+        }
+    }
+
+    #[test]
+    fn no_lockout_below_threshold() {
+        let policy = policy();
+        assert_eq!(policy.lockout_for(0, 5), None);
+        assert_eq!(policy.lockout_for(4, 5), None);
+    }
+
+    #[test]
+    fn lockout_starts_at_base_on_threshold() {
+        assert_eq!(policy().lockout_for(5, 5), Some(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn lockout_doubles_per_failure_past_threshold() {
+        let policy = policy();
+        assert_eq!(policy.lockout_for(6, 5), Some(Duration::from_secs(120)));
+        assert_eq!(policy.lockout_for(7, 5), Some(Duration::from_secs(240)));
+        assert_eq!(policy.lockout_for(10, 5), Some(Duration::from_secs(1920)));
+    }
+
+    #[test]
+    fn lockout_is_capped_at_max() {
+        let policy = policy();
+        assert_eq!(policy.lockout_for(11, 5), Some(Duration::from_secs(3600)));
+        assert_eq!(policy.lockout_for(u32::MAX, 5), Some(Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn zero_threshold_locks_immediately() {
+        assert_eq!(policy().lockout_for(0, 0), Some(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn ip_failures_block_after_threshold() {
+        let throttle = LoginThrottle::new(LockoutPolicy { max_ip_attempts: 2, ..policy() });
+        assert_eq!(throttle.record_ip_failure("203.0.113.7"), None);
+        assert_eq!(throttle.ip_retry_after("203.0.113.7"), None);
+        assert_eq!(throttle.record_ip_failure("203.0.113.7"), Some(Duration::from_secs(60)));
+        assert!(throttle.ip_retry_after("203.0.113.7").is_some());
+        assert_eq!(throttle.ip_retry_after("198.51.100.9"), None);
+    }
+}
@@ -0,0 +1,217 @@
+use async_trait::async_trait;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::config::MailConfig;
+
+// Has no default: the log transport prints password reset, email verification and email change
+// links, so it must be chosen on purpose
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MailTransport {
+    Smtp,
+    File,
+    Log,
+}
+
+impl FromStr for MailTransport {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "smtp" => Ok(Self::Smtp),
+            "file" => Ok(Self::File),
+            "log" => Ok(Self::Log),
+            _ => Err(()),
+        }
+    }
+}
+
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: Email) -> anyhow::Result<()>;
+}
+
+// Delivers mail through an SMTP relay (STARTTLS)
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    pub fn new(
+        host: &str,
+        port: u16,
+        username: Option<String>,
+        password: Option<String>,
+        from: &str,
+    ) -> anyhow::Result<Self> {
+        let mut builder = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?.port(port);
+        if let (Some(username), Some(password)) = (username, password) {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+            from: from.parse()?,
+        })
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, email: Email) -> anyhow::Result<()> {
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(email.to.parse()?)
+            .subject(email.subject)
+            .body(email.body)?;
+
+        self.transport.send(message).await?;
+        Ok(())
+    }
+}
+
+// Local development / tests: writes each mail to a file in `dir`,
+// or only logs it when no directory is configured
+pub struct FileMailer {
+    dir: Option<PathBuf>,
+}
+
+impl FileMailer {
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self { dir }
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, email: Email) -> anyhow::Result<()> {
+        let content = format!(
+            "To: {}\nSubject: {}\n\n{}\n",
+            email.to, email.subject, email.body
+        );
+
+        match &self.dir {
+            Some(dir) => {
+                tokio::fs::create_dir_all(dir).await?;
+                let path = dir.join(format!("{}.eml", Uuid::new_v4()));
+                tokio::fs::write(&path, content).await?;
+                log::info!(to = email.to.as_str(), path:% = path.display(); "Mail written to file");
+            }
+            None => log::info!(to = email.to.as_str(); "{content}"),
+        }
+
+        Ok(())
+    }
+}
+
+pub fn from_config(config: &MailConfig) -> anyhow::Result<Arc<dyn Mailer>> {
+    let transport = config
+        .transport
+        .ok_or_else(|| anyhow::anyhow!("mail.transport (MAIL_TRANSPORT) must be set"))?;
+
+    match transport {
+        MailTransport::Smtp => {
+            let host = config
+                .smtp_host
+                .as_deref()
+                .ok_or_else(|| anyhow::anyhow!("mail.smtp_host (SMTP_HOST) must be set for the smtp transport"))?;
+            Ok(Arc::new(SmtpMailer::new(
+                host,
+                config.smtp_port,
+                config.smtp_username.clone(),
+                config.smtp_password.clone(),
+                &config.from,
+            )?))
+        }
+        MailTransport::File => Ok(Arc::new(FileMailer::new(Some(config.dir.clone())))),
+        MailTransport::Log => {
+            log::warn!("MAIL_TRANSPORT=log writes password reset, email verification and email change links to the log; use it for local development only");
+            Ok(Arc::new(FileMailer::new(None)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(transport: Option<MailTransport>) -> MailConfig {
+        MailConfig {
+            transport,
+            ..MailConfig::default()
+        }
+    }
+
+    #[test]
+    fn parses_transport_names() {
+        assert_eq!("smtp".parse(), Ok(MailTransport::Smtp));
+        assert_eq!("file".parse(), Ok(MailTransport::File));
+        assert_eq!("log".parse(), Ok(MailTransport::Log));
+        assert_eq!("sendmail".parse::<MailTransport>(), Err(()));
+    }
+
+    #[test]
+    fn transport_is_required() {
+        let error = from_config(&config(None)).err().unwrap();
+        assert_eq!(error.to_string(), "mail.transport (MAIL_TRANSPORT) must be set");
+    }
+
+    #[test]
+    fn smtp_requires_host() {
+        let error = from_config(&config(Some(MailTransport::Smtp))).err().unwrap();
+        assert_eq!(error.to_string(), "mail.smtp_host (SMTP_HOST) must be set for the smtp transport");
+    }
+
+    #[test]
+    fn smtp_rejects_invalid_sender() {
+        let config = MailConfig {
+            smtp_host: Some("smtp.example.com".to_string()),
+            from: "not an address".to_string(),
+            ..config(Some(MailTransport::Smtp))
+        };
+        assert!(from_config(&config).is_err());
+    }
+
+    #[actix_web::test]
+    async fn file_transport_writes_one_eml_per_mail() {
+        let dir = std::env::temp_dir().join(format!("mailer-test-{}", Uuid::new_v4()));
+        let mailer = from_config(&MailConfig {
+            dir: dir.clone(),
+            ..config(Some(MailTransport::File))
+        })
+        .unwrap();
+
+        mailer
+            .send(Email {
+                to: "user@example.com".to_string(),
+                subject: "Reset your password".to_string(),
+                body: "https://app.example.com/reset?token=abc".to_string(),
+            })
+            .await
+            .unwrap();
+
+        let files: Vec<_> = std::fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().path()).collect();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].extension().unwrap(), "eml");
+        let content = std::fs::read_to_string(&files[0]).unwrap();
+        assert_eq!(
+            content,
+            "To: user@example.com\nSubject: Reset your password\n\nhttps://app.example.com/reset?token=abc\n"
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,30 +1,210 @@
-use actix_web::{App, HttpServer};
+use actix_web::{http::KeepAlive, middleware::DefaultHeaders, web, App, HttpServer};
+use std::time::Duration;
 use dotenv::dotenv;
-use std::env;
 
+mod client_ip;
+mod config;
+mod cors;
 mod database;
+mod encryption;
+mod etag;
+mod jwt;
+mod logging;
+mod login_throttle;
+mod mailer;
+mod metrics;
+mod password;
+mod patch;
+mod rate_limit;
 mod routes;
+mod security_events;
+mod server;
+mod telemetry;
+mod tls;
 mod controllers;
 mod models;
 mod dtos;
 mod middleware;
+mod oidc;
+mod tokens;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
+    logging::init();
 
-    let db = database::connect().await.expect("Failed to connect to database");
+    let config = config::Config::load().expect("Invalid configuration");
+    let _telemetry = telemetry::init(&config.tracing).expect("Failed to initialize tracing");
 
-    let port = env::var("PORT").unwrap_or_else(|_| "8080".to_string());
+    jwt::init(&config.jwt).expect("Failed to load JWT keys");
+    password::init(&config.password_hashing).expect("Invalid password hashing configuration");
+    encryption::init(&config.mfa).expect("Invalid TOTP encryption key");
 
-    println!("Server running at http://localhost:{port}");
+    let db = database::connect(&config.database).await.expect("Failed to connect to database");
+    middleware::idempotency::spawn_cleanup(db.clone());
+    controllers::two_factor_controller::encrypt_legacy_secrets(&db)
+        .await
+        .expect("Failed to encrypt stored TOTP secrets");
+    let mailer = mailer::from_config(&config.mail).expect("Failed to configure mailer");
+    let login_throttle = web::Data::new(login_throttle::LoginThrottle::new(
+        login_throttle::LockoutPolicy::new(&config.login_throttle),
+    ));
+
+    let pending_rehashes = web::Data::new(password::PendingRehashes::new(Duration::from_secs(
+        config.tokens.mfa_challenge_minutes as u64 * 60,
+    )));
+
+    let oidc = oidc::OidcConfig::from_config(&config.oidc)
+        .map(|config| web::Data::new(oidc::OidcProvider::new(config)));
+
+    let metrics = web::Data::new(metrics::Metrics::new().expect("Failed to register metrics"));
+
+    let rate_limits = web::Data::new(rate_limit::RateLimits {
+        store: Box::new(rate_limit::MemoryStore::new()),
+        routes: match config.rate_limit.enabled {
+            true => config.rate_limit.routes.clone(),
+            false => Default::default(),
+        },
+    });
+
+    // Scrapes go to a separate listener when METRICS_PORT is set, so it can stay off the public network
+    let admin_server = match (config.metrics.enabled, config.metrics.port) {
+        (true, Some(port)) => {
+            let db = db.clone();
+            let metrics = metrics.clone();
+            log::info!("Metrics available at http://{}:{}/metrics", config.metrics.host, port);
+            Some(
+                HttpServer::new(move || {
+                    App::new()
+                        .app_data(web::Data::new(db.clone()))
+                        .app_data(metrics.clone())
+                        .route("/metrics", web::get().to(controllers::metrics_controller::export))
+                })
+                .workers(1)
+                .bind((config.metrics.host.clone(), port))?
+                .run(),
+            )
+        }
+        _ => None,
+    };
+    // Anyone who can reach the API could read the metrics, so that needs an explicit opt-in
+    let metrics_on_api = config.metrics.enabled && config.metrics.port.is_none() && config.metrics.on_api_port;
+    if config.metrics.enabled && config.metrics.port.is_none() && !config.metrics.on_api_port {
+        log::warn!("Metrics are enabled but not served; set METRICS_PORT or METRICS_ON_API_PORT=true");
+    }
+
+    let certificates = config
+        .tls
+        .enabled()
+        .then(|| tls::CertificateStore::load(&config.tls))
+        .transpose()
+        .expect("Failed to load TLS certificate");
+    let rustls_config = certificates
+        .as_ref()
+        .map(|certificates| certificates.server_config())
+        .transpose()
+        .expect("Invalid TLS configuration");
+    if let Some(certificates) = &certificates {
+        if config.tls.reload_interval_secs > 0 {
+            certificates.clone().watch(Duration::from_secs(config.tls.reload_interval_secs));
+        }
+    }
+    let hsts = certificates.as_ref().and_then(|_| tls::hsts_header(&config.tls));
+
+    // Plain-HTTP listeners that only send clients over to HTTPS
+    let redirect_server = match config.server.https_port().expect("Invalid bind address") {
+        Some(port) if certificates.is_some() && !config.tls.redirect_bind.is_empty() => {
+            let mut redirect_server = HttpServer::new(move || {
+                App::new()
+                    .app_data(web::Data::new(tls::HttpsPort(port)))
+                    .default_service(web::to(tls::redirect_to_https))
+            })
+            .workers(1);
+            for address in config.tls.redirect_addresses().expect("Invalid redirect address") {
+                redirect_server = redirect_server.bind(address.as_str())?;
+                log::info!("Redirecting http://{address} to HTTPS port {port}");
+            }
+            Some(redirect_server.run())
+        }
+        _ => None,
+    };
+
+    let addresses = config.server.bind_addresses().expect("Invalid bind address");
+    let keep_alive = match config.server.keep_alive_secs {
+        0 => KeepAlive::Disabled,
+        secs => KeepAlive::Timeout(Duration::from_secs(secs)),
+    };
+    let body_limit = config.server.body_limit_bytes;
+    let workers = config.server.workers;
+    let shutdown_timeout = config.server.shutdown_timeout_secs;
+    let pool = db.clone();
+    let config = web::Data::new(config);
+
+    let mut server = HttpServer::new(move || {
+        let mut security_headers = DefaultHeaders::new();
+        if let Some(hsts) = &hsts {
+            security_headers = security_headers.add(("Strict-Transport-Security", hsts.as_str()));
+        }
 
-    HttpServer::new(move || {
         App::new()
-            .app_data(actix_web::web::Data::new(db.clone()))
+            .wrap(security_headers)
+            .wrap(cors::middleware(&config.cors))
+            .wrap(middleware::metrics::RequestMetrics::new(metrics.clone()))
+            .wrap(middleware::request_id::RequestTracing)
+            .app_data(config.clone())
+            .app_data(web::Data::new(db.clone()))
+            .app_data(web::Data::from(mailer.clone()))
+            .app_data(login_throttle.clone())
+            .app_data(pending_rehashes.clone())
+            .app_data(rate_limits.clone())
+            .app_data(metrics.clone())
+            .app_data(web::JsonConfig::default().limit(body_limit))
+            .app_data(web::PayloadConfig::new(body_limit))
+            .configure(|cfg| {
+                if let Some(oidc) = &oidc {
+                    cfg.app_data(oidc.clone());
+                }
+                if metrics_on_api {
+                    cfg.route("/metrics", web::get().to(controllers::metrics_controller::export));
+                }
+            })
             .configure(routes::config)
     })
-    .bind(("127.0.0.1", port.parse().unwrap()))?
-    .run()
-    .await
+    .keep_alive(keep_alive)
+    // SIGTERM/SIGINT stop accepting connections; in-flight requests get this long to finish
+    .shutdown_timeout(shutdown_timeout);
+    if let Some(workers) = workers {
+        server = server.workers(workers);
+    }
+    for address in &addresses {
+        server = match address {
+            server::BindAddress::Tcp(tcp) => match &rustls_config {
+                Some(rustls_config) => {
+                    log::info!("Listening on https://{tcp}");
+                    server.bind_rustls_0_23(tcp.as_str(), rustls_config.clone())?
+                }
+                None => {
+                    log::info!("Listening on http://{tcp}");
+                    server.bind(tcp.as_str())?
+                }
+            },
+            server::BindAddress::Unix(path) => {
+                server::remove_stale_socket(path)?;
+                log::info!("Listening on {address}");
+                server.bind_uds(path)?
+            }
+        };
+    }
+
+    let servers = [Some(server.run()), admin_server, redirect_server];
+    let result = futures_util::future::try_join_all(servers.into_iter().flatten())
+        .await
+        .map(|_| ());
+
+    log::info!("Server stopped, closing database connections");
+    if let Err(e) = pool.close().await {
+        log::warn!(error:% = e; "Database pool close error");
+    }
+    server::remove_sockets(&addresses);
+    result
 }
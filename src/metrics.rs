@@ -0,0 +1,141 @@
+use prometheus::{
+    Encoder, GaugeVec, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use sea_orm::DatabaseConnection;
+
+use crate::password;
+
+pub const LOGIN_SUCCESS: &str = "success";
+pub const LOGIN_FAILURE: &str = "failure";
+pub const LOGIN_LOCKED: &str = "locked";
+
+// Label used for requests that did not match any route, so scanners cannot blow up cardinality
+pub const UNMATCHED_ROUTE: &str = "<unmatched>";
+
+// Process-wide Prometheus collectors, shared with handlers and middleware as `web::Data<Metrics>`
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+    db_pool_connections: IntGaugeVec,
+    db_pool_max_connections: IntGauge,
+    logins: IntCounterVec,
+    password_hashing_tasks: IntGaugeVec,
+    password_hashing_concurrency_limit: IntGauge,
+    password_hashing_completed: IntGauge,
+    password_hashing_queue_seconds: GaugeVec,
+}
+
+impl Metrics {
+    pub fn new() -> prometheus::Result<Self> {
+        let registry = Registry::new();
+
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests by route pattern and status code"),
+            &["method", "route", "status"],
+        )?;
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time until the response headers were ready, by route pattern and status code",
+            ),
+            &["method", "route", "status"],
+        )?;
+        let db_pool_connections = IntGaugeVec::new(
+            Opts::new("db_pool_connections", "Database pool connections by state"),
+            &["state"],
+        )?;
+        let db_pool_max_connections = IntGauge::new(
+            "db_pool_max_connections",
+            "Maximum number of connections the database pool may open",
+        )?;
+        let logins = IntCounterVec::new(
+            Opts::new("auth_logins_total", "Password login attempts by outcome"),
+            &["outcome"],
+        )?;
+
+        registry.register(Box::new(http_requests.clone()))?;
+        registry.register(Box::new(http_request_duration.clone()))?;
+        registry.register(Box::new(db_pool_connections.clone()))?;
+        registry.register(Box::new(db_pool_max_connections.clone()))?;
+        let password_hashing_tasks = IntGaugeVec::new(
+            Opts::new("password_hashing_tasks", "Password hashes by state (waiting for a slot / running)"),
+            &["state"],
+        )?;
+        let password_hashing_concurrency_limit = IntGauge::new(
+            "password_hashing_concurrency_limit",
+            "Maximum number of password hashes that may run at once",
+        )?;
+        let password_hashing_completed = IntGauge::new(
+            "password_hashing_completed",
+            "Password hashes finished since the process started",
+        )?;
+        let password_hashing_queue_seconds = GaugeVec::new(
+            Opts::new(
+                "password_hashing_queue_seconds",
+                "Time password hashes waited for a slot since the process started (avg / max)",
+            ),
+            &["stat"],
+        )?;
+
+        registry.register(Box::new(logins.clone()))?;
+        registry.register(Box::new(password_hashing_tasks.clone()))?;
+        registry.register(Box::new(password_hashing_concurrency_limit.clone()))?;
+        registry.register(Box::new(password_hashing_completed.clone()))?;
+        registry.register(Box::new(password_hashing_queue_seconds.clone()))?;
+
+        // Export every outcome from the start so rate() works before the first failure
+        for outcome in [LOGIN_SUCCESS, LOGIN_FAILURE, LOGIN_LOCKED] {
+            logins.with_label_values(&[outcome]);
+        }
+
+        Ok(Self {
+            registry,
+            http_requests,
+            http_request_duration,
+            db_pool_connections,
+            db_pool_max_connections,
+            logins,
+            password_hashing_tasks,
+            password_hashing_concurrency_limit,
+            password_hashing_completed,
+            password_hashing_queue_seconds,
+        })
+    }
+
+    pub fn observe_request(&self, method: &str, route: &str, status: u16, seconds: f64) {
+        let status = status.to_string();
+        let labels = [method, route, status.as_str()];
+        self.http_requests.with_label_values(&labels).inc();
+        self.http_request_duration.with_label_values(&labels).observe(seconds);
+    }
+
+    pub fn record_login(&self, outcome: &str) {
+        self.logins.with_label_values(&[outcome]).inc();
+    }
+
+    // Pool and hashing gauges are sampled at scrape time rather than tracked on every checkout
+    pub fn render(&self, db: &DatabaseConnection) -> Result<String, String> {
+        let pool = db.get_postgres_connection_pool();
+        let size = i64::from(pool.size());
+        let idle = pool.num_idle() as i64;
+        self.db_pool_connections.with_label_values(&["idle"]).set(idle);
+        self.db_pool_connections.with_label_values(&["in_use"]).set((size - idle).max(0));
+        self.db_pool_max_connections
+            .set(i64::from(pool.options().get_max_connections()));
+
+        let hashing = password::stats();
+        self.password_hashing_tasks.with_label_values(&["waiting"]).set(hashing.waiting as i64);
+        self.password_hashing_tasks.with_label_values(&["running"]).set(hashing.running as i64);
+        self.password_hashing_concurrency_limit.set(hashing.concurrency_limit as i64);
+        self.password_hashing_completed.set(hashing.completed as i64);
+        self.password_hashing_queue_seconds.with_label_values(&["avg"]).set(hashing.avg_queue_ms / 1000.0);
+        self.password_hashing_queue_seconds.with_label_values(&["max"]).set(hashing.max_queue_ms / 1000.0);
+
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .map_err(|e| e.to_string())?;
+        String::from_utf8(buffer).map_err(|e| e.to_string())
+    }
+}
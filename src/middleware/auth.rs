@@ -1,18 +1,24 @@
 use actix_web::{
     dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
-    error::ErrorUnauthorized,
-    http::header,
-    Error, HttpMessage, HttpRequest,
+    error::{ErrorForbidden, ErrorInternalServerError, ErrorUnauthorized},
+    http::{header, Method},
+    web, Error, HttpMessage, HttpRequest,
 };
 use futures_util::future::{ready, LocalBoxFuture, Ready};
-use jsonwebtoken::{decode, DecodingKey, Validation};
+use chrono::Utc;
+use sea_orm::sea_query::Expr;
 use sea_orm::{DatabaseConnection, EntityTrait, QueryFilter, ColumnTrait};
 use serde::{Deserialize, Serialize};
-use std::env;
+use serde_json::json;
 use std::rc::Rc;
 use uuid::Uuid;
 
-use crate::models::user;
+use crate::client_ip::client_ip;
+use crate::config::Config;
+use crate::jwt;
+use crate::models::{api_key, session, user};
+use crate::security_events;
+use crate::tokens::hash_token;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Claims {
@@ -20,23 +26,77 @@ pub struct Claims {
     pub email: String,
     pub role: String,
     pub exp: usize,
+    #[serde(default)]
+    pub mfa: bool, // token was issued after a second factor was verified
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scopes: Option<Vec<String>>, // only set for API key principals
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>, // login session the token belongs to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act: Option<Actor>, // set when an admin is impersonating `sub`
+}
+
+// The real caller behind an impersonation token (RFC 8693 `act` claim)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Actor {
+    pub sub: String,
+    pub email: String,
+}
+
+pub const API_KEY_PREFIX: &str = "gca_";
+pub const API_KEY_SCOPES: [&str; 4] = ["games:read", "games:write", "creators:read", "creators:write"];
+
+enum Credential {
+    Bearer(String),
+    ApiKey(String),
 }
 
 #[derive(Clone)]
 pub struct AuthMiddleware {
     pub required_role: Option<String>,
+    // Methods that need a verified email address
+    pub require_verified_email: Vec<Method>,
+    pub api_key_scope: Option<String>,
+    pub forbid_impersonation: bool,
 }
 
 impl AuthMiddleware {
     pub fn new() -> Self {
-        Self { required_role: None }
+        Self {
+            required_role: None,
+            require_verified_email: Vec::new(),
+            api_key_scope: None,
+            forbid_impersonation: false,
+        }
     }
 
     pub fn with_role(role: String) -> Self {
         Self {
             required_role: Some(role),
+            require_verified_email: Vec::new(),
+            api_key_scope: None,
+            forbid_impersonation: false,
         }
     }
+
+    // Accept API keys on these routes; GET/HEAD need `<scope>:read`, anything else `<scope>:write`
+    pub fn api_key_scope(mut self, scope: &str) -> Self {
+        self.api_key_scope = Some(scope.to_string());
+        self
+    }
+
+    // Reject users whose email address has not been verified yet on requests with this method,
+    // e.g. creating content while reads stay open
+    pub fn require_verified_email(mut self, method: Method) -> Self {
+        self.require_verified_email.push(method);
+        self
+    }
+
+    // Reject impersonation tokens, for account-security and admin actions
+    pub fn forbid_impersonation(mut self) -> Self {
+        self.forbid_impersonation = true;
+        self
+    }
 }
 
 impl<S, B> Transform<S, ServiceRequest> for AuthMiddleware
@@ -55,6 +115,9 @@ where
         ready(Ok(AuthMiddlewareService {
             service: Rc::new(service),
             required_role: self.required_role.clone(),
+            require_verified_email: self.require_verified_email.clone(),
+            api_key_scope: self.api_key_scope.clone(),
+            forbid_impersonation: self.forbid_impersonation,
         }))
     }
 }
@@ -62,6 +125,9 @@ where
 pub struct AuthMiddlewareService<S> {
     service: Rc<S>,
     required_role: Option<String>,
+    require_verified_email: Vec<Method>,
+    api_key_scope: Option<String>,
+    forbid_impersonation: bool,
 }
 
 impl<S, B> Service<ServiceRequest> for AuthMiddlewareService<S>
@@ -79,55 +145,218 @@ where
     fn call(&self, req: ServiceRequest) -> Self::Future {
         let service = Rc::clone(&self.service);
         let required_role = self.required_role.clone();
+        let require_verified_email = self.require_verified_email.contains(req.method());
+        let api_key_scope = self.api_key_scope.clone();
+        let forbid_impersonation = self.forbid_impersonation;
 
         Box::pin(async move {
-            // Function 1: Decode JWT and attach to req.user
-            let token = extract_token_from_request(&req);
-            if token.is_none() {
+            // Function 1: Decode JWT (or resolve API key) and attach to req.user
+            let credential = extract_token_from_request(&req);
+            if credential.is_none() {
                 return Err(ErrorUnauthorized("No token provided"));
             }
 
-            let token = token.unwrap();
-            let claims = decode_jwt(&token)?;
-            
+            let claims = match credential.unwrap() {
+                Credential::Bearer(token) => {
+                    let claims = decode_jwt(&token)?;
+                    if let Some(sid) = &claims.sid {
+                        let db = req
+                            .app_data::<web::Data<DatabaseConnection>>()
+                            .ok_or_else(|| ErrorInternalServerError("Database not configured"))?;
+                        check_session(sid, &claims, db.get_ref()).await?;
+                    }
+                    claims
+                }
+                Credential::ApiKey(key) => {
+                    let scope = api_key_scope
+                        .ok_or_else(|| ErrorForbidden("API keys are not accepted for this route"))?;
+                    let db = req
+                        .app_data::<web::Data<DatabaseConnection>>()
+                        .ok_or_else(|| ErrorInternalServerError("Database not configured"))?;
+                    let claims = authenticate_api_key(&key, db.get_ref()).await?;
+
+                    let access = if req.method() == Method::GET || req.method() == Method::HEAD {
+                        "read"
+                    } else {
+                        "write"
+                    };
+                    let required_scope = format!("{scope}:{access}");
+                    if !claims.scopes.iter().flatten().any(|s| s == &required_scope) {
+                        return Err(ErrorForbidden(format!("API key is missing scope {required_scope}")));
+                    }
+                    claims
+                }
+            };
+
             // Attach user info to request extensions
             req.extensions_mut().insert(claims.clone());
 
+            if claims.act.is_some() && forbid_impersonation {
+                return Err(ErrorForbidden("Not allowed while impersonating"));
+            }
+
             // Function 2: Check role if required
-            if let Some(required_role) = required_role {
-                if claims.role != required_role {
+            if let Some(required_role) = &required_role {
+                if &claims.role != required_role {
                     return Err(ErrorUnauthorized("Insufficient permissions"));
                 }
             }
 
-            let res = service.call(req).await?;
-            Ok(res)
+            // Role-protected routes need a second factor when policy demands it. API keys are exempt:
+            // for those roles a key can only be created from a session that passed 2FA.
+            if required_role.is_some() && !claims.mfa && claims.scopes.is_none() {
+                let config = req
+                    .app_data::<web::Data<Config>>()
+                    .ok_or_else(|| ErrorInternalServerError("Config not configured"))?;
+                if config.mfa.required_for_role(&claims.role) {
+                    return Err(ErrorForbidden("Two-factor authentication required"));
+                }
+            }
+
+            if require_verified_email {
+                let db = req
+                    .app_data::<web::Data<DatabaseConnection>>()
+                    .ok_or_else(|| ErrorInternalServerError("Database not configured"))?;
+                let user = find_user(&claims, db.get_ref()).await?;
+                if user.email_verified_at.is_none() {
+                    return Err(ErrorForbidden("Email address not verified"));
+                }
+            }
+
+            let Some(actor) = claims.act.as_ref() else {
+                return service.call(req).await;
+            };
+
+            // Every request made under impersonation goes to the audit log
+            let db = req
+                .app_data::<web::Data<DatabaseConnection>>()
+                .cloned()
+                .ok_or_else(|| ErrorInternalServerError("Database not configured"))?;
+            let ip = client_ip(req.request());
+            let mut details = json!({
+                "actor": actor.sub,
+                "actor_email": actor.email,
+                "method": req.method().as_str(),
+                "path": req.path(),
+            });
+
+            let res = service.call(req).await;
+            let status = match &res {
+                Ok(res) => res.status().as_u16(),
+                Err(e) => e.as_response_error().status_code().as_u16(),
+            };
+            details["status"] = json!(status);
+
+            let user_id = claims.sub.parse().ok();
+            security_events::record(
+                db.get_ref(),
+                user_id,
+                security_events::IMPERSONATED_REQUEST,
+                ip.as_deref(),
+                Some(details),
+            )
+            .await;
+
+            res
         })
     }
 }
 
-// Function 1: Extract and decode JWT token
-fn extract_token_from_request(req: &ServiceRequest) -> Option<String> {
-    req.headers()
+// Function 1: Extract JWT from `Authorization: Bearer`, or an API key from
+// `X-API-Key` / `Authorization: ApiKey`
+fn extract_token_from_request(req: &ServiceRequest) -> Option<Credential> {
+    if let Some(key) = req
+        .headers()
+        .get("X-API-Key")
+        .and_then(|value| value.to_str().ok())
+    {
+        return Some(Credential::ApiKey(key.trim().to_string()));
+    }
+
+    let auth_str = req
+        .headers()
         .get(header::AUTHORIZATION)
-        .and_then(|auth_header| auth_header.to_str().ok())
-        .and_then(|auth_str| {
-            if auth_str.starts_with("Bearer ") {
-                Some(auth_str[7..].to_string())
-            } else {
-                None
-            }
-        })
+        .and_then(|auth_header| auth_header.to_str().ok())?;
+
+    if let Some(token) = auth_str.strip_prefix("Bearer ") {
+        Some(Credential::Bearer(token.to_string()))
+    } else {
+        auth_str
+            .strip_prefix("ApiKey ")
+            .map(|key| Credential::ApiKey(key.trim().to_string()))
+    }
 }
 
 fn decode_jwt(token: &str) -> Result<Claims, Error> {
-    let secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-    let key = DecodingKey::from_secret(secret.as_ref());
-    
-    let token_data = decode::<Claims>(token, &key, &Validation::default())
-        .map_err(|_| ErrorUnauthorized("Invalid token"))?;
-    
-    Ok(token_data.claims)
+    jwt::decode::<Claims>(token, None).map_err(|_| ErrorUnauthorized("Invalid token"))
+}
+
+// Rejects tokens whose session was terminated, and refreshes its last-seen time
+async fn check_session(sid: &str, claims: &Claims, db: &DatabaseConnection) -> Result<(), Error> {
+    let session_id: Uuid = sid.parse().map_err(|_| ErrorUnauthorized("Invalid token"))?;
+
+    let session = session::Entity::find_by_id(session_id)
+        .one(db)
+        .await
+        .map_err(|_| ErrorUnauthorized("Database error"))?
+        .filter(|session| session.user_id.to_string() == claims.sub && session.revoked_at.is_none())
+        .ok_or_else(|| ErrorUnauthorized("Session has been terminated"))?;
+
+    // Only write when the stored value is stale, not on every request
+    let now = Utc::now();
+    if now.signed_duration_since(session.last_seen_at) > chrono::Duration::minutes(1) {
+        session::Entity::update_many()
+            .col_expr(session::Column::LastSeenAt, Expr::value(now))
+            .filter(session::Column::Id.eq(session.id))
+            .exec(db)
+            .await
+            .map_err(|_| ErrorUnauthorized("Database error"))?;
+    }
+
+    Ok(())
+}
+
+// Resolves an API key to the same `Claims` principal a JWT would produce
+async fn authenticate_api_key(key: &str, db: &DatabaseConnection) -> Result<Claims, Error> {
+    if !key.starts_with(API_KEY_PREFIX) {
+        return Err(ErrorUnauthorized("Invalid API key"));
+    }
+
+    let (api_key, user) = api_key::Entity::find()
+        .filter(api_key::Column::KeyHash.eq(hash_token(key)))
+        .find_also_related(user::Entity)
+        .one(db)
+        .await
+        .map_err(|_| ErrorUnauthorized("Database error"))?
+        .ok_or_else(|| ErrorUnauthorized("Invalid API key"))?;
+
+    let user = user.ok_or_else(|| ErrorUnauthorized("Invalid API key"))?;
+
+    let now = Utc::now();
+    if api_key.expires_at.is_some_and(|expires_at| expires_at <= now) {
+        return Err(ErrorUnauthorized("API key has expired"));
+    }
+
+    api_key::Entity::update_many()
+        .col_expr(api_key::Column::LastUsedAt, Expr::value(now))
+        .filter(api_key::Column::Id.eq(api_key.id))
+        .exec(db)
+        .await
+        .map_err(|_| ErrorUnauthorized("Database error"))?;
+
+    Ok(Claims {
+        sub: user.id.to_string(),
+        email: user.email,
+        role: user.role,
+        exp: api_key
+            .expires_at
+            .map(|expires_at| expires_at.timestamp() as usize)
+            .unwrap_or(usize::MAX),
+        mfa: false,
+        sid: None,
+        act: None,
+        scopes: Some(api_key.scopes.split_whitespace().map(str::to_string).collect()),
+    })
 }
 
 // Function 3: Get current user from database
@@ -141,6 +370,10 @@ pub async fn get_current_user(
         .ok_or_else(|| ErrorUnauthorized("User not found in request"))?
         .clone();
 
+    find_user(&claims, db).await
+}
+
+async fn find_user(claims: &Claims, db: &DatabaseConnection) -> Result<user::Model, Error> {
     let user_id: Uuid = claims.sub.parse().map_err(|_| ErrorUnauthorized("Invalid user ID"))?;
 
     let user = user::Entity::find_by_id(user_id)
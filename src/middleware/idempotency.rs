@@ -0,0 +1,430 @@
+use actix_web::{
+    body::{self, BoxBody, EitherBody, MessageBody},
+    dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    error::{ErrorBadRequest, ErrorConflict, ErrorInternalServerError, ErrorUnprocessableEntity},
+    http::header::{self, HeaderName, HeaderValue},
+    http::{Method, StatusCode},
+    web, Error, HttpMessage, HttpResponse,
+};
+use chrono::{DateTime, Duration, Utc};
+use futures_util::future::{ready, LocalBoxFuture, Ready};
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, Set, SqlErr,
+};
+use sha2::{Digest, Sha256};
+use std::rc::Rc;
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::middleware::auth::Claims;
+use crate::models::idempotency_key;
+
+pub const IDEMPOTENCY_KEY_HEADER: HeaderName = HeaderName::from_static("idempotency-key");
+// Set on responses served from a stored earlier attempt
+const REPLAYED_HEADER: HeaderName = HeaderName::from_static("idempotent-replayed");
+// Stored with the response so a replayed 201 still points at the created resource and its version
+const REPLAYED_HEADERS: [HeaderName; 2] = [header::LOCATION, header::ETAG];
+const CLEANUP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(3600);
+
+// Makes POSTs with an `Idempotency-Key` header safe to retry: the first response is stored
+// per user and key, and retries with the same body get it back instead of running the handler
+// again. Reusing a key for a different request is 422; a retry while the first attempt is
+// still running is 409, until IDEMPOTENCY_IN_PROGRESS_TIMEOUT_SECONDS pass and it may take over. 5xx responses are not stored, so those can be retried for real.
+// Register it before AuthMiddleware in the scope so the caller is known.
+pub struct Idempotency;
+
+impl<S, B> Transform<S, ServiceRequest> for Idempotency
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B, BoxBody>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = IdempotencyService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(IdempotencyService {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct IdempotencyService<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for IdempotencyService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B, BoxBody>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+
+        let key = req.headers().get(&IDEMPOTENCY_KEY_HEADER).cloned();
+        let user_id = req
+            .extensions()
+            .get::<Claims>()
+            .and_then(|claims| claims.sub.parse::<Uuid>().ok());
+        let (Some(key), Some(user_id), &Method::POST) = (key, user_id, req.method()) else {
+            return Box::pin(async move { Ok(service.call(req).await?.map_into_left_body()) });
+        };
+
+        Box::pin(async move {
+            let key = key
+                .to_str()
+                .ok()
+                .filter(|key| is_valid_key(key))
+                .ok_or_else(|| ErrorBadRequest("Idempotency-Key must be 1-255 visible ASCII characters"))?
+                .to_string();
+            let db = req
+                .app_data::<web::Data<DatabaseConnection>>()
+                .cloned()
+                .ok_or_else(|| ErrorInternalServerError("Database not configured"))?;
+            let (ttl_hours, in_progress_seconds) = req
+                .app_data::<web::Data<Config>>()
+                .map(|config| (config.tokens.idempotency_key_hours, config.tokens.idempotency_in_progress_seconds))
+                .ok_or_else(|| ErrorInternalServerError("Config not configured"))?;
+
+            // The body is read here for the fingerprint and handed back to the handler
+            let body = req.extract::<web::Bytes>().await?;
+            let fingerprint = fingerprint(&req, &body);
+            req.set_payload(Payload::from(body));
+
+            let id = match begin(db.get_ref(), user_id, &key, &fingerprint, ttl_hours, in_progress_seconds).await? {
+                Begin::Started(id) => id,
+                Begin::Completed(record) => {
+                    log::info!(idempotency_key = key.as_str(); "Replaying stored response");
+                    return Ok(req.into_response(replay(&record)).map_into_right_body());
+                }
+            };
+
+            let res = match service.call(req).await {
+                Ok(res) if !res.status().is_server_error() => res,
+                res => {
+                    release(db.get_ref(), id).await;
+                    return res.map(ServiceResponse::map_into_left_body);
+                }
+            };
+
+            let (req, res) = res.into_parts();
+            let (res, body) = res.into_parts();
+            let body = body::to_bytes(body).await.map_err(|e| {
+                log::error!(error:% = e.into(); "Response body error");
+                ErrorInternalServerError("Response body error")
+            })?;
+            let res = res.set_body(body.clone());
+            complete(db.get_ref(), id, &res, &body).await;
+
+            Ok(ServiceResponse::new(req, res.map_into_boxed_body()).map_into_right_body())
+        })
+    }
+}
+
+enum Begin {
+    Started(Uuid),
+    Completed(idempotency_key::Model),
+}
+
+// An earlier attempt with the same key and request
+enum Existing {
+    Completed(idempotency_key::Model),
+    // Still unfinished after the in-progress timeout, e.g. the worker crashed or the future was dropped
+    Abandoned(Uuid),
+}
+
+// Claims `key` for this request, or finds the earlier attempt that already holds it
+async fn begin(
+    db: &DatabaseConnection,
+    user_id: Uuid,
+    key: &str,
+    fingerprint: &str,
+    ttl_hours: i64,
+    in_progress_seconds: i64,
+) -> Result<Begin, Error> {
+    let now = Utc::now();
+    // Other expired keys are left to the periodic cleanup (`spawn_cleanup`)
+    idempotency_key::Entity::delete_many()
+        .filter(idempotency_key::Column::UserId.eq(user_id))
+        .filter(idempotency_key::Column::Key.eq(key))
+        .filter(idempotency_key::Column::ExpiresAt.lt(now))
+        .exec(db)
+        .await
+        .map_err(database_error)?;
+
+    let existing = idempotency_key::Entity::find()
+        .filter(idempotency_key::Column::UserId.eq(user_id))
+        .filter(idempotency_key::Column::Key.eq(key))
+        .one(db)
+        .await
+        .map_err(database_error)?;
+    let stale_before = now - Duration::seconds(in_progress_seconds);
+    match existing.map(|record| check_existing(record, fingerprint, stale_before)).transpose()? {
+        Some(Existing::Completed(record)) => return Ok(Begin::Completed(record)),
+        Some(Existing::Abandoned(id)) => return take_over(db, id, stale_before).await,
+        None => {}
+    }
+
+    let id = Uuid::new_v4();
+    let record = idempotency_key::ActiveModel {
+        id: Set(id),
+        user_id: Set(user_id),
+        key: Set(key.to_string()),
+        fingerprint: Set(fingerprint.to_string()),
+        response_status: Set(None),
+        response_content_type: Set(None),
+        response_headers: Set(None),
+        response_body: Set(None),
+        created_at: Set(now.into()),
+        started_at: Set(now.into()),
+        expires_at: Set((now + Duration::hours(ttl_hours)).into()),
+    };
+    match record.insert(db).await {
+        Ok(_) => Ok(Begin::Started(id)),
+        // A concurrent retry claimed the key between our lookup and insert
+        Err(e) if matches!(e.sql_err(), Some(SqlErr::UniqueConstraintViolation(_))) => Err(in_progress()),
+        Err(e) => Err(database_error(e)),
+    }
+}
+
+fn check_existing(
+    record: idempotency_key::Model,
+    fingerprint: &str,
+    stale_before: DateTime<Utc>,
+) -> Result<Existing, Error> {
+    if record.fingerprint != fingerprint {
+        return Err(ErrorUnprocessableEntity(
+            "Idempotency-Key was already used for a different request",
+        ));
+    }
+    if record.response_status.is_some() {
+        return Ok(Existing::Completed(record));
+    }
+    if record.started_at < stale_before {
+        return Ok(Existing::Abandoned(record.id));
+    }
+    Err(in_progress())
+}
+
+// Restarts an abandoned attempt for this request. The condition on `started_at` lets only one
+// of several concurrent retries win; the others see it in progress again.
+async fn take_over(db: &DatabaseConnection, id: Uuid, stale_before: DateTime<Utc>) -> Result<Begin, Error> {
+    let claimed = idempotency_key::Entity::update_many()
+        .col_expr(idempotency_key::Column::StartedAt, Expr::value(Utc::now()))
+        .filter(idempotency_key::Column::Id.eq(id))
+        .filter(idempotency_key::Column::ResponseStatus.is_null())
+        .filter(idempotency_key::Column::StartedAt.lt(stale_before))
+        .exec(db)
+        .await
+        .map_err(database_error)?;
+    if claimed.rows_affected == 0 {
+        return Err(in_progress());
+    }
+    log::warn!(idempotency_key_id:% = id; "Taking over an abandoned idempotent request");
+    Ok(Begin::Started(id))
+}
+
+fn in_progress() -> Error {
+    ErrorConflict("A request with this Idempotency-Key is still in progress")
+}
+
+// Deletes expired keys every CLEANUP_INTERVAL for as long as the server runs, so requests
+// only ever touch their own key
+pub fn spawn_cleanup(db: DatabaseConnection) {
+    actix_web::rt::spawn(async move {
+        let mut ticker = actix_web::rt::time::interval(CLEANUP_INTERVAL);
+        loop {
+            ticker.tick().await;
+            let deleted = idempotency_key::Entity::delete_many()
+                .filter(idempotency_key::Column::ExpiresAt.lt(Utc::now()))
+                .exec(&db)
+                .await;
+            match deleted {
+                Ok(deleted) if deleted.rows_affected > 0 => {
+                    log::info!(deleted = deleted.rows_affected; "Expired idempotency keys deleted");
+                }
+                Ok(_) => {}
+                Err(e) => log::warn!(error:% = e; "Idempotency key cleanup error"),
+            }
+        }
+    });
+}
+
+async fn complete(db: &DatabaseConnection, id: Uuid, res: &HttpResponse<web::Bytes>, body: &[u8]) {
+    let content_type = res
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let headers: serde_json::Map<String, serde_json::Value> = REPLAYED_HEADERS
+        .iter()
+        .filter_map(|name| {
+            let value = res.headers().get(name)?.to_str().ok()?;
+            Some((name.to_string(), value.into()))
+        })
+        .collect();
+    let record = idempotency_key::ActiveModel {
+        id: Set(id),
+        response_status: Set(Some(res.status().as_u16() as i16)),
+        response_content_type: Set(content_type),
+        response_headers: Set(Some(headers.into())),
+        response_body: Set(Some(body.to_vec())),
+        ..Default::default()
+    };
+    if let Err(e) = record.update(db).await {
+        // Without the stored response a retry would be stuck on 409 until the key expires
+        log::error!(error:% = e; "Idempotency key store error");
+        release(db, id).await;
+    }
+}
+
+// Frees the key so the client can retry after an error
+async fn release(db: &DatabaseConnection, id: Uuid) {
+    if let Err(e) = idempotency_key::Entity::delete_by_id(id).exec(db).await {
+        log::warn!(error:% = e; "Idempotency key release error");
+    }
+}
+
+fn replay(record: &idempotency_key::Model) -> HttpResponse {
+    let status = record
+        .response_status
+        .and_then(|status| StatusCode::from_u16(status as u16).ok())
+        .unwrap_or(StatusCode::OK);
+    let mut res = HttpResponse::build(status);
+    if let Some(content_type) = &record.response_content_type {
+        res.content_type(content_type.as_str());
+    }
+    let stored = record.response_headers.as_ref().and_then(|headers| headers.as_object());
+    for (name, value) in stored.into_iter().flatten() {
+        let value = value.as_str().and_then(|value| HeaderValue::from_str(value).ok());
+        if let (Ok(name), Some(value)) = (HeaderName::try_from(name.as_str()), value) {
+            res.insert_header((name, value));
+        }
+    }
+    res.insert_header((REPLAYED_HEADER, HeaderValue::from_static("true")))
+        .body(record.response_body.clone().unwrap_or_default())
+}
+
+fn fingerprint(req: &ServiceRequest, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(req.method().as_str());
+    hasher.update(b" ");
+    hasher.update(req.uri().path_and_query().map(|path| path.as_str()).unwrap_or("/"));
+    hasher.update(b"\n");
+    hasher.update(body);
+    hex::encode(hasher.finalize())
+}
+
+fn is_valid_key(key: &str) -> bool {
+    !key.is_empty() && key.len() <= 255 && key.bytes().all(|b| b.is_ascii_graphic())
+}
+
+fn database_error(e: DbErr) -> Error {
+    log::error!(error:% = e; "Database error");
+    ErrorInternalServerError("Database error")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{body::to_bytes, test as actix_test};
+
+    fn record(fingerprint: &str, response_status: Option<i16>) -> idempotency_key::Model {
+        let now = Utc::now();
+        idempotency_key::Model {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            key: "key-1".to_string(),
+            fingerprint: fingerprint.to_string(),
+            response_status,
+            response_content_type: response_status.map(|_| "application/json".to_string()),
+            response_headers: response_status.map(|_| {
+                serde_json::json!({ "location": "/api/games/42", "etag": "\"abc\"" })
+            }),
+            response_body: response_status.map(|_| br#"{"id":42}"#.to_vec()),
+            created_at: now.into(),
+            started_at: now.into(),
+            expires_at: (now + Duration::hours(24)).into(),
+        }
+    }
+
+    fn status_of(result: Result<Existing, Error>) -> StatusCode {
+        result.err().unwrap().as_response_error().status_code()
+    }
+
+    fn check(record: idempotency_key::Model, fingerprint: &str) -> Result<Existing, Error> {
+        check_existing(record, fingerprint, Utc::now() - Duration::seconds(60))
+    }
+
+    #[test]
+    fn validates_key_format() {
+        assert!(is_valid_key("3f2b9c1e-order-17"));
+        assert!(!is_valid_key(""));
+        assert!(!is_valid_key("has space"));
+        assert!(!is_valid_key(&"k".repeat(256)));
+        assert!(is_valid_key(&"k".repeat(255)));
+    }
+
+    #[test]
+    fn fingerprint_covers_path_and_body() {
+        let req = actix_test::TestRequest::post().uri("/api/games").to_srv_request();
+        let other_path = actix_test::TestRequest::post().uri("/api/creators").to_srv_request();
+        assert_eq!(fingerprint(&req, b"{}"), fingerprint(&req, b"{}"));
+        assert_ne!(fingerprint(&req, b"{}"), fingerprint(&req, b"{\"a\":1}"));
+        assert_ne!(fingerprint(&req, b"{}"), fingerprint(&other_path, b"{}"));
+    }
+
+    #[test]
+    fn different_request_with_same_key_is_unprocessable() {
+        let status = status_of(check(record("a", Some(201)), "b"));
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[test]
+    fn retry_while_in_progress_conflicts() {
+        assert_eq!(status_of(check(record("a", None), "a")), StatusCode::CONFLICT);
+    }
+
+    #[test]
+    fn retry_takes_over_an_abandoned_attempt() {
+        let mut abandoned = record("a", None);
+        abandoned.started_at = (Utc::now() - Duration::seconds(61)).into();
+        let id = abandoned.id;
+        assert!(matches!(check(abandoned, "a"), Ok(Existing::Abandoned(found)) if found == id));
+    }
+
+    #[test]
+    fn abandoned_attempt_still_checks_the_fingerprint() {
+        let mut abandoned = record("a", None);
+        abandoned.started_at = (Utc::now() - Duration::seconds(61)).into();
+        assert_eq!(status_of(check(abandoned, "b")), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[test]
+    fn completed_request_is_replayed() {
+        assert!(matches!(check(record("a", Some(201)), "a"), Ok(Existing::Completed(_))));
+    }
+
+    #[actix_web::test]
+    async fn replay_restores_status_headers_and_body() {
+        let res = replay(&record("a", Some(201)));
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let headers = res.headers();
+        assert_eq!(headers.get(header::CONTENT_TYPE).unwrap(), "application/json");
+        assert_eq!(headers.get(header::LOCATION).unwrap(), "/api/games/42");
+        assert_eq!(headers.get(header::ETAG).unwrap(), "\"abc\"");
+        assert_eq!(headers.get(REPLAYED_HEADER).unwrap(), "true");
+        assert_eq!(to_bytes(res.into_body()).await.unwrap(), r#"{"id":42}"#);
+    }
+}
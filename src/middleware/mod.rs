@@ -1 +1,5 @@
-pub mod auth; 
\ No newline at end of file
+pub mod auth; 
+pub mod idempotency;
+pub mod metrics;
+pub mod rate_limit;
+pub mod request_id;
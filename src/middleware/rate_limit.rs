@@ -0,0 +1,337 @@
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{self, HeaderMap, HeaderName, HeaderValue},
+    http::StatusCode,
+    web, Error, HttpMessage, HttpResponse, ResponseError,
+};
+use futures_util::future::{ready, LocalBoxFuture, Ready};
+use serde_json::json;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::client_ip::client_ip;
+use crate::middleware::auth::Claims;
+use crate::rate_limit::{Decision, RateLimits};
+
+const LIMIT_HEADER: HeaderName = HeaderName::from_static("x-ratelimit-limit");
+const REMAINING_HEADER: HeaderName = HeaderName::from_static("x-ratelimit-remaining");
+const RESET_HEADER: HeaderName = HeaderName::from_static("x-ratelimit-reset");
+
+#[derive(Clone, Copy)]
+enum Subject {
+    Ip,
+    User,
+}
+
+// Token-bucket limit for a route scope, looked up by `route` in `RateLimits` (config
+// `rate_limit.routes`). Scopes without a configured limit pass through.
+#[derive(Clone)]
+pub struct RateLimiter {
+    route: String,
+    subject: Subject,
+}
+
+impl RateLimiter {
+    // One bucket per client IP, for routes reachable without a token
+    pub fn per_ip(route: &str) -> Self {
+        Self {
+            route: route.to_string(),
+            subject: Subject::Ip,
+        }
+    }
+
+    // One bucket per user (`Claims.sub`), so clients behind a shared IP do not starve each other.
+    // Register it before AuthMiddleware in the scope so it runs after authentication.
+    pub fn per_user(route: &str) -> Self {
+        Self {
+            route: route.to_string(),
+            subject: Subject::User,
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimiter
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RateLimiterService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimiterService {
+            service: Rc::new(service),
+            route: self.route.clone(),
+            subject: self.subject,
+        }))
+    }
+}
+
+pub struct RateLimiterService<S> {
+    service: Rc<S>,
+    route: String,
+    subject: Subject,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimiterService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+
+        let limits = req.app_data::<web::Data<RateLimits>>().cloned();
+        let Some((limits, limit)) = limits.and_then(|limits| {
+            let limit = limits.routes.get(&self.route).copied()?;
+            Some((limits, limit))
+        }) else {
+            return Box::pin(service.call(req));
+        };
+
+        // Requests without claims (e.g. a per-user limiter outside AuthMiddleware) fall back to the IP
+        let user_id = match self.subject {
+            Subject::User => req.extensions().get::<Claims>().map(|claims| claims.sub.clone()),
+            Subject::Ip => None,
+        };
+        let key = match user_id {
+            Some(user_id) => format!("{}:user:{user_id}", self.route),
+            None => {
+                let ip = client_ip(req.request()).unwrap_or_else(|| "unknown".to_string());
+                format!("{}:ip:{ip}", self.route)
+            }
+        };
+
+        Box::pin(async move {
+            // A broken shared store should not take the API down with it
+            let decision = match limits.store.acquire(&key, limit).await {
+                Ok(decision) => decision,
+                Err(e) => {
+                    log::warn!(error:% = e, key = key.as_str(); "Rate limit store error");
+                    return service.call(req).await;
+                }
+            };
+
+            if !decision.allowed {
+                log::info!(key = key.as_str(); "Rate limit exceeded");
+                return Err(RateLimited(decision).into());
+            }
+
+            match service.call(req).await {
+                Ok(mut res) => {
+                    insert_headers(res.headers_mut(), &decision);
+                    Ok(res)
+                }
+                Err(err) => Err(WithRateLimit { inner: err, decision }.into()),
+            }
+        })
+    }
+}
+
+// 429 with the same headers as successful responses plus Retry-After
+#[derive(Debug)]
+struct RateLimited(Decision);
+
+impl fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Too many requests")
+    }
+}
+
+impl ResponseError for RateLimited {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::TOO_MANY_REQUESTS
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let retry_after = self.0.retry_after.map(|wait| wait.as_secs_f64().ceil() as u64).unwrap_or(1).max(1);
+        let mut res = HttpResponse::TooManyRequests()
+            .insert_header((header::RETRY_AFTER, retry_after.to_string()))
+            .json(json!({
+                "error": "Too many requests, try again later",
+                "retry_after": retry_after
+            }));
+        insert_headers(res.headers_mut(), &self.0);
+        res
+    }
+}
+
+// Error responses are built after this middleware returns, so the headers are added there
+#[derive(Debug)]
+struct WithRateLimit {
+    inner: Error,
+    decision: Decision,
+}
+
+impl fmt::Display for WithRateLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+impl ResponseError for WithRateLimit {
+    fn status_code(&self) -> StatusCode {
+        self.inner.as_response_error().status_code()
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut res = self.inner.error_response();
+        insert_headers(res.headers_mut(), &self.decision);
+        res
+    }
+}
+
+// With nested limiters (per-IP outside AuthMiddleware, per-user inside) the client sees the
+// bucket closest to running out
+fn insert_headers(headers: &mut HeaderMap, decision: &Decision) {
+    let tighter = headers
+        .get(&REMAINING_HEADER)
+        .and_then(|value| value.to_str().ok()?.parse::<u64>().ok())
+        .is_some_and(|remaining| remaining < u64::from(decision.remaining));
+    if tighter {
+        return;
+    }
+
+    let reset = decision.reset_after.as_secs_f64().ceil() as u64;
+    headers.insert(LIMIT_HEADER, HeaderValue::from(decision.limit));
+    headers.insert(REMAINING_HEADER, HeaderValue::from(decision.remaining));
+    headers.insert(RESET_HEADER, HeaderValue::from(reset));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rate_limit::{MemoryStore, RateLimit};
+    use actix_web::{dev::ServiceResponse, test as actix_test, App};
+
+    fn limits(limit: &str) -> web::Data<RateLimits> {
+        let limit: RateLimit = limit.parse().unwrap();
+        web::Data::new(RateLimits {
+            store: Box::new(MemoryStore::new()),
+            routes: [("auth".to_string(), limit)].into(),
+        })
+    }
+
+    // Middleware errors come back as Err here; the server turns them into responses
+    fn into_response(result: Result<ServiceResponse, Error>) -> HttpResponse {
+        match result {
+            Ok(res) => res.into_parts().1,
+            Err(err) => err.error_response(),
+        }
+    }
+
+    fn post(uri: &str) -> actix_test::TestRequest {
+        actix_test::TestRequest::post().uri(uri)
+    }
+
+    fn header<'a>(res: &'a HttpResponse, name: &HeaderName) -> &'a str {
+        res.headers().get(name).unwrap().to_str().unwrap()
+    }
+
+    #[actix_web::test]
+    async fn reports_limit_and_rounds_waits_up_to_whole_seconds() {
+        // One token every 30 seconds
+        let app = actix_test::init_service(
+            App::new().app_data(limits("2/60")).service(
+                web::scope("/auth")
+                    .wrap(RateLimiter::per_ip("auth"))
+                    .route("/login", web::post().to(HttpResponse::Ok)),
+            ),
+        )
+        .await;
+
+        let res = into_response(actix_test::try_call_service(&app, post("/auth/login").to_request()).await);
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(header(&res, &LIMIT_HEADER), "2");
+        assert_eq!(header(&res, &REMAINING_HEADER), "1");
+        assert_eq!(header(&res, &RESET_HEADER), "30");
+
+        let res = into_response(actix_test::try_call_service(&app, post("/auth/login").to_request()).await);
+        assert_eq!(header(&res, &REMAINING_HEADER), "0");
+        assert_eq!(header(&res, &RESET_HEADER), "60");
+
+        let res = into_response(actix_test::try_call_service(&app, post("/auth/login").to_request()).await);
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(header(&res, &header::RETRY_AFTER), "30");
+        assert_eq!(header(&res, &REMAINING_HEADER), "0");
+        assert_eq!(header(&res, &RESET_HEADER), "60");
+    }
+
+    #[actix_web::test]
+    async fn adds_headers_to_errors_from_inner_middleware() {
+        let app = actix_test::init_service(
+            App::new().app_data(limits("5/60")).service(
+                web::scope("/auth")
+                    .wrap_fn(|_, _| async { Err::<ServiceResponse, _>(actix_web::error::ErrorConflict("Busy")) })
+                    .wrap(RateLimiter::per_ip("auth"))
+                    .route("/login", web::post().to(HttpResponse::Ok)),
+            ),
+        )
+        .await;
+
+        let res = into_response(actix_test::try_call_service(&app, post("/auth/login").to_request()).await);
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+        assert_eq!(header(&res, &LIMIT_HEADER), "5");
+        assert_eq!(header(&res, &REMAINING_HEADER), "4");
+    }
+
+    #[actix_web::test]
+    async fn nested_limiters_report_the_tighter_bucket() {
+        let limits = web::Data::new(RateLimits {
+            store: Box::new(MemoryStore::new()),
+            routes: [
+                ("api_ip".to_string(), "10/60".parse().unwrap()),
+                ("games".to_string(), "2/60".parse().unwrap()),
+            ]
+            .into(),
+        });
+        let app = actix_test::init_service(
+            App::new().app_data(limits).service(
+                web::scope("/games")
+                    .wrap(RateLimiter::per_user("games"))
+                    .wrap(RateLimiter::per_ip("api_ip"))
+                    .route("", web::post().to(HttpResponse::Ok)),
+            ),
+        )
+        .await;
+
+        let res = into_response(actix_test::try_call_service(&app, post("/games").to_request()).await);
+        assert_eq!(header(&res, &LIMIT_HEADER), "2");
+        assert_eq!(header(&res, &REMAINING_HEADER), "1");
+
+        actix_test::try_call_service(&app, post("/games").to_request()).await.unwrap();
+        let res = into_response(actix_test::try_call_service(&app, post("/games").to_request()).await);
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(header(&res, &LIMIT_HEADER), "2");
+        assert_eq!(header(&res, &REMAINING_HEADER), "0");
+    }
+
+    #[actix_web::test]
+    async fn routes_without_a_limit_pass_through() {
+        let app = actix_test::init_service(
+            App::new().app_data(limits("1/60")).service(
+                web::scope("/games")
+                    .wrap(RateLimiter::per_ip("games"))
+                    .route("", web::post().to(HttpResponse::Ok)),
+            ),
+        )
+        .await;
+
+        for _ in 0..3 {
+            let res = into_response(actix_test::try_call_service(&app, post("/games").to_request()).await);
+            assert_eq!(res.status(), StatusCode::OK);
+            assert!(res.headers().get(LIMIT_HEADER).is_none());
+        }
+    }
+}
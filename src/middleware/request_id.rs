@@ -0,0 +1,189 @@
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{HeaderName, HeaderValue, USER_AGENT},
+    http::StatusCode,
+    Error, HttpMessage, HttpResponse, ResponseError,
+};
+use futures_util::future::{ready, LocalBoxFuture, Ready};
+use std::fmt;
+use tracing::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use std::rc::Rc;
+use std::time::Instant;
+use uuid::Uuid;
+
+use crate::client_ip::client_ip;
+use crate::logging;
+use crate::telemetry;
+use crate::middleware::auth::Claims;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+// Accepts the caller's X-Request-ID (or generates one), tags every log line written while the
+// request is handled, echoes it on the response (errors included) and writes the access log.
+// Also opens the request's tracing span.
+// Register it as the outermost middleware so everything else runs inside its scope.
+pub struct RequestTracing;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestTracing
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RequestTracingService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestTracingService {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct RequestTracingService<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestTracingService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+
+        let request_id = req
+            .headers()
+            .get(&REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .filter(|value| is_valid_request_id(value))
+            .map(str::to_string)
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+        let method = req.method().clone();
+        let path = req.path().to_string();
+        let ip = client_ip(req.request());
+        let user_agent = req
+            .headers()
+            .get(USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+
+        // Server span for the whole request, continuing the caller's trace when it sent `traceparent`
+        let route = req.match_pattern();
+        let span = tracing::info_span!(
+            "HTTP request",
+            otel.name = format!("{} {}", method, route.as_deref().unwrap_or("<unmatched>")),
+            otel.kind = "server",
+            otel.status_code = tracing::field::Empty,
+            http.request.method = method.as_str(),
+            http.route = route,
+            url.path = path.as_str(),
+            http.response.status_code = tracing::field::Empty,
+            request_id = request_id.as_str(),
+            user.id = tracing::field::Empty,
+        );
+        let _ = span.set_parent(telemetry::extract_context(req.headers()));
+        let request_span = span.clone();
+
+        let future = logging::with_request_id(request_id.clone(), async move {
+            let started = Instant::now();
+
+            let (res, status, user_id, actor_id) = match service.call(req).await {
+                Ok(mut res) => {
+                    if let Ok(value) = HeaderValue::from_str(&request_id) {
+                        res.headers_mut().insert(REQUEST_ID_HEADER, value);
+                    }
+                    let (user_id, actor_id) = res
+                        .request()
+                        .extensions()
+                        .get::<Claims>()
+                        .map(|claims| (Some(claims.sub.clone()), claims.act.as_ref().map(|act| act.sub.clone())))
+                        .unwrap_or_default();
+                    let status = res.status().as_u16();
+                    (Ok(res), status, user_id, actor_id)
+                }
+                // Middleware rejections (e.g. 401 from AuthMiddleware) never reach a handler
+                Err(err) => {
+                    let status = err.as_response_error().status_code().as_u16();
+                    let err = Error::from(WithRequestId { inner: err, request_id });
+                    (Err(err), status, None, None)
+                }
+            };
+
+            request_span.record("http.response.status_code", status);
+            if status >= 500 {
+                request_span.record("otel.status_code", "ERROR");
+            }
+            if let Some(user_id) = &user_id {
+                request_span.record("user.id", user_id.as_str());
+            }
+
+            log::info!(
+                target: "access",
+                method = method.as_str(),
+                path = path.as_str(),
+                status = status,
+                duration_ms = started.elapsed().as_secs_f64() * 1000.0,
+                user_id = user_id,
+                actor_id = actor_id,
+                ip = ip,
+                user_agent = user_agent;
+                "{} {} {}",
+                method,
+                path,
+                status
+            );
+
+            res
+        });
+        Box::pin(future.instrument(span))
+    }
+}
+
+// Error responses are built after this middleware returns, so the header is added there
+#[derive(Debug)]
+struct WithRequestId {
+    inner: Error,
+    request_id: String,
+}
+
+impl fmt::Display for WithRequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+impl ResponseError for WithRequestId {
+    fn status_code(&self) -> StatusCode {
+        self.inner.as_response_error().status_code()
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut res = self.inner.error_response();
+        if let Ok(value) = HeaderValue::from_str(&self.request_id) {
+            res.headers_mut().insert(REQUEST_ID_HEADER, value);
+        }
+        res
+    }
+}
+
+// Client-supplied IDs end up in logs, so only short header-safe tokens are kept
+fn is_valid_request_id(value: &str) -> bool {
+    !value.is_empty()
+        && value.len() <= 128
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b':'))
+}
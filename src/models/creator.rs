@@ -11,6 +11,7 @@ pub struct Model {
     pub first_name: String,
     pub last_name: String,
     pub email: String,
+    pub user_id: Option<Uuid>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
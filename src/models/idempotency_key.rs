@@ -0,0 +1,26 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+// A POST made with an `Idempotency-Key` header. The response columns stay empty while the
+// first request is still running.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "idempotency_keys")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub key: String,
+    pub fingerprint: String, // sha256 of method, path and body
+    pub response_status: Option<i16>,
+    pub response_content_type: Option<String>,
+    pub response_headers: Option<Json>, // replayed headers (REPLAYED_HEADERS), name -> value
+    pub response_body: Option<Vec<u8>>,
+    pub created_at: DateTimeWithTimeZone,
+    pub started_at: DateTimeWithTimeZone, // when the current attempt claimed the key
+    pub expires_at: DateTimeWithTimeZone,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -1,3 +1,14 @@
+pub mod api_key;
 pub mod creator;
+pub mod email_change_token;
+pub mod email_verification_token;
 pub mod game;
+pub mod idempotency_key;
+pub mod invitation;
+pub mod password_reset_token;
+pub mod recovery_code;
+pub mod security_event;
+pub mod session;
+pub mod sso_login_code;
 pub mod user;
+pub mod user_identity;
@@ -9,6 +9,12 @@ pub struct Model {
     pub email: String,
     pub password_hash: String,
     pub role: String,
+    pub email_verified_at: Option<DateTimeWithTimeZone>,
+    pub totp_secret: Option<String>,
+    pub totp_enabled_at: Option<DateTimeWithTimeZone>,
+    pub totp_last_step: Option<i64>, // last accepted TOTP time step; codes at or before it are replays
+    pub failed_login_attempts: i32,
+    pub locked_until: Option<DateTimeWithTimeZone>,
     pub created_at: DateTimeWithTimeZone,
     pub updated_at: DateTimeWithTimeZone,
 }
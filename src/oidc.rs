@@ -0,0 +1,188 @@
+use actix_web::cookie::{time, Cookie, SameSite};
+use openidconnect::core::{CoreClient, CoreProviderMetadata, CoreResponseType};
+use openidconnect::reqwest::async_http_client;
+use openidconnect::http::header::{HeaderName, HeaderValue};
+use openidconnect::{
+    AuthenticationFlow, AuthorizationCode, ClientId, ClientSecret, CsrfToken, HttpRequest, HttpResponse,
+    IssuerUrl, Nonce, PkceCodeChallenge, PkceCodeVerifier, RedirectUrl, Scope, TokenResponse,
+};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+
+use crate::config;
+use crate::telemetry;
+use crate::tokens::hash_token;
+
+const PENDING_LOGIN_TTL: Duration = Duration::from_secs(600);
+
+// Holds a hash of the pending `state`, so only the browser that started the login can finish it
+pub const STATE_COOKIE: &str = "oidc_state";
+const STATE_COOKIE_PATH: &str = "/api/auth/oidc";
+
+pub struct OidcConfig {
+    pub issuer_url: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub redirect_url: String,
+    pub default_role: String,
+    pub frontend_redirect_url: Option<String>,
+}
+
+impl OidcConfig {
+    // SSO is only enabled when oidc.issuer_url is set; validation made sure the rest is there
+    pub fn from_config(config: &config::OidcConfig) -> Option<Self> {
+        Some(Self {
+            issuer_url: config.issuer_url.clone()?,
+            client_id: config.client_id.clone()?,
+            client_secret: config.client_secret.clone(),
+            redirect_url: config.redirect_url.clone()?,
+            default_role: config.default_role.clone(),
+            frontend_redirect_url: config.frontend_redirect_url.clone(),
+        })
+    }
+}
+
+// Identity asserted by the provider's verified ID token
+pub struct ExternalIdentity {
+    pub issuer: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub email_verified: bool,
+}
+
+struct PendingLogin {
+    pkce_verifier: PkceCodeVerifier,
+    nonce: Nonce,
+    created_at: Instant,
+}
+
+pub struct OidcProvider {
+    pub config: OidcConfig,
+    client: OnceCell<CoreClient>,
+    pending: Mutex<HashMap<String, PendingLogin>>,
+}
+
+impl OidcProvider {
+    pub fn new(config: OidcConfig) -> Self {
+        Self {
+            config,
+            client: OnceCell::new(),
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Discovery runs on first use and is cached for the lifetime of the process
+    async fn client(&self) -> anyhow::Result<&CoreClient> {
+        self.client
+            .get_or_try_init(|| async {
+                let issuer_url = IssuerUrl::new(self.config.issuer_url.clone())?;
+                let metadata = CoreProviderMetadata::discover_async(issuer_url, traced_http_client).await?;
+
+                Ok(CoreClient::from_provider_metadata(
+                    metadata,
+                    ClientId::new(self.config.client_id.clone()),
+                    self.config.client_secret.clone().map(ClientSecret::new),
+                )
+                .set_redirect_uri(RedirectUrl::new(self.config.redirect_url.clone())?))
+            })
+            .await
+    }
+
+    // Returns the provider URL and the cookie binding this login to the current browser
+    pub async fn authorize_url(&self) -> anyhow::Result<(String, Cookie<'static>)> {
+        let client = self.client().await?;
+        let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+
+        let (url, state, nonce) = client
+            .authorize_url(
+                AuthenticationFlow::<CoreResponseType>::AuthorizationCode,
+                CsrfToken::new_random,
+                Nonce::new_random,
+            )
+            .add_scope(Scope::new("email".to_string()))
+            .add_scope(Scope::new("profile".to_string()))
+            .set_pkce_challenge(pkce_challenge)
+            .url();
+
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|_, login| login.created_at.elapsed() < PENDING_LOGIN_TTL);
+        pending.insert(
+            state.secret().clone(),
+            PendingLogin {
+                pkce_verifier,
+                nonce,
+                created_at: Instant::now(),
+            },
+        );
+
+        let cookie = Cookie::build(STATE_COOKIE, hash_token(state.secret()))
+            .path(STATE_COOKIE_PATH)
+            .max_age(time::Duration::seconds(PENDING_LOGIN_TTL.as_secs() as i64))
+            .http_only(true)
+            // Lax, not Strict: the callback is a top-level redirect from the provider's site
+            .same_site(SameSite::Lax)
+            .secure(self.config.redirect_url.starts_with("https://"))
+            .finish();
+
+        Ok((url.to_string(), cookie))
+    }
+
+    // Expires the state cookie once the callback has been handled
+    pub fn clear_state_cookie(&self) -> Cookie<'static> {
+        let mut cookie = Cookie::build(STATE_COOKIE, "")
+            .path(STATE_COOKIE_PATH)
+            .http_only(true)
+            .same_site(SameSite::Lax)
+            .secure(self.config.redirect_url.starts_with("https://"))
+            .finish();
+        cookie.make_removal();
+        cookie
+    }
+
+    // Exchanges the authorization code and verifies the returned ID token. `state_cookie` is the
+    // STATE_COOKIE value sent by the browser; a callback opened in any other browser is refused.
+    pub async fn exchange(&self, code: &str, state: &str, state_cookie: Option<&str>) -> anyhow::Result<ExternalIdentity> {
+        if state_cookie != Some(hash_token(state).as_str()) {
+            anyhow::bail!("Login state does not belong to this browser");
+        }
+
+        let pending = self
+            .pending
+            .lock()
+            .unwrap()
+            .remove(state)
+            .filter(|login| login.created_at.elapsed() < PENDING_LOGIN_TTL)
+            .ok_or_else(|| anyhow::anyhow!("Unknown or expired login state"))?;
+
+        let client = self.client().await?;
+        let token_response = client
+            .exchange_code(AuthorizationCode::new(code.to_string()))
+            .set_pkce_verifier(pending.pkce_verifier)
+            .request_async(traced_http_client)
+            .await?;
+
+        let id_token = token_response
+            .id_token()
+            .ok_or_else(|| anyhow::anyhow!("Provider did not return an ID token"))?;
+        let claims = id_token.claims(&client.id_token_verifier(), &pending.nonce)?;
+
+        Ok(ExternalIdentity {
+            issuer: claims.issuer().to_string(),
+            subject: claims.subject().to_string(),
+            email: claims.email().map(|email| email.to_string()),
+            email_verified: claims.email_verified().unwrap_or(false),
+        })
+    }
+}
+
+// Calls to the provider carry `traceparent`, so they show up in the same trace
+async fn traced_http_client(mut request: HttpRequest) -> Result<HttpResponse, impl std::error::Error> {
+    for (name, value) in telemetry::propagation_headers() {
+        if let (Ok(name), Ok(value)) = (HeaderName::from_bytes(name.as_bytes()), HeaderValue::from_str(&value)) {
+            request.headers.insert(name, value);
+        }
+    }
+    async_http_client(request).await
+}
@@ -0,0 +1,379 @@
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+use uuid::Uuid;
+
+use crate::config::PasswordHashingConfig;
+
+static POLICY: OnceLock<PasswordPolicy> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    Argon2id,
+    Bcrypt,
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "argon2id" => Ok(Self::Argon2id),
+            "bcrypt" => Ok(Self::Bcrypt),
+            _ => Err(()),
+        }
+    }
+}
+
+pub struct PasswordPolicy {
+    algorithm: HashAlgorithm,
+    bcrypt_cost: u32,
+    argon2_params: Params,
+    // Caps how many hashes run at once so a login storm can't eat every blocking thread
+    concurrency: usize,
+    limiter: Semaphore,
+    stats: HashingCounters,
+}
+
+#[derive(Default)]
+struct HashingCounters {
+    waiting: AtomicUsize,
+    running: AtomicUsize,
+    completed: AtomicU64,
+    queue_micros_total: AtomicU64,
+    queue_micros_max: AtomicU64,
+    run_micros_total: AtomicU64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HashingStats {
+    pub concurrency_limit: usize,
+    pub waiting: usize,
+    pub running: usize,
+    pub completed: u64,
+    pub avg_queue_ms: f64,
+    pub max_queue_ms: f64,
+    pub avg_run_ms: f64,
+}
+
+impl PasswordPolicy {
+    // Argon2id is tuned with memory/iterations/parallelism, bcrypt with its cost.
+    // `concurrency` limits parallel hashes (defaults to the number of CPUs).
+    pub fn new(config: &PasswordHashingConfig) -> anyhow::Result<Self> {
+        let argon2_params = Params::new(
+            config.argon2_memory_kib,
+            config.argon2_iterations,
+            config.argon2_parallelism,
+            None,
+        )
+        .map_err(|e| anyhow::anyhow!("Invalid Argon2 parameters: {e}"))?;
+
+        let concurrency = config
+            .concurrency
+            .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
+
+        Ok(Self {
+            algorithm: config.algorithm,
+            bcrypt_cost: config.bcrypt_cost,
+            argon2_params,
+            concurrency,
+            limiter: Semaphore::new(concurrency),
+            stats: HashingCounters::default(),
+        })
+    }
+
+    fn argon2(&self) -> Argon2<'static> {
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, self.argon2_params.clone())
+    }
+}
+
+pub fn init(config: &PasswordHashingConfig) -> anyhow::Result<()> {
+    let policy = PasswordPolicy::new(config)?;
+    POLICY
+        .set(policy)
+        .map_err(|_| anyhow::anyhow!("Password policy already initialized"))
+}
+
+fn policy() -> &'static PasswordPolicy {
+    POLICY.get().expect("Password policy not initialized")
+}
+
+// Hashes with the configured algorithm; the result is a self-describing PHC/bcrypt string
+#[tracing::instrument(name = "password.hash", skip_all, fields(queue_ms))]
+pub async fn hash(password: &str) -> anyhow::Result<String> {
+    let password = password.to_string();
+    run_limited(move |policy| hash_blocking(policy, &password)).await
+}
+
+// Verifies against either kind of stored hash, whatever the current configuration
+#[tracing::instrument(name = "password.verify", skip_all, fields(queue_ms))]
+pub async fn verify(password: &str, stored_hash: &str) -> anyhow::Result<bool> {
+    let password = password.to_string();
+    let stored_hash = stored_hash.to_string();
+    run_limited(move |_| verify_blocking(&password, &stored_hash)).await
+}
+
+// Runs CPU-heavy hashing on the blocking pool instead of an actix worker thread,
+// waiting for a slot when `password_hashing.concurrency` hashes are already running
+async fn run_limited<T, F>(work: F) -> anyhow::Result<T>
+where
+    F: FnOnce(&'static PasswordPolicy) -> anyhow::Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    let policy = policy();
+    let stats = &policy.stats;
+
+    let queued_at = Instant::now();
+    let waiting = Gauge::enter(&stats.waiting);
+    let _permit = policy
+        .limiter
+        .acquire()
+        .await
+        .map_err(|e| anyhow::anyhow!("Password hashing pool closed: {e}"))?;
+    drop(waiting);
+
+    let queue_micros = queued_at.elapsed().as_micros() as u64;
+    tracing::Span::current().record("queue_ms", queue_micros as f64 / 1000.0);
+    stats.queue_micros_total.fetch_add(queue_micros, Ordering::Relaxed);
+    stats.queue_micros_max.fetch_max(queue_micros, Ordering::Relaxed);
+
+    let running = Gauge::enter(&stats.running);
+    let started_at = Instant::now();
+    let result = actix_web::web::block(move || work(policy)).await;
+    drop(running);
+    stats.run_micros_total.fetch_add(started_at.elapsed().as_micros() as u64, Ordering::Relaxed);
+    stats.completed.fetch_add(1, Ordering::Relaxed);
+
+    result.map_err(|e| anyhow::anyhow!("Password hashing task failed: {e}"))?
+}
+
+// Keeps a gauge accurate even when the request future is dropped mid-wait
+struct Gauge<'a>(&'a AtomicUsize);
+
+impl<'a> Gauge<'a> {
+    fn enter(counter: &'a AtomicUsize) -> Self {
+        counter.fetch_add(1, Ordering::Relaxed);
+        Self(counter)
+    }
+}
+
+impl Drop for Gauge<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+pub fn stats() -> HashingStats {
+    let policy = policy();
+    let stats = &policy.stats;
+    let completed = stats.completed.load(Ordering::Relaxed);
+    let average = |total: &AtomicU64| {
+        if completed == 0 {
+            0.0
+        } else {
+            total.load(Ordering::Relaxed) as f64 / completed as f64 / 1000.0
+        }
+    };
+
+    HashingStats {
+        concurrency_limit: policy.concurrency,
+        waiting: stats.waiting.load(Ordering::Relaxed),
+        running: stats.running.load(Ordering::Relaxed),
+        completed,
+        avg_queue_ms: average(&stats.queue_micros_total),
+        max_queue_ms: stats.queue_micros_max.load(Ordering::Relaxed) as f64 / 1000.0,
+        avg_run_ms: average(&stats.run_micros_total),
+    }
+}
+
+fn hash_blocking(policy: &PasswordPolicy, password: &str) -> anyhow::Result<String> {
+    match policy.algorithm {
+        HashAlgorithm::Argon2id => {
+            let salt = SaltString::generate(&mut OsRng);
+            let hash = policy
+                .argon2()
+                .hash_password(password.as_bytes(), &salt)
+                .map_err(|e| anyhow::anyhow!("Argon2 hashing failed: {e}"))?;
+            Ok(hash.to_string())
+        }
+        HashAlgorithm::Bcrypt => Ok(bcrypt::hash(password, policy.bcrypt_cost)?),
+    }
+}
+
+fn verify_blocking(password: &str, stored_hash: &str) -> anyhow::Result<bool> {
+    if is_bcrypt(stored_hash) {
+        return Ok(bcrypt::verify(password, stored_hash)?);
+    }
+
+    let parsed = PasswordHash::new(stored_hash)
+        .map_err(|e| anyhow::anyhow!("Unrecognised password hash: {e}"))?;
+    match Argon2::default().verify_password(password.as_bytes(), &parsed) {
+        Ok(()) => Ok(true),
+        Err(argon2::password_hash::Error::Password) => Ok(false),
+        Err(e) => Err(anyhow::anyhow!("Argon2 verification failed: {e}")),
+    }
+}
+
+// True when the stored hash was produced with a different algorithm or weaker parameters
+pub fn needs_rehash(stored_hash: &str) -> bool {
+    policy().needs_rehash(stored_hash)
+}
+
+impl PasswordPolicy {
+    fn needs_rehash(&self, stored_hash: &str) -> bool {
+        match self.algorithm {
+            HashAlgorithm::Bcrypt => bcrypt_cost(stored_hash) != Some(self.bcrypt_cost),
+            HashAlgorithm::Argon2id => {
+                let Ok(parsed) = PasswordHash::new(stored_hash) else {
+                    return true;
+                };
+                if parsed.algorithm != Algorithm::Argon2id.ident()
+                    || parsed.version != Some(Version::V0x13.into())
+                {
+                    return true;
+                }
+                match Params::try_from(&parsed) {
+                    Ok(params) => {
+                        params.m_cost() != self.argon2_params.m_cost()
+                            || params.t_cost() != self.argon2_params.t_cost()
+                            || params.p_cost() != self.argon2_params.p_cost()
+                    }
+                    Err(_) => true,
+                }
+            }
+        }
+    }
+}
+
+fn is_bcrypt(stored_hash: &str) -> bool {
+    ["$2a$", "$2b$", "$2x$", "$2y$"]
+        .iter()
+        .any(|prefix| stored_hash.starts_with(prefix))
+}
+
+// bcrypt hashes look like `$2b$12$<salt+hash>`
+fn bcrypt_cost(stored_hash: &str) -> Option<u32> {
+    if !is_bcrypt(stored_hash) {
+        return None;
+    }
+    stored_hash.get(4..6)?.parse().ok()
+}
+
+// Upgraded hashes computed at the password step of a two-step login. The plaintext is gone by
+// the time the second factor is checked, so the new hash waits here and is only written once
+// the code is verified. Kept per process: a challenge finished on another instance simply
+// upgrades on a later login.
+pub struct PendingRehashes {
+    ttl: Duration,
+    hashes: Mutex<HashMap<Uuid, (String, Instant)>>,
+}
+
+impl PendingRehashes {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            hashes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn insert(&self, user_id: Uuid, password_hash: String) {
+        let mut hashes = self.hashes.lock().unwrap();
+        hashes.retain(|_, (_, created_at)| created_at.elapsed() < self.ttl);
+        hashes.insert(user_id, (password_hash, Instant::now()));
+    }
+
+    pub fn take(&self, user_id: Uuid) -> Option<String> {
+        self.hashes
+            .lock()
+            .unwrap()
+            .remove(&user_id)
+            .filter(|(_, created_at)| created_at.elapsed() < self.ttl)
+            .map(|(password_hash, _)| password_hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Cheapest parameters each algorithm accepts, so the tests stay fast
+    fn policy(algorithm: HashAlgorithm, argon2_iterations: u32) -> PasswordPolicy {
+        PasswordPolicy::new(&PasswordHashingConfig {
+            algorithm,
+            bcrypt_cost: 4,
+            argon2_memory_kib: 8,
+            argon2_iterations,
+            argon2_parallelism: 1,
+            concurrency: Some(1),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn argon2id_hash_verifies() {
+        let hash = hash_blocking(&policy(HashAlgorithm::Argon2id, 1), "correct horse").unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(verify_blocking("correct horse", &hash).unwrap());
+        assert!(!verify_blocking("wrong horse", &hash).unwrap());
+    }
+
+    #[test]
+    fn bcrypt_hash_verifies() {
+        let hash = hash_blocking(&policy(HashAlgorithm::Bcrypt, 1), "correct horse").unwrap();
+        assert!(is_bcrypt(&hash));
+        assert_eq!(bcrypt_cost(&hash), Some(4));
+        assert!(verify_blocking("correct horse", &hash).unwrap());
+        assert!(!verify_blocking("wrong horse", &hash).unwrap());
+    }
+
+    #[test]
+    fn unrecognised_hash_is_an_error() {
+        assert!(verify_blocking("password", "plaintext").is_err());
+    }
+
+    #[test]
+    fn rehash_when_algorithm_changes() {
+        let bcrypt_hash = hash_blocking(&policy(HashAlgorithm::Bcrypt, 1), "pw").unwrap();
+        let argon2_hash = hash_blocking(&policy(HashAlgorithm::Argon2id, 1), "pw").unwrap();
+        assert!(policy(HashAlgorithm::Argon2id, 1).needs_rehash(&bcrypt_hash));
+        assert!(policy(HashAlgorithm::Bcrypt, 1).needs_rehash(&argon2_hash));
+    }
+
+    #[test]
+    fn rehash_when_parameters_change() {
+        let hash = hash_blocking(&policy(HashAlgorithm::Argon2id, 1), "pw").unwrap();
+        assert!(!policy(HashAlgorithm::Argon2id, 1).needs_rehash(&hash));
+        assert!(policy(HashAlgorithm::Argon2id, 2).needs_rehash(&hash));
+    }
+
+    #[test]
+    fn bcrypt_cost_is_read_from_the_hash() {
+        assert_eq!(bcrypt_cost("$2b$12$abcdefghijklmnopqrstuv"), Some(12));
+        assert_eq!(bcrypt_cost("$argon2id$v=19$m=8,t=1,p=1$abc$def"), None);
+    }
+
+    #[test]
+    fn pending_rehash_is_taken_once() {
+        let pending = PendingRehashes::new(Duration::from_secs(60));
+        let user_id = Uuid::new_v4();
+        pending.insert(user_id, "hash".to_string());
+        assert_eq!(pending.take(user_id).as_deref(), Some("hash"));
+        assert_eq!(pending.take(user_id), None);
+    }
+
+    #[test]
+    fn pending_rehash_expires() {
+        let pending = PendingRehashes::new(Duration::ZERO);
+        let user_id = Uuid::new_v4();
+        pending.insert(user_id, "hash".to_string());
+        assert_eq!(pending.take(user_id), None);
+    }
+}
@@ -0,0 +1,201 @@
+use actix_web::http::header;
+use actix_web::{HttpRequest, HttpResponse};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+pub const MERGE_PATCH: &str = "application/merge-patch+json";
+pub const JSON_PATCH: &str = "application/json-patch+json";
+
+pub enum PatchError {
+    // Content-Type is neither patch format (415)
+    UnsupportedMediaType,
+    // The body is not a valid document of its format (400)
+    Malformed(String),
+    // A JSON Patch operation does not apply to the current resource, e.g. a failed `test` (409)
+    Conflict(String),
+    // The patched resource is not a valid resource (422)
+    Invalid(String),
+}
+
+impl PatchError {
+    pub fn response(&self) -> HttpResponse {
+        match self {
+            Self::UnsupportedMediaType => HttpResponse::UnsupportedMediaType()
+                .insert_header(("Accept-Patch", format!("{MERGE_PATCH}, {JSON_PATCH}")))
+                .body(format!("PATCH requires Content-Type {MERGE_PATCH} or {JSON_PATCH}")),
+            Self::Malformed(message) => HttpResponse::BadRequest().body(message.clone()),
+            Self::Conflict(message) => HttpResponse::Conflict().body(message.clone()),
+            Self::Invalid(message) => HttpResponse::UnprocessableEntity().body(message.clone()),
+        }
+    }
+}
+
+// Applies a JSON Merge Patch (RFC 7396) or JSON Patch (RFC 6902) body to the JSON form of
+// `current` and reads the result as the full replacement `T`. Members in `read_only` may not
+// change and members the model does not have may not be added.
+pub fn apply<M: Serialize, T: DeserializeOwned>(
+    req: &HttpRequest,
+    current: &M,
+    body: &[u8],
+    read_only: &[&str],
+) -> Result<T, PatchError> {
+    let content_type = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .map(|value| value.trim().to_ascii_lowercase())
+        .ok_or(PatchError::UnsupportedMediaType)?;
+
+    let original = serde_json::to_value(current).map_err(|e| PatchError::Invalid(e.to_string()))?;
+    let mut document = original.clone();
+
+    match content_type.as_str() {
+        MERGE_PATCH => {
+            let patch: Value = serde_json::from_slice(body)
+                .map_err(|e| PatchError::Malformed(format!("Invalid merge patch: {e}")))?;
+            if !patch.is_object() {
+                return Err(PatchError::Malformed("Merge patch must be a JSON object".to_string()));
+            }
+            json_patch::merge(&mut document, &patch);
+        }
+        JSON_PATCH => {
+            let patch: json_patch::Patch = serde_json::from_slice(body)
+                .map_err(|e| PatchError::Malformed(format!("Invalid JSON patch: {e}")))?;
+            json_patch::patch(&mut document, &patch.0).map_err(|e| PatchError::Conflict(e.to_string()))?;
+        }
+        _ => return Err(PatchError::UnsupportedMediaType),
+    }
+
+    let (Some(original), Some(patched)) = (original.as_object(), document.as_object()) else {
+        return Err(PatchError::Invalid("Patched document must be a JSON object".to_string()));
+    };
+    if let Some(field) = patched.keys().find(|field| !original.contains_key(*field)) {
+        return Err(PatchError::Invalid(format!("Unknown field '{field}'")));
+    }
+    if let Some(field) = read_only.iter().find(|field| original.get(**field) != patched.get(**field)) {
+        return Err(PatchError::Invalid(format!("Field '{field}' is read-only")));
+    }
+
+    serde_json::from_value(document).map_err(|e| PatchError::Invalid(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::test::TestRequest;
+    use serde::Deserialize;
+
+    const READ_ONLY: &[&str] = &["id", "created_at"];
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Game {
+        id: u32,
+        name: String,
+        description: Option<String>,
+        created_at: String,
+    }
+
+    fn game() -> Game {
+        Game {
+            id: 7,
+            name: "Tetris".to_string(),
+            description: Some("Blocks".to_string()),
+            created_at: "2025-06-01T00:00:00Z".to_string(),
+        }
+    }
+
+    fn patch(content_type: &str, body: &str) -> Result<Game, PatchError> {
+        let req = TestRequest::patch()
+            .insert_header((header::CONTENT_TYPE, content_type))
+            .to_http_request();
+        apply(&req, &game(), body.as_bytes(), READ_ONLY)
+    }
+
+    fn status(result: Result<Game, PatchError>) -> StatusCode {
+        match result {
+            Ok(_) => StatusCode::OK,
+            Err(e) => e.response().status(),
+        }
+    }
+
+    #[test]
+    fn merge_patch_updates_and_clears_fields() {
+        let patched = patch(MERGE_PATCH, r#"{"name": "Tetris 2", "description": null}"#).ok().unwrap();
+        assert_eq!(patched.name, "Tetris 2");
+        assert_eq!(patched.description, None);
+        assert_eq!(patched.id, 7);
+
+        // A media type parameter does not change the format
+        assert!(patch("application/merge-patch+json; charset=utf-8", "{}").is_ok());
+    }
+
+    #[test]
+    fn merge_patch_null_on_read_only_field_is_rejected() {
+        assert_eq!(status(patch(MERGE_PATCH, r#"{"id": null}"#)), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(status(patch(MERGE_PATCH, r#"{"created_at": null}"#)), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(status(patch(MERGE_PATCH, r#"{"id": 8}"#)), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[test]
+    fn merge_patch_unknown_fields() {
+        // Removing a member that does not exist changes nothing
+        assert_eq!(patch(MERGE_PATCH, r#"{"owner": null}"#).ok(), Some(game()));
+        assert_eq!(status(patch(MERGE_PATCH, r#"{"owner": "me"}"#)), StatusCode::UNPROCESSABLE_ENTITY);
+        // Required members cannot be removed
+        assert_eq!(status(patch(MERGE_PATCH, r#"{"name": null}"#)), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[test]
+    fn json_patch_applies_operations_in_order() {
+        let body = r#"[
+            {"op": "test", "path": "/name", "value": "Tetris"},
+            {"op": "replace", "path": "/name", "value": "Tetris 2"},
+            {"op": "remove", "path": "/description"}
+        ]"#;
+        let patched = patch(JSON_PATCH, body).ok().unwrap();
+        assert_eq!(patched.name, "Tetris 2");
+        assert_eq!(patched.description, None);
+    }
+
+    #[test]
+    fn json_patch_failed_test_is_a_conflict() {
+        let body = r#"[
+            {"op": "test", "path": "/name", "value": "Pong"},
+            {"op": "replace", "path": "/name", "value": "Tetris 2"}
+        ]"#;
+        assert_eq!(status(patch(JSON_PATCH, body)), StatusCode::CONFLICT);
+        let body = r#"[{"op": "replace", "path": "/missing/deep", "value": 1}]"#;
+        assert_eq!(status(patch(JSON_PATCH, body)), StatusCode::CONFLICT);
+    }
+
+    #[test]
+    fn json_patch_cannot_touch_read_only_fields() {
+        for body in [
+            r#"[{"op": "replace", "path": "/id", "value": 8}]"#,
+            r#"[{"op": "remove", "path": "/id"}]"#,
+            r#"[{"op": "replace", "path": "/created_at", "value": "2000-01-01T00:00:00Z"}]"#,
+            r#"[{"op": "copy", "from": "/name", "path": "/created_at"}]"#,
+            r#"[{"op": "move", "from": "/created_at", "path": "/description"}]"#,
+        ] {
+            assert_eq!(status(patch(JSON_PATCH, body)), StatusCode::UNPROCESSABLE_ENTITY, "{body}");
+        }
+        // Writing the current value back is not a change
+        let body = r#"[{"op": "replace", "path": "/id", "value": 7}]"#;
+        assert!(patch(JSON_PATCH, body).is_ok());
+    }
+
+    #[test]
+    fn rejects_other_media_types_and_malformed_bodies() {
+        assert_eq!(status(patch("application/json", "{}")), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        let req = TestRequest::patch().to_http_request();
+        let missing: Result<Game, _> = apply(&req, &game(), b"{}", READ_ONLY);
+        assert_eq!(status(missing), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        assert_eq!(status(patch(MERGE_PATCH, "{")), StatusCode::BAD_REQUEST);
+        assert_eq!(status(patch(MERGE_PATCH, "[]")), StatusCode::BAD_REQUEST);
+        assert_eq!(status(patch(JSON_PATCH, r#"[{"op": "frobnicate", "path": "/name"}]"#)), StatusCode::BAD_REQUEST);
+    }
+}
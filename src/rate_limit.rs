@@ -0,0 +1,199 @@
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// `requests` per `per_secs` seconds, written `"120/60"` in config. Token bucket: up to
+// `requests` can be spent at once, then they refill evenly over the period.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct RateLimit {
+    pub requests: u32,
+    pub per_secs: u64,
+}
+
+impl RateLimit {
+    fn refill_per_sec(&self) -> f64 {
+        self.requests as f64 / self.per_secs as f64
+    }
+}
+
+impl FromStr for RateLimit {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid rate limit '{value}' (expected requests/seconds, e.g. 120/60)");
+        let (requests, per_secs) = value.trim().split_once('/').ok_or_else(invalid)?;
+        let requests: u32 = requests.trim().parse().map_err(|_| invalid())?;
+        let per_secs: u64 = per_secs.trim().parse().map_err(|_| invalid())?;
+        if requests == 0 || per_secs == 0 {
+            return Err(invalid());
+        }
+        Ok(Self { requests, per_secs })
+    }
+}
+
+impl TryFrom<String> for RateLimit {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl fmt::Display for RateLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.requests, self.per_secs)
+    }
+}
+
+// Outcome of taking one token, with what the X-RateLimit-* headers report
+#[derive(Debug, Clone, Copy)]
+pub struct Decision {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    // Until the bucket is full again
+    pub reset_after: Duration,
+    // Until the next token, when the request was rejected
+    pub retry_after: Option<Duration>,
+}
+
+// Where buckets live. The in-memory store limits each process separately; a shared backend
+// (e.g. Redis) implements this trait to enforce one limit across replicas.
+#[async_trait]
+pub trait RateLimitStore: Send + Sync {
+    async fn acquire(&self, key: &str, limit: RateLimit) -> anyhow::Result<Decision>;
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    // From here on the bucket is indistinguishable from a new one
+    full_at: Instant,
+}
+
+pub struct MemoryStore {
+    buckets: Mutex<HashMap<String, Bucket>>,
+    last_prune: Mutex<Instant>,
+}
+
+// Full buckets are dropped this often so idle clients do not pile up
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self {
+            buckets: Mutex::new(HashMap::new()),
+            last_prune: Mutex::new(Instant::now()),
+        }
+    }
+
+    fn prune(&self, buckets: &mut HashMap<String, Bucket>, now: Instant) {
+        let mut last_prune = self.last_prune.lock().unwrap();
+        if now.duration_since(*last_prune) < PRUNE_INTERVAL {
+            return;
+        }
+        *last_prune = now;
+        buckets.retain(|_, bucket| bucket.full_at > now);
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for MemoryStore {
+    async fn acquire(&self, key: &str, limit: RateLimit) -> anyhow::Result<Decision> {
+        let now = Instant::now();
+        let capacity = limit.requests as f64;
+        let refill_per_sec = limit.refill_per_sec();
+
+        let mut buckets = self.buckets.lock().unwrap();
+        self.prune(&mut buckets, now);
+
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+            full_at: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * refill_per_sec).min(capacity);
+        bucket.updated = now;
+
+        let allowed = bucket.tokens >= 1.0;
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+        let reset_after = Duration::from_secs_f64((capacity - bucket.tokens) / refill_per_sec);
+        bucket.full_at = now + reset_after;
+        let retry_after = (!allowed).then(|| Duration::from_secs_f64((1.0 - bucket.tokens) / refill_per_sec));
+
+        Ok(Decision {
+            allowed,
+            limit: limit.requests,
+            remaining: bucket.tokens.floor() as u32,
+            reset_after,
+            retry_after,
+        })
+    }
+}
+
+// Shared with the rate limit middleware as `web::Data<RateLimits>`
+pub struct RateLimits {
+    pub store: Box<dyn RateLimitStore>,
+    // Route scope name → limit; scopes without an entry are not limited
+    pub routes: BTreeMap<String, RateLimit>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limit(value: &str) -> RateLimit {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_requests_per_seconds() {
+        assert_eq!(limit("120/60"), RateLimit { requests: 120, per_secs: 60 });
+        assert_eq!(limit(" 5 / 1 "), RateLimit { requests: 5, per_secs: 1 });
+        assert_eq!(limit("120/60").to_string(), "120/60");
+    }
+
+    #[test]
+    fn rejects_zero_and_garbage() {
+        for value in ["0/60", "10/0", "", "10", "10/", "/60", "ten/60", "-1/60", "10/60/2", "1.5/60"] {
+            assert!(value.parse::<RateLimit>().is_err(), "{value:?} should be rejected");
+        }
+    }
+
+    #[actix_web::test]
+    async fn denies_when_empty_and_refills_over_time() {
+        let store = MemoryStore::new();
+        // One token every 500ms
+        let limit = limit("2/1");
+
+        let first = store.acquire("key", limit).await.unwrap();
+        assert!(first.allowed);
+        assert_eq!((first.limit, first.remaining), (2, 1));
+        let second = store.acquire("key", limit).await.unwrap();
+        assert!(second.allowed);
+        assert_eq!(second.remaining, 0);
+
+        let denied = store.acquire("key", limit).await.unwrap();
+        assert!(!denied.allowed);
+        assert_eq!(denied.remaining, 0);
+        let retry_after = denied.retry_after.unwrap();
+        assert!(retry_after > Duration::ZERO && retry_after <= Duration::from_millis(500));
+        assert!(denied.reset_after <= Duration::from_secs(1));
+
+        // Other keys have their own bucket
+        assert!(store.acquire("other", limit).await.unwrap().allowed);
+
+        tokio::time::sleep(retry_after + Duration::from_millis(20)).await;
+        let refilled = store.acquire("key", limit).await.unwrap();
+        assert!(refilled.allowed);
+        assert!(refilled.retry_after.is_none());
+    }
+}
@@ -1,30 +1,94 @@
-use actix_web::web;
-use crate::controllers::{creator_controller, game_controller, auth_controller};
+use actix_web::{http::Method, web};
+use crate::controllers::{
+    account_controller, admin_controller, api_key_controller, creator_controller, game_controller, auth_controller,
+    email_verification_controller, health_controller, invitation_controller, oidc_controller, session_controller,
+    two_factor_controller,
+};
 use crate::middleware::auth::AuthMiddleware;
+use crate::middleware::idempotency::Idempotency;
+use crate::middleware::rate_limit::RateLimiter;
 
 pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.route("/.well-known/jwks.json", web::get().to(auth_controller::jwks));
+
+    // Probes for orchestrators (no auth)
+    cfg.service(
+        web::scope("/health")
+            .route("/live", web::get().to(health_controller::live))
+            .route("/ready", web::get().to(health_controller::ready)),
+    );
+
     // Auth routes (no middleware required)
     cfg.service(
         web::scope("/api/auth")
+            .wrap(RateLimiter::per_ip("auth"))
             .route("/register", web::post().to(auth_controller::register))
             .route("/login", web::post().to(auth_controller::login))
+            .route("/login/mfa", web::post().to(two_factor_controller::verify_login))
+            .route("/accept-invite", web::post().to(invitation_controller::accept_invite))
+            .route("/forgot-password", web::post().to(auth_controller::forgot_password))
+            .route("/reset-password", web::post().to(auth_controller::reset_password))
+            .route("/verify-email", web::post().to(email_verification_controller::verify_email))
+            .route("/resend-verification", web::post().to(email_verification_controller::resend_verification))
+            .route("/confirm-email-change", web::post().to(account_controller::confirm_email_change))
+            .route("/oidc/login", web::get().to(oidc_controller::login))
+            .route("/oidc/callback", web::get().to(oidc_controller::callback))
+            .route("/oidc/exchange", web::post().to(oidc_controller::exchange))
     );
 
     // Protected routes with auth middleware
     cfg.service(
         web::scope("/api/auth")
+            .wrap(RateLimiter::per_user("account"))
             .wrap(AuthMiddleware::new())
             .route("/me", web::get().to(auth_controller::current_user))
     );
 
+    // Current user routes
+    cfg.service(
+        web::scope("/api/me")
+            .wrap(RateLimiter::per_user("account"))
+            .wrap(AuthMiddleware::new().forbid_impersonation())
+            .route("/password", web::post().to(account_controller::change_password))
+            .route("/email", web::post().to(account_controller::request_email_change))
+            .route("/2fa/enroll", web::post().to(two_factor_controller::enroll))
+            .route("/2fa/confirm", web::post().to(two_factor_controller::confirm))
+            .route("/2fa/disable", web::post().to(two_factor_controller::disable))
+            .route("/2fa/recovery-codes", web::post().to(two_factor_controller::regenerate_recovery_codes))
+            .route("/api-keys", web::post().to(api_key_controller::create_api_key))
+            .route("/api-keys", web::get().to(api_key_controller::list_api_keys))
+            .route("/api-keys/{id}", web::delete().to(api_key_controller::delete_api_key))
+            .route("/sessions", web::get().to(session_controller::list_sessions))
+            .route("/sessions/{id}", web::delete().to(session_controller::delete_session)),
+    );
+
+    // Admin routes
+    cfg.service(
+        web::scope("/api/admin")
+            .wrap(RateLimiter::per_user("admin"))
+            .wrap(AuthMiddleware::with_role("admin".to_string()).forbid_impersonation())
+            .route("/invitations", web::post().to(invitation_controller::create_invitation))
+            .route("/users/{id}/unlock", web::post().to(admin_controller::unlock_user))
+            .route("/impersonate/{user_id}", web::post().to(admin_controller::impersonate_user))
+            .route("/impersonations/{session_id}", web::delete().to(admin_controller::end_impersonation))
+            .route("/password-hashing", web::get().to(admin_controller::password_hashing_stats))
+            .route("/traces", web::get().to(admin_controller::list_traces))
+            .route("/traces", web::delete().to(admin_controller::clear_traces)),
+    );
+
     // Creator routes with role-based auth
     cfg.service(
         web::scope("/api/creators")
-            .wrap(AuthMiddleware::with_role("admin".to_string()))
+            .wrap(Idempotency)
+            .wrap(RateLimiter::per_user("creators"))
+            .wrap(AuthMiddleware::with_role("admin".to_string()).api_key_scope("creators"))
+            // Outermost, so unauthenticated floods are limited before token and API key checks
+            .wrap(RateLimiter::per_ip("api_ip"))
             .route("", web::post().to(creator_controller::create_creator))
             .route("", web::get().to(creator_controller::get_all_creators))
             .route("/{id}", web::get().to(creator_controller::get_creator_by_id))
             .route("/{id}", web::put().to(creator_controller::update_creator))
+            .route("/{id}", web::patch().to(creator_controller::patch_creator))
             .route("/{id}", web::delete().to(creator_controller::delete_creator))
             .route("/{id}/games", web::get().to(creator_controller::get_games_by_creator)),
     );
@@ -32,11 +96,19 @@ pub fn config(cfg: &mut web::ServiceConfig) {
     // Game routes with role-based auth
     cfg.service(
         web::scope("/api/games")
-            .wrap(AuthMiddleware::with_role("creator".to_string()))
+            .wrap(Idempotency)
+            .wrap(RateLimiter::per_user("games"))
+            .wrap(
+                AuthMiddleware::with_role("creator".to_string())
+                    .api_key_scope("games")
+                    .require_verified_email(Method::POST),
+            )
+            .wrap(RateLimiter::per_ip("api_ip"))
             .route("", web::post().to(game_controller::create_game))
             .route("", web::get().to(game_controller::list_games))
             .route("/{id}", web::get().to(game_controller::get_game))
             .route("/{id}", web::put().to(game_controller::update_game))
+            .route("/{id}", web::patch().to(game_controller::patch_game))
             .route("/{id}", web::delete().to(game_controller::delete_game))
             .route("/{id}/with-creator", web::get().to(game_controller::get_game_with_creator)),
     );
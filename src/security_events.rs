@@ -0,0 +1,43 @@
+use chrono::Utc;
+use sea_orm::{ActiveModelTrait, ConnectionTrait, Set};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::models::security_event;
+
+pub const LOGIN_FAILED: &str = "login_failed";
+pub const LOGIN_SUCCEEDED: &str = "login_succeeded";
+pub const ACCOUNT_LOCKED: &str = "account_locked";
+pub const ACCOUNT_UNLOCKED: &str = "account_unlocked";
+pub const IP_BLOCKED: &str = "ip_blocked";
+pub const PASSWORD_CHANGED: &str = "password_changed";
+pub const PASSWORD_RESET: &str = "password_reset";
+pub const EMAIL_CHANGE_REQUESTED: &str = "email_change_requested";
+pub const EMAIL_CHANGED: &str = "email_changed";
+pub const IMPERSONATION_STARTED: &str = "impersonation_started";
+pub const IMPERSONATION_ENDED: &str = "impersonation_ended";
+pub const IMPERSONATED_REQUEST: &str = "impersonated_request";
+pub const TWO_FACTOR_DISABLED: &str = "two_factor_disabled";
+pub const RECOVERY_CODES_REGENERATED: &str = "recovery_codes_regenerated";
+
+// Recording is best effort: a failure is logged but never fails the request
+pub async fn record<C: ConnectionTrait>(
+    db: &C,
+    user_id: Option<Uuid>,
+    event_type: &str,
+    ip_address: Option<&str>,
+    details: Option<Value>,
+) {
+    let event = security_event::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        user_id: Set(user_id),
+        event_type: Set(event_type.to_string()),
+        ip_address: Set(ip_address.map(|ip| ip.to_string())),
+        details: Set(details),
+        created_at: Set(Utc::now().into()),
+    };
+
+    if let Err(e) = event.insert(db).await {
+        log::warn!(error:% = e; "Security event recording error");
+    }
+}
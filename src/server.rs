@@ -0,0 +1,102 @@
+use std::fmt;
+use std::fs;
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+
+use crate::config::{ServerConfig, TlsConfig};
+
+// `0.0.0.0:8080`, `[::]:8080`, `localhost:8080` or `unix:/run/app/api.sock`
+#[derive(Debug, Clone)]
+pub enum BindAddress {
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+impl BindAddress {
+    pub fn parse(address: &str) -> anyhow::Result<Self> {
+        if let Some(path) = address.strip_prefix("unix:") {
+            if path.is_empty() {
+                anyhow::bail!("'{address}' must name a socket path, e.g. unix:/run/app/api.sock");
+            }
+            return Ok(Self::Unix(PathBuf::from(path)));
+        }
+
+        let (host, port) = address
+            .rsplit_once(':')
+            .ok_or_else(|| anyhow::anyhow!("'{address}' must be host:port, [ipv6]:port or unix:/path"))?;
+        if host.is_empty() || port.parse::<u16>().is_err() {
+            anyhow::bail!("'{address}' must be host:port, [ipv6]:port or unix:/path");
+        }
+        if host.contains(':') && !(host.starts_with('[') && host.ends_with(']')) {
+            anyhow::bail!("'{address}': IPv6 addresses must be in brackets, e.g. [::]:8080");
+        }
+        Ok(Self::Tcp(address.to_string()))
+    }
+
+    pub fn port(&self) -> Option<u16> {
+        match self {
+            Self::Tcp(tcp) => tcp.rsplit_once(':').and_then(|(_, port)| port.parse().ok()),
+            Self::Unix(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for BindAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(address) => write!(f, "{address}"),
+            Self::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+impl ServerConfig {
+    // BIND_ADDRESSES when set, otherwise HOST:PORT
+    pub fn bind_addresses(&self) -> anyhow::Result<Vec<BindAddress>> {
+        if self.bind.is_empty() {
+            let host = if self.host.contains(':') && !self.host.starts_with('[') {
+                format!("[{}]", self.host)
+            } else {
+                self.host.clone()
+            };
+            return BindAddress::parse(&format!("{host}:{}", self.port)).map(|address| vec![address]);
+        }
+        self.bind.iter().map(|address| BindAddress::parse(address)).collect()
+    }
+
+    // Port of the first TCP address, which HTTP → HTTPS redirects point at
+    pub fn https_port(&self) -> anyhow::Result<Option<u16>> {
+        Ok(self.bind_addresses()?.iter().find_map(BindAddress::port))
+    }
+}
+
+impl TlsConfig {
+    pub fn redirect_addresses(&self) -> anyhow::Result<Vec<String>> {
+        self.redirect_bind
+            .iter()
+            .map(|address| match BindAddress::parse(address)? {
+                BindAddress::Tcp(tcp) => Ok(tcp),
+                BindAddress::Unix(_) => anyhow::bail!("'{address}': redirect listeners must be TCP"),
+            })
+            .collect()
+    }
+}
+
+// Called after shutdown so the next start does not find our socket file
+pub fn remove_sockets(addresses: &[BindAddress]) {
+    for address in addresses {
+        if let BindAddress::Unix(path) = address {
+            if let Err(e) = remove_stale_socket(path) {
+                log::warn!(error:% = e, path:% = path.display(); "Socket cleanup error");
+            }
+        }
+    }
+}
+
+// A socket file left behind by an earlier run would make bind_uds fail
+pub fn remove_stale_socket(path: &Path) -> std::io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => fs::remove_file(path),
+        _ => Ok(()),
+    }
+}
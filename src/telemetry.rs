@@ -0,0 +1,301 @@
+use opentelemetry::propagation::{Extractor, Injector};
+use opentelemetry::trace::{Span, SpanKind, TraceContextExt, Tracer, TracerProvider};
+use opentelemetry::{global, Context, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{InMemorySpanExporter, Sampler, SdkTracer, SdkTracerProvider, SpanData};
+use opentelemetry_sdk::Resource;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::filter::{filter_fn, Targets};
+use tracing_subscriber::layer::{self, Layer, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+
+use crate::config::TracingConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TraceExporter {
+    // Spans are not recorded at all
+    None,
+    // Batched OTLP/HTTP (protobuf) export to `tracing.otlp_endpoint`
+    Otlp,
+    // Kept in process and served by GET /api/admin/traces, for tests and local debugging
+    Memory,
+}
+
+impl FromStr for TraceExporter {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "none" => Ok(Self::None),
+            "otlp" => Ok(Self::Otlp),
+            "memory" => Ok(Self::Memory),
+            _ => Err(()),
+        }
+    }
+}
+
+// sqlx reports each finished statement as an event with this target
+const QUERY_TARGET: &str = "sqlx::query";
+
+static IN_MEMORY_EXPORTER: OnceLock<InMemorySpanExporter> = OnceLock::new();
+
+// Flushes buffered spans when dropped at the end of `main`
+pub struct TelemetryGuard {
+    provider: Option<SdkTracerProvider>,
+}
+
+impl Drop for TelemetryGuard {
+    fn drop(&mut self) {
+        if let Some(provider) = self.provider.take() {
+            if let Err(e) = provider.shutdown() {
+                log::warn!(error:% = e; "Trace exporter shutdown error");
+            }
+        }
+    }
+}
+
+// Installs the tracing subscriber that turns our spans (requests, controllers, password hashing)
+// and sqlx query events into OpenTelemetry spans. Incoming `traceparent` headers are honoured.
+pub fn init(config: &TracingConfig) -> anyhow::Result<TelemetryGuard> {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let builder = SdkTracerProvider::builder()
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(config.sample_ratio))))
+        .with_resource(Resource::builder().with_service_name(config.service_name.clone()).build());
+
+    let provider = match config.exporter {
+        TraceExporter::None => None,
+        TraceExporter::Otlp => {
+            let exporter = opentelemetry_otlp::SpanExporter::builder()
+                .with_http()
+                .with_endpoint(otlp_traces_url(&config.otlp_endpoint))
+                .build()?;
+            Some(builder.with_batch_exporter(exporter).build())
+        }
+        TraceExporter::Memory => {
+            let exporter = InMemorySpanExporter::default();
+            let _ = IN_MEMORY_EXPORTER.set(exporter.clone());
+            Some(builder.with_simple_exporter(exporter).build())
+        }
+    };
+    let tracer = provider
+        .as_ref()
+        .map(|provider| provider.tracer("game-creator-api"));
+
+    // Installed even without an exporter: otherwise `tracing` falls back to writing every span
+    // to the JSON log
+    tracing_subscriber::registry()
+        .with(tracer.clone().map(|tracer| {
+            tracing_opentelemetry::layer()
+                .with_tracer(tracer)
+                .with_filter(Targets::new().with_target("main", Level::INFO))
+        }))
+        .with(tracer.map(|tracer| {
+            QuerySpans { tracer }.with_filter(Targets::new().with_target(QUERY_TARGET, Level::DEBUG))
+        }))
+        .with(QueryLog.with_filter(filter_fn(|metadata| {
+            metadata.target() == QUERY_TARGET && log::log_enabled!(target: QUERY_TARGET, log_level(metadata.level()))
+        })))
+        .try_init()?;
+
+    Ok(TelemetryGuard { provider })
+}
+
+// OTEL_EXPORTER_OTLP_ENDPOINT is the collector base URL; the signal path is appended
+fn otlp_traces_url(endpoint: &str) -> String {
+    let endpoint = endpoint.trim_end_matches('/');
+    if endpoint.ends_with("/v1/traces") {
+        endpoint.to_string()
+    } else {
+        format!("{endpoint}/v1/traces")
+    }
+}
+
+// Spans captured by the `memory` exporter, or None when another exporter is configured
+pub fn finished_spans() -> Option<Vec<SpanData>> {
+    let exporter = IN_MEMORY_EXPORTER.get()?;
+    Some(exporter.get_finished_spans().unwrap_or_default())
+}
+
+pub fn reset_finished_spans() {
+    if let Some(exporter) = IN_MEMORY_EXPORTER.get() {
+        exporter.reset();
+    }
+}
+
+// Parent context from the W3C `traceparent`/`tracestate` request headers
+pub fn extract_context(headers: &actix_web::http::header::HeaderMap) -> Context {
+    global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(headers)))
+}
+
+// `traceparent`/`tracestate` for outgoing requests made on behalf of the current span
+pub fn propagation_headers() -> Vec<(String, String)> {
+    let mut headers = HeaderInjector(Vec::new());
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&Context::current(), &mut headers)
+    });
+    headers.0
+}
+
+// Trace and span id of the active span, for correlating log lines with traces
+pub fn current_trace_ids() -> Option<(String, String)> {
+    let context = Context::current();
+    let span = context.span();
+    let span_context = span.span_context();
+    span_context.is_valid().then(|| {
+        (
+            span_context.trace_id().to_string(),
+            span_context.span_id().to_string(),
+        )
+    })
+}
+
+struct HeaderExtractor<'a>(&'a actix_web::http::header::HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|name| name.as_str()).collect()
+    }
+}
+
+struct HeaderInjector(Vec<(String, String)>);
+
+impl Injector for HeaderInjector {
+    fn set(&mut self, key: &str, value: String) {
+        self.0.push((key.to_string(), value));
+    }
+}
+
+// SeaORM has no query hooks, but sqlx reports every statement with its duration once it
+// finishes (`sqlx::query` events). Each one becomes a client span under the active span,
+// back-dated by the reported duration.
+struct QuerySpans {
+    tracer: SdkTracer,
+}
+
+impl<S: Subscriber> Layer<S> for QuerySpans {
+    fn on_event(&self, event: &Event<'_>, _ctx: layer::Context<'_, S>) {
+        let parent = Context::current();
+        if !parent.has_active_span() {
+            return;
+        }
+
+        let mut query = QueryFields::default();
+        event.record(&mut query);
+        let Some(elapsed_secs) = query.elapsed_secs else {
+            return;
+        };
+
+        let end = SystemTime::now();
+        let start = end
+            .checked_sub(Duration::from_secs_f64(elapsed_secs))
+            .unwrap_or(end);
+        let statement = query.statement().to_string();
+        let operation = query
+            .summary
+            .split_whitespace()
+            .next()
+            .unwrap_or("QUERY")
+            .to_uppercase();
+
+        let mut span = self
+            .tracer
+            .span_builder(operation.clone())
+            .with_kind(SpanKind::Client)
+            .with_start_time(start)
+            .with_attributes([
+                KeyValue::new("db.system.name", "postgresql"),
+                KeyValue::new("db.operation.name", operation),
+                KeyValue::new("db.query.text", statement),
+                KeyValue::new("db.response.returned_rows", query.rows_returned as i64),
+                KeyValue::new("db.response.affected_rows", query.rows_affected as i64),
+            ])
+            .start_with_context(&self.tracer, &parent);
+        span.end_with_timestamp(end);
+    }
+}
+
+// Statements still reach the JSON log with RUST_LOG=debug now that `tracing` has a subscriber
+struct QueryLog;
+
+impl<S: Subscriber> Layer<S> for QueryLog {
+    fn on_event(&self, event: &Event<'_>, _ctx: layer::Context<'_, S>) {
+        let mut query = QueryFields::default();
+        event.record(&mut query);
+        log::log!(
+            target: QUERY_TARGET,
+            log_level(event.metadata().level()),
+            elapsed_ms = query.elapsed_secs.unwrap_or_default() * 1000.0,
+            rows_returned = query.rows_returned,
+            rows_affected = query.rows_affected;
+            "{}",
+            query.statement()
+        );
+    }
+}
+
+fn log_level(level: &Level) -> log::Level {
+    match *level {
+        Level::ERROR => log::Level::Error,
+        Level::WARN => log::Level::Warn,
+        Level::INFO => log::Level::Info,
+        Level::DEBUG => log::Level::Debug,
+        Level::TRACE => log::Level::Trace,
+    }
+}
+
+#[derive(Default)]
+struct QueryFields {
+    summary: String,
+    statement: String,
+    rows_returned: u64,
+    rows_affected: u64,
+    elapsed_secs: Option<f64>,
+}
+
+impl QueryFields {
+    // sqlx leaves `db.statement` empty when the summary already is the whole statement
+    fn statement(&self) -> &str {
+        match self.statement.trim() {
+            "" => &self.summary,
+            statement => statement,
+        }
+    }
+}
+
+impl Visit for QueryFields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "summary" => self.summary = value.to_string(),
+            "db.statement" => self.statement = value.to_string(),
+            _ => {}
+        }
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        match field.name() {
+            "rows_returned" => self.rows_returned = value,
+            "rows_affected" => self.rows_affected = value,
+            _ => {}
+        }
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        if field.name() == "elapsed_secs" {
+            self.elapsed_secs = Some(value);
+        }
+    }
+
+    fn record_debug(&mut self, _field: &Field, _value: &dyn std::fmt::Debug) {}
+
+}
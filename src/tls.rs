@@ -0,0 +1,162 @@
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+use rustls::crypto::{ring, CryptoProvider};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls_pki_types::pem::PemObject;
+use rustls_pki_types::{CertificateDer, PrivateKeyDer};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+
+use crate::config::TlsConfig;
+
+// Serves the certificate currently on disk. `reload_if_changed` swaps in a renewed certificate
+// for new handshakes; open connections keep the one they negotiated.
+pub struct CertificateStore {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    provider: Arc<CryptoProvider>,
+    current: RwLock<Arc<CertifiedKey>>,
+    modified: Mutex<(Option<SystemTime>, Option<SystemTime>)>,
+}
+
+impl CertificateStore {
+    pub fn load(config: &TlsConfig) -> anyhow::Result<Arc<Self>> {
+        let (Some(cert_path), Some(key_path)) = (&config.cert_path, &config.key_path) else {
+            anyhow::bail!("tls.cert_path and tls.key_path must both be set");
+        };
+        let provider = Arc::new(ring::default_provider());
+        let modified = (modified_at(cert_path), modified_at(key_path));
+        let current = load_certified_key(cert_path, key_path, &provider)?;
+
+        Ok(Arc::new(Self {
+            cert_path: cert_path.clone(),
+            key_path: key_path.clone(),
+            provider,
+            current: RwLock::new(Arc::new(current)),
+            modified: Mutex::new(modified),
+        }))
+    }
+
+    pub fn server_config(self: &Arc<Self>) -> anyhow::Result<rustls::ServerConfig> {
+        Ok(rustls::ServerConfig::builder_with_provider(self.provider.clone())
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_cert_resolver(self.clone()))
+    }
+
+    // A renewal that fails to parse (e.g. key written before the certificate) keeps the old
+    // certificate and is retried on the next check
+    pub fn reload_if_changed(&self) {
+        let modified = (modified_at(&self.cert_path), modified_at(&self.key_path));
+        let mut last = self.modified.lock().unwrap();
+        if *last == modified {
+            return;
+        }
+
+        match load_certified_key(&self.cert_path, &self.key_path, &self.provider) {
+            Ok(key) => {
+                *self.current.write().unwrap() = Arc::new(key);
+                *last = modified;
+                log::info!(cert_path:% = self.cert_path.display(); "TLS certificate reloaded");
+            }
+            Err(e) => {
+                log::warn!(error:% = e, cert_path:% = self.cert_path.display(); "TLS certificate reload error");
+            }
+        }
+    }
+
+    // Checks the files every `interval` for as long as the server runs
+    pub fn watch(self: Arc<Self>, interval: Duration) {
+        actix_web::rt::spawn(async move {
+            let mut ticker = actix_web::rt::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                self.reload_if_changed();
+            }
+        });
+    }
+}
+
+impl ResolvesServerCert for CertificateStore {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().unwrap().clone())
+    }
+}
+
+impl fmt::Debug for CertificateStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CertificateStore")
+            .field("cert_path", &self.cert_path)
+            .field("key_path", &self.key_path)
+            .finish_non_exhaustive()
+    }
+}
+
+// `Strict-Transport-Security` value, or None when HSTS is disabled
+pub fn hsts_header(config: &TlsConfig) -> Option<String> {
+    if config.hsts_max_age_secs == 0 {
+        return None;
+    }
+    let mut value = format!("max-age={}", config.hsts_max_age_secs);
+    if config.hsts_include_subdomains {
+        value.push_str("; includeSubDomains");
+    }
+    Some(value)
+}
+
+// Chain (leaf first) and private key in PEM; the key must match the leaf certificate
+fn load_certified_key(cert_path: &Path, key_path: &Path, provider: &CryptoProvider) -> anyhow::Result<CertifiedKey> {
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| anyhow::anyhow!("Failed to read certificate {}: {e}", cert_path.display()))?;
+    if certs.is_empty() {
+        anyhow::bail!("No certificate found in {}", cert_path.display());
+    }
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .map_err(|e| anyhow::anyhow!("Failed to read private key {}: {e}", key_path.display()))?;
+
+    CertifiedKey::from_der(certs, key, provider)
+        .map_err(|e| anyhow::anyhow!("Invalid certificate/key pair {}: {e}", cert_path.display()))
+}
+
+fn modified_at(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+// Port the HTTPS listener is reachable on, for the HTTP → HTTPS redirect listener
+#[derive(Clone, Copy)]
+pub struct HttpsPort(pub u16);
+
+// Permanent redirect to the same host and path over HTTPS; 308 keeps the method and body
+pub async fn redirect_to_https(req: HttpRequest, https_port: web::Data<HttpsPort>) -> HttpResponse {
+    let host = req
+        .headers()
+        .get(header::HOST)
+        .and_then(|value| value.to_str().ok())
+        .or_else(|| req.uri().host());
+    let Some(host) = host.map(strip_port).filter(|host| !host.is_empty()) else {
+        return HttpResponse::BadRequest().body("Missing Host header");
+    };
+
+    let authority = match https_port.0 {
+        443 => host.to_string(),
+        port => format!("{host}:{port}"),
+    };
+    let path = req.uri().path_and_query().map(|path| path.as_str()).unwrap_or("/");
+
+    HttpResponse::PermanentRedirect()
+        .insert_header((header::LOCATION, format!("https://{authority}{path}")))
+        .finish()
+}
+
+// `example.com:80` → `example.com`, `[::1]:80` → `[::1]`
+fn strip_port(host: &str) -> &str {
+    match host.rsplit_once(':') {
+        Some((name, port)) if !name.is_empty() && !port.contains(']') && port.bytes().all(|b| b.is_ascii_digit()) => name,
+        _ => host,
+    }
+}